@@ -159,6 +159,59 @@ pub trait ParseCallbacks: fmt::Debug {
     fn wrap_as_variadic_fn(&self, _name: &str) -> Option<String> {
         None
     }
+
+    /// Whether to leave the named function out of the sandbox in sandboxed
+    /// bindings. It is then bound as a regular `extern` function, which runs
+    /// with the privileges of the caller rather than as a method on the
+    /// sandbox type. Functions of dynamically loaded libraries are always
+    /// sandboxed.
+    fn skip_sandboxing(&self, _function: &str) -> bool {
+        false
+    }
+
+    /// How the sandboxed binding of the named function reports a failed
+    /// check, overriding [`SandboxConfig::fallible`](crate::SandboxConfig::fallible).
+    fn sandbox_error_style(
+        &self,
+        _function: &str,
+    ) -> Option<SandboxErrorStyle> {
+        None
+    }
+
+    /// Whether the pointer returned by the named sandboxed function may be
+    /// null, overriding the configuration, annotations and declaration of the
+    /// function.
+    fn sandbox_return_nullable(&self, _function: &str) -> Option<bool> {
+        None
+    }
+
+    /// Whether a pointer argument of a sandboxed function may be null, in
+    /// which case it is taken as an `Option`. Pointer arguments are otherwise
+    /// never null.
+    fn sandbox_argument_nullable(
+        &self,
+        _info: SandboxArgumentInfo<'_>,
+    ) -> Option<bool> {
+        None
+    }
+
+    /// How a pointer argument of a sandboxed function is passed into the
+    /// sandbox.
+    fn sandbox_argument_kind(
+        &self,
+        _info: SandboxArgumentInfo<'_>,
+    ) -> Option<SandboxArgumentKind> {
+        None
+    }
+
+    /// Who owns the copy of an argument passed with
+    /// [`SandboxArgumentKind::CopyIn`] once the function returns.
+    fn sandbox_argument_ownership(
+        &self,
+        _info: SandboxArgumentInfo<'_>,
+    ) -> Option<SandboxOwnership> {
+        None
+    }
 }
 
 /// Relevant information about a type to which new derive attributes will be added using
@@ -211,3 +264,57 @@ pub struct FieldInfo<'a> {
     /// The name of the field.
     pub field_name: &'a str,
 }
+
+/// Relevant information about an argument of a sandboxed function, passed to
+/// the sandbox hooks of [`ParseCallbacks`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct SandboxArgumentInfo<'a> {
+    /// The name of the method on the sandbox type calling the function.
+    pub function: &'a str,
+    /// The name of the argument, if it has one.
+    pub name: Option<&'a str>,
+    /// The position of the argument.
+    pub index: usize,
+}
+
+/// How a sandboxed binding reports a failed check of an argument or return
+/// value.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SandboxErrorStyle {
+    /// Panic.
+    Panic,
+    /// Return a `Result` with the runtime's `SandboxError`.
+    Result,
+}
+
+/// How a sandboxed binding passes a pointer argument into the sandbox.
+///
+/// Copying in and out needs an allocator, see
+/// [`SandboxConfig::allocator`](crate::SandboxConfig::allocator), and isn't
+/// available for dynamically loaded libraries or opaque records.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SandboxArgumentKind {
+    /// Take a sandbox pointer or handle, which must already point into the
+    /// sandbox. This is the default.
+    Pointer,
+    /// Take a reference, or a slice if the argument is counted, to memory of
+    /// the caller and pass a copy of it in sandbox memory. The pointee must be
+    /// `SandboxSafe`, or the binding can't be called.
+    CopyIn,
+    /// Leave the argument out and pass a pointer to zeroed sandbox memory
+    /// instead, returning the value the function leaves there along with its
+    /// return value. The pointee must be `SandboxSafe`, as the function may
+    /// leave any bytes there.
+    Out,
+}
+
+/// Who owns the copy of an argument passed with
+/// [`SandboxArgumentKind::CopyIn`] once the function returns.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SandboxOwnership {
+    /// The binding frees the copy after the call. This is the default.
+    Borrowed,
+    /// The function takes ownership of the copy, and frees it itself.
+    Transferred,
+}
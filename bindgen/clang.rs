@@ -47,6 +47,13 @@ impl Attribute {
         kind: None,
         token_kind: CXToken_Identifier,
     };
+
+    /// A `returns_nonnull` attribute.
+    pub(crate) const RETURNS_NONNULL: Self = Self {
+        name: b"returns_nonnull",
+        kind: None,
+        token_kind: CXToken_Identifier,
+    };
 }
 
 /// A cursor into the Clang AST, pointing to an AST node.
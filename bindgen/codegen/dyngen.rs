@@ -4,6 +4,9 @@ use crate::ir::function::ClangAbi;
 use proc_macro2::Ident;
 
 /// Used to build the output tokens for dynamic bindings.
+///
+/// With sandboxed bindings, the library is instead loaded into a sandbox by the runtime, and its
+/// functions are only reachable through methods calling them inside the sandbox.
 #[derive(Default)]
 pub(crate) struct DynamicItems {
     /// Tracks the tokens that will appears inside the library struct -- e.g.:
@@ -83,6 +86,44 @@ impl DynamicItems {
         let init_fields = &self.init_fields;
         let struct_implementation = &self.struct_implementation;
 
+        if let Some(ref sandbox) = ctx.options().sandbox {
+            let runtime = sandbox.runtime_path();
+            let load_library = if ctx.options().wrap_unsafe_ops {
+                quote!(unsafe { __sandbox.load_library(path, imports)? })
+            } else {
+                quote!(__sandbox.load_library(path, imports)?)
+            };
+
+            return quote! {
+                pub struct #lib_ident {
+                    __sandbox: #runtime::Sandbox,
+                    __library: #runtime::Library,
+                    #(#struct_members)*
+                }
+
+                impl #lib_ident {
+                    /// Loads the library into a new sandbox, resolving its
+                    /// imports against `imports`.
+                    pub unsafe fn new<P>(
+                        path: P,
+                        imports: &#runtime::Imports,
+                    ) -> Result<Self, ::std::io::Error>
+                    where P: AsRef<::std::path::Path> {
+                        let mut __sandbox = #runtime::Sandbox::new();
+                        let __library = #load_library;
+                        #( #constructor_inits )*
+                        Ok(#lib_ident {
+                            __sandbox,
+                            __library,
+                            #( #init_fields ),*
+                        })
+                    }
+
+                    #( #struct_implementation )*
+                }
+            };
+        }
+
         let from_library = if ctx.options().wrap_unsafe_ops {
             quote!(unsafe { Self::from_library(library) })
         } else {
@@ -198,4 +239,100 @@ impl DynamicItems {
             #ident
         });
     }
+
+    /// Like [`DynamicItems::push`], but for sandboxed bindings: `call_expr` calls the function
+    /// pointer `f` inside `self.__sandbox`, with the transformed arguments.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn push_sandboxed(
+        &mut self,
+        ident: Ident,
+        abi: ClangAbi,
+        is_required: bool,
+        args: Vec<proc_macro2::TokenStream>,
+        ret: proc_macro2::TokenStream,
+        transformed_args: Vec<proc_macro2::TokenStream>,
+        transformed_ret: proc_macro2::TokenStream,
+        bounds: proc_macro2::TokenStream,
+        call_expr: proc_macro2::TokenStream,
+        attributes: Vec<proc_macro2::TokenStream>,
+        ctx: &BindgenContext,
+    ) {
+        let signature = quote! { unsafe extern #abi fn ( #( #args),* ) #ret };
+        let member = if is_required {
+            signature.clone()
+        } else {
+            quote! { Option<#signature> }
+        };
+
+        // The function pointers are private: calling them directly would run
+        // library code outside of the sandbox.
+        self.struct_members.push(quote! {
+            #ident: #member,
+        });
+
+        // N.B: If the signature was required, it won't be wrapped in an
+        //      Option<...> and we can simply call it directly.
+        let sandbox = ctx.options().sandbox.as_ref().unwrap();
+        let fn_ = if is_required {
+            quote! { self.#ident }
+        } else if codegen::utils::sandbox_is_fallible(ctx, &ident.to_string())
+        {
+            let error = sandbox.error_type_path();
+            let error_kind = sandbox.error_kind_type_path();
+            let ident_str = ident.to_string();
+            quote! {
+                self.#ident.ok_or(#error::new(#ident_str, #error_kind::MissingSymbol))?
+            }
+        } else {
+            quote! { self.#ident.expect("Function not exported by library.") }
+        };
+
+        // `call_expr` calls `f` inside `self.__sandbox`.
+        self.struct_implementation.push(quote! {
+            #(#attributes)*
+            pub fn #ident (
+                &mut self,
+                #( #transformed_args ),*
+            ) #transformed_ret #bounds {
+                let f = #fn_;
+                unsafe { #call_expr }
+            }
+        });
+
+        let ident_str = ident.to_string();
+        let library_get = quote! {
+            __library.get(#ident_str).map(|sym| {
+                ::std::mem::transmute::<
+                    *const ::std::ffi::c_void,
+                    #signature,
+                >(sym)
+            })
+        };
+        let library_get = if ctx.options().wrap_unsafe_ops {
+            quote!(unsafe { #library_get })
+        } else {
+            library_get
+        };
+
+        // N.B: Unwrap the signature upon construction if it is required to be
+        //      resolved.
+        self.constructor_inits.push(if is_required {
+            quote! {
+                let #ident = #library_get.ok_or_else(|| {
+                    ::std::io::Error::new(
+                        ::std::io::ErrorKind::NotFound,
+                        #ident_str,
+                    )
+                })?;
+            }
+        } else {
+            quote! {
+                let #ident = #library_get;
+            }
+        });
+
+        self.init_fields.push(quote! {
+            #ident
+        });
+    }
 }
@@ -13,6 +13,8 @@ pub(crate) mod struct_layout;
 pub(crate) mod bitfield_unit;
 #[cfg(all(test, target_endian = "little"))]
 mod bitfield_unit_tests;
+#[cfg(test)]
+mod sandbox_tests;
 
 use self::dyngen::DynamicItems;
 use self::helpers::attributes;
@@ -20,7 +22,9 @@ use self::struct_layout::StructLayoutTracker;
 
 use super::BindgenOptions;
 
-use crate::callbacks::{DeriveInfo, FieldInfo, TypeKind as DeriveTypeKind};
+use crate::callbacks::{
+    DeriveInfo, FieldInfo, SandboxArgumentKind, TypeKind as DeriveTypeKind,
+};
 use crate::codegen::error::Error;
 use crate::ir::analysis::{HasVtable, Sizedness};
 use crate::ir::annotations::{
@@ -51,6 +55,7 @@ use crate::ir::template::{
 };
 use crate::ir::ty::{Type, TypeKind};
 use crate::ir::var::Var;
+use crate::sandbox::SandboxConfig;
 
 use proc_macro2::{self, Ident, Span};
 use quote::TokenStreamExt;
@@ -228,6 +233,18 @@ struct WrapAsVariadic {
     idx_of_va_list_arg: usize,
 }
 
+/// A C++ shim that calls a sandboxed function and catches its exceptions,
+/// serialized along with the wrappers of `wrap_static_fns`.
+struct SandboxShim {
+    /// The function whose signature the shim has.
+    function: ItemId,
+    /// The name of the shim.
+    name: String,
+    /// The symbol the shim calls, or `None` if it calls a function pointer
+    /// passed as its first argument.
+    target: Option<String>,
+}
+
 struct CodegenResult<'a> {
     items: Vec<proc_macro2::TokenStream>,
     dynamic_items: DynamicItems,
@@ -279,6 +296,9 @@ struct CodegenResult<'a> {
     /// List of items to serialize. With optionally the argument for the wrap as
     /// variadic transformation to be applied.
     items_to_serialize: Vec<(ItemId, Option<WrapAsVariadic>)>,
+
+    /// Exception-catching shims of sandboxed functions to serialize.
+    sandbox_shims: Vec<SandboxShim>,
 }
 
 impl<'a> CodegenResult<'a> {
@@ -297,6 +317,7 @@ impl<'a> CodegenResult<'a> {
             vars_seen: Default::default(),
             overload_counters: Default::default(),
             items_to_serialize: Default::default(),
+            sandbox_shims: Default::default(),
         }
     }
 
@@ -768,6 +789,8 @@ impl CodeGenerator for Var {
                 }
             }
         } else {
+            let doc_attrs = attrs.clone();
+
             // If necessary, apply a `#[link_name]` attribute
             if let Some(link_name) = self.link_name() {
                 attrs.push(attributes::link_name::<false>(link_name));
@@ -789,6 +812,69 @@ impl CodeGenerator for Var {
                 quote! { mut }
             };
 
+            if let (Some(sandbox), None) = (
+                ctx.options().sandbox.as_ref(),
+                ctx.options().dynamic_library_name.as_ref(),
+            ) {
+                // In sandboxed bindings, the variable is only reachable
+                // through accessors on the sandbox type, which borrow the
+                // sandbox like any other reference into it.
+                let prefix = ctx.trait_prefix();
+                let runtime = sandbox.runtime_path();
+                let sandbox_type = sandbox.sandbox_type_ident();
+                let ptr_ident =
+                    ctx.rust_ident(format!("{}_ptr", canonical_name));
+                let (ptr_ty, addr_of) = if self.is_const() {
+                    (sandbox.ptr_type_path(), quote! { addr_of })
+                } else {
+                    (sandbox.ptr_mut_type_path(), quote! { addr_of_mut })
+                };
+                let as_mut = if self.is_const() {
+                    None
+                } else {
+                    let mut_ident =
+                        ctx.rust_ident(format!("{}_mut", canonical_name));
+                    Some(quote! {
+                        #(#doc_attrs)*
+                        pub fn #mut_ident(&mut self) -> &mut #ty
+                        where
+                            for<'a> #ty: #runtime::SandboxSafe,
+                        {
+                            self.#ptr_ident().as_mut(&mut self.__sandbox)
+                        }
+                    })
+                };
+
+                result.push(quote! {
+                    impl #sandbox_type {
+                        #(#doc_attrs)*
+                        pub fn #ptr_ident(&self) -> #ptr_ty<#ty> {
+                            extern "C" {
+                                #(#attrs)*
+                                static #maybe_mut #canonical_ident: #ty;
+                            }
+
+                            #[allow(unused_unsafe)]
+                            let ptr = unsafe {
+                                ::#prefix::ptr::#addr_of!(#canonical_ident)
+                            };
+                            #ptr_ty::new(ptr)
+                        }
+
+                        #(#doc_attrs)*
+                        pub fn #canonical_ident(&self) -> &#ty
+                        where
+                            for<'a> #ty: #runtime::SandboxSafe,
+                        {
+                            self.#ptr_ident().as_ref(&self.__sandbox)
+                        }
+
+                        #as_mut
+                    }
+                });
+                return;
+            }
+
             let tokens = quote!(
                 extern "C" {
                     #(#attrs)*
@@ -1144,6 +1230,7 @@ impl<'a> CodeGenerator for Vtable<'a> {
             self.comp_info.destructor().is_none()
         {
             let class_ident = ctx.rust_ident(self.item_id.canonical_name(ctx));
+            let mut sandboxed_calls = vec![];
 
             let methods = self
                 .comp_info
@@ -1176,6 +1263,18 @@ impl<'a> CodeGenerator for Vtable<'a> {
                         quote! { this: *mut #class_ident }
                     };
 
+                    if let Some(ref sandbox) = ctx.options().sandbox {
+                        sandboxed_calls.push(sandbox_virtual_call_tokens(
+                            ctx,
+                            result,
+                            sandbox,
+                            &name,
+                            function_item,
+                            signature,
+                            &function_name,
+                        ));
+                    }
+
                     Some(quote! {
                         pub #function_name : unsafe extern "C" fn( #( #args ),* ) #ret
                     })
@@ -1187,6 +1286,8 @@ impl<'a> CodeGenerator for Vtable<'a> {
                 pub struct #name {
                     #( #methods ),*
                 }
+
+                #( #sandboxed_calls )*
             })
         } else {
             // For the cases we don't support, simply generate an empty struct.
@@ -2410,6 +2511,17 @@ impl CodeGenerator for CompInfo {
                 }
             }
 
+            if let Some(ref sandbox) = ctx.options().sandbox {
+                if !is_opaque && !self.is_forward_declaration() {
+                    result.push(sandbox_field_tokens(
+                        ctx,
+                        sandbox,
+                        self,
+                        &canonical_name,
+                    ));
+                }
+            }
+
             let mut method_names = Default::default();
             if ctx.options().codegen_config.methods() {
                 for method in self.methods() {
@@ -2635,6 +2747,27 @@ impl Method {
             write!(&mut function_name, "{}", times_seen).unwrap();
         }
         let function_name = ctx.rust_ident(function_name);
+
+        if ctx.options().sandbox.is_some() {
+            // Sandboxed bindings call methods through the thunks that
+            // `Function::codegen` put on the sandbox type above, taking `this`
+            // as a sandbox pointer. Constructors and destructors may also get
+            // methods managing the object's memory in the sandbox.
+            if ctx.options().dynamic_library_name.is_none() {
+                if let Some(tokens) = sandbox_lifecycle_tokens(
+                    ctx,
+                    self,
+                    function_item,
+                    signature,
+                    &name,
+                    &function_name,
+                ) {
+                    result.push(tokens);
+                }
+            }
+            return;
+        }
+
         let mut args = utils::fnsig_arguments(ctx, signature);
         let mut ret = utils::fnsig_return_ty(ctx, signature);
 
@@ -3872,6 +4005,16 @@ impl TryToRustTy for Type {
                 // variant here.
                 let ty = signature.try_to_rust_ty(ctx, item)?;
 
+                // Sandboxed bindings only let function pointers into the
+                // sandbox's code be called, and only inside the sandbox.
+                let ty = match ctx.options().sandbox {
+                    Some(ref sandbox) => {
+                        let fn_ptr = sandbox.fn_ptr_type_path();
+                        syn::parse_quote! { #fn_ptr<#ty> }
+                    }
+                    None => ty,
+                };
+
                 let prefix = ctx.trait_prefix();
                 Ok(syn::parse_quote! { ::#prefix::option::Option<#ty> })
             }
@@ -4252,12 +4395,30 @@ impl CodeGenerator for Function {
             attributes.push(attributes::link_name::<true>(&name));
         }
 
-        if signature.is_variadic() {
-            // variadic arguments are unsupported
+        // Functions of dynamically loaded libraries can't be called outside
+        // of the sandbox, as the loaded library only exposes them through
+        // sandboxed methods.
+        let sandbox = ctx.options().sandbox.as_ref().filter(|_| {
+            is_dynamic_function ||
+                !utils::skips_sandboxing(ctx, &canonical_name)
+        });
+        if sandbox.is_some() && signature.is_variadic() {
+            // Variadic arguments can't be passed into the sandbox; fixed-arity
+            // shims (see `sandbox_build::Build::variadic`) are bound instead.
+            sandbox_variadic_fn_diagnostic(
+                &canonical_name,
+                item.location(),
+                ctx,
+            );
             return None;
         }
 
-        let wrap_as_variadic = if should_wrap && !signature.is_variadic() {
+        // Sandboxed bindings don't support variadic functions, including
+        // wrapped ones.
+        let wrap_as_variadic = if should_wrap &&
+            !signature.is_variadic() &&
+            sandbox.is_none()
+        {
             utils::wrap_as_variadic_fn(ctx, signature, name)
         } else {
             None
@@ -4289,45 +4450,9 @@ impl CodeGenerator for Function {
         } else {
             (&canonical_name, utils::fnsig_arguments(ctx, signature))
         };
-        let transformed_args = utils::fnsig_transformed_arguments(ctx, signature);
         let ret = utils::fnsig_return_ty(ctx, signature);
-        let transformed_ret = utils::fnsig_transformed_return_ty(ctx, signature);
-
-        let transformed_arg_identifiers = utils::fnsig_transformed_argument_identifiers(ctx, signature);
 
         let ident = ctx.rust_ident(ident);
-        let call_expr = match utils::fnsig_return_ty_internal(ctx, signature) {
-            syn::Type::Ptr(t) => {
-                if t.const_token.is_some() {
-                    quote! {
-                        mpk::SandboxPtr::new(self.0.call(move || #ident ( #( #transformed_arg_identifiers ),* )))
-                    }
-                } else {
-                    quote! {
-                        mpk::SandboxPtrMut::new(self.0.call(move || #ident ( #( #transformed_arg_identifiers ),* )))
-                    }
-                }
-            },
-            _ => {
-                quote! {
-                    self.0.call(move || #ident ( #( #transformed_arg_identifiers ),* ))
-                }
-            }
-        };
-
-        let tokens = quote! {
-            impl Sandboxed {
-                pub fn #ident (&mut self, #( #transformed_args ),* ) #transformed_ret {
-                    #wasm_link_attribute
-                    extern #abi {
-                        #(#attributes)*
-                        fn #ident ( #( #args ),* ) #ret;
-                    }
-
-                    unsafe { #call_expr }
-                }
-            }
-        };
 
         // Add the item to the serialization list if necessary
         if should_wrap {
@@ -4336,8 +4461,113 @@ impl CodeGenerator for Function {
                 .push((item.id(), wrap_as_variadic));
         }
 
-        // If we're doing dynamic binding generation, add to the dynamic items.
-        if is_dynamic_function {
+        if let Some(sandbox) = sandbox {
+            // Exceptions can only be caught by a shim compiled into the
+            // sandboxed library, which dynamically loaded ones don't have.
+            let shim = (sandbox.catches_exceptions() && !is_dynamic_function)
+                .then(|| format!("{}__sandbox_catch", canonical_name));
+            let callee = match shim {
+                _ if is_dynamic_function => SandboxCallee::Direct(quote! { f }),
+                Some(ref shim) => {
+                    let shim = ctx.rust_ident_raw(shim);
+                    SandboxCallee::Shim(quote! { #shim })
+                }
+                None => SandboxCallee::Direct(quote! { #ident }),
+            };
+            let SandboxedCall {
+                args: transformed_args,
+                ret: transformed_ret,
+                bounds,
+                call: call_expr,
+            } = sandboxed_call(
+                ctx,
+                sandbox,
+                signature,
+                &canonical_name,
+                item.annotations(),
+                callee.clone(),
+                false,
+            );
+
+            // If we're doing dynamic binding generation, add to the dynamic items.
+            if is_dynamic_function {
+                result.dynamic_items().push_sandboxed(
+                    ident,
+                    abi,
+                    ctx.options().dynamic_link_require_all,
+                    args,
+                    ret,
+                    transformed_args,
+                    transformed_ret,
+                    bounds,
+                    call_expr,
+                    attributes,
+                    ctx,
+                );
+            } else {
+                let declaration = match shim {
+                    Some(shim) => {
+                        let target = if should_wrap {
+                            canonical_name.clone() +
+                                ctx.wrap_static_fns_suffix()
+                        } else {
+                            self.link_name()
+                                .or(mangled_name)
+                                .unwrap_or(name)
+                                .to_owned()
+                        };
+                        let shim_ident = ctx.rust_ident_raw(&shim);
+                        result.sandbox_shims.push(SandboxShim {
+                            function: item.id(),
+                            name: shim,
+                            target: Some(target),
+                        });
+                        quote! {
+                            extern "C" {
+                                fn #shim_ident ( #( #args ),* ) #ret;
+                            }
+                        }
+                    }
+                    None => quote! {
+                        #wasm_link_attribute
+                        extern #abi {
+                            #(#attributes)*
+                            fn #ident ( #( #args ),* ) #ret;
+                        }
+                    },
+                };
+                let batch_call = sandboxed_call(
+                    ctx,
+                    sandbox,
+                    signature,
+                    &canonical_name,
+                    item.annotations(),
+                    callee,
+                    true,
+                )
+                .call;
+                let sandbox_type = sandbox.sandbox_type_ident();
+                let batch_type = sandbox.batch_type_ident();
+                result.push(quote! {
+                    impl #sandbox_type {
+                        pub fn #ident (&mut self, #( #transformed_args ),* ) #transformed_ret #bounds {
+                            #declaration
+
+                            unsafe { #call_expr }
+                        }
+                    }
+
+                    impl #batch_type<'_> {
+                        pub fn #ident (&mut self, #( #transformed_args ),* ) #transformed_ret #bounds {
+                            #declaration
+
+                            unsafe { #batch_call }
+                        }
+                    }
+                });
+            }
+        } else if is_dynamic_function {
+            // If we're doing dynamic binding generation, add to the dynamic items.
             let args_identifiers =
                 utils::fnsig_argument_identifiers(ctx, signature);
             let ret_ty = utils::fnsig_return_ty(ctx, signature);
@@ -4354,7 +4584,13 @@ impl CodeGenerator for Function {
                 ctx,
             );
         } else {
-            result.push(tokens);
+            result.push(quote! {
+                #wasm_link_attribute
+                extern #abi {
+                    #(#attributes)*
+                    pub fn #ident ( #( #args ),* ) #ret;
+                }
+            });
         }
         Some(times_seen)
     }
@@ -4457,6 +4693,44 @@ fn variadic_fn_diagnostic(
     }
 }
 
+fn sandbox_variadic_fn_diagnostic(
+    fn_name: &str,
+    _location: Option<&crate::clang::SourceLocation>,
+    _ctx: &BindgenContext,
+) {
+    warn!(
+        "Skipping variadic function `{}` in sandboxed bindings.",
+        fn_name,
+    );
+
+    #[cfg(feature = "experimental")]
+    if _ctx.options().emit_diagnostics {
+        use crate::diagnostics::{get_line, Diagnostic, Level, Slice};
+
+        let mut diag = Diagnostic::default();
+
+        diag.with_title(format!("Cannot generate a sandboxed binding for the variadic function `{}`.", fn_name), Level::Warn)
+            .add_annotation("Variadic arguments cannot be passed into the sandbox.", Level::Note)
+            .add_annotation("No code will be generated for this function; bind a fixed-arity instantiation of it instead.", Level::Help);
+
+        if let Some(loc) = _location {
+            let (file, line, col, _) = loc.location();
+
+            if let Some(filename) = file.name() {
+                if let Ok(Some(source)) = get_line(&filename, line) {
+                    let mut slice = Slice::default();
+                    slice
+                        .with_source(source)
+                        .with_location(filename, line, col);
+                    diag.add_slice(slice);
+                }
+            }
+        }
+
+        diag.display()
+    }
+}
+
 fn objc_method_codegen(
     ctx: &BindgenContext,
     method: &ObjCMethod,
@@ -4775,6 +5049,8 @@ pub(crate) fn codegen(
             let dynamic_items_tokens =
                 result.dynamic_items().get_tokens(lib_ident, context);
             result.push(dynamic_items_tokens);
+        } else if let Some(ref sandbox) = context.options().sandbox {
+            result.push(sandbox_type_tokens(sandbox));
         }
 
         utils::serialize_items(&result, context)?;
@@ -4786,80 +5062,810 @@ pub(crate) fn codegen(
     })
 }
 
-pub(crate) mod utils {
-    use super::serialize::CSerialize;
-    use super::{error, CodegenError, CodegenResult, ToRustTyOrOpaque};
-    use crate::ir::context::BindgenContext;
-    use crate::ir::context::TypeId;
-    use crate::ir::function::{Abi, ClangAbi, FunctionSig};
-    use crate::ir::item::{Item, ItemCanonicalPath};
-    use crate::ir::ty::TypeKind;
-    use crate::{args_are_cpp, file_is_cpp};
-    use std::borrow::Cow;
-    use std::io::Write;
-    use std::mem;
-    use std::path::PathBuf;
-    use std::str::FromStr;
-
-    pub(super) fn serialize_items(
-        result: &CodegenResult,
-        context: &BindgenContext,
-    ) -> Result<(), CodegenError> {
-        if result.items_to_serialize.is_empty() {
-            return Ok(());
-        }
-
-        let path = context
-            .options()
-            .wrap_static_fns_path
-            .as_ref()
-            .map(PathBuf::from)
-            .unwrap_or_else(|| {
-                std::env::temp_dir().join("bindgen").join("extern")
-            });
-
-        let dir = path.parent().unwrap();
-
-        if !dir.exists() {
-            std::fs::create_dir_all(dir)?;
-        }
+/// The parts of a method on the sandbox type that calls a sandboxed function.
+struct SandboxedCall {
+    args: Vec<proc_macro2::TokenStream>,
+    ret: proc_macro2::TokenStream,
+    /// The `where` clause of the method, which requires the values copied
+    /// into and out of the sandbox to be `SandboxSafe`.
+    bounds: proc_macro2::TokenStream,
+    /// Converts the arguments and calls the function, wrapping a returned
+    /// pointer. This must be placed in an `unsafe` block.
+    call: proc_macro2::TokenStream,
+}
 
-        let is_cpp = args_are_cpp(&context.options().clang_args) ||
-            context
-                .options()
-                .input_headers
-                .iter()
-                .any(|h| file_is_cpp(h));
+/// How a method on the sandbox type calls into the sandbox.
+#[derive(Clone)]
+enum SandboxCallee {
+    /// Calls the function directly.
+    Direct(proc_macro2::TokenStream),
+    /// Calls the C++ shim that calls the function and catches its exceptions.
+    Shim(proc_macro2::TokenStream),
+    /// Calls the C++ shim that calls the function pointer `target` and catches
+    /// its exceptions.
+    ShimPointer {
+        shim: proc_macro2::TokenStream,
+        target: proc_macro2::TokenStream,
+    },
+}
 
-        let source_path = path.with_extension(if is_cpp { "cpp" } else { "c" });
+/// Generates the parts of a method on the sandbox type that calls `callee`,
+/// a function named `name` with the given signature, inside the sandbox. In a
+/// `batch`, the method is on the batch type and calls `callee` directly, as it
+/// already runs inside the sandbox.
+fn sandboxed_call(
+    ctx: &BindgenContext,
+    sandbox: &SandboxConfig,
+    signature: &FunctionSig,
+    name: &str,
+    annotations: &Annotations,
+    callee: SandboxCallee,
+    batch: bool,
+) -> SandboxedCall {
+    let catch = !matches!(callee, SandboxCallee::Direct(_));
+    let fallible = utils::sandbox_is_fallible(ctx, name);
+    let counted = utils::sandbox_counted_arguments(ctx, signature, annotations);
+    let arguments =
+        utils::sandbox_arguments(ctx, signature, name, &counted);
+    let owned_string =
+        utils::sandbox_returns_owned_string(ctx, signature, name);
+    let return_count = if owned_string {
+        None
+    } else {
+        utils::fnsig_sandbox_return_count(ctx, signature, annotations)
+    };
+    let return_kind = if owned_string {
+        utils::SandboxReturn::OwnedString
+    } else if return_count.is_some() {
+        utils::SandboxReturn::Slice
+    } else {
+        utils::SandboxReturn::Pointer
+    };
+    let args = utils::fnsig_transformed_arguments(
+        ctx, signature, &counted, &arguments,
+    );
+    // Returned pointers may be null unless the parse callbacks, the
+    // configuration, an annotation or the declaration say otherwise.
+    let nullable = ctx
+        .options()
+        .last_callback(|cb| cb.sandbox_return_nullable(name))
+        .unwrap_or_else(|| {
+            !sandbox.is_nonnull_return(name) &&
+                !annotations
+                    .returns_nonnull()
+                    .unwrap_or_else(|| signature.returns_nonnull())
+        });
+    let outs = utils::sandbox_out_types(ctx, signature, &arguments);
+    let ret = utils::fnsig_transformed_return_ty(
+        ctx,
+        signature,
+        return_kind,
+        nullable,
+        catch,
+        fallible,
+        &outs,
+    );
+    let arg_identifiers = utils::fnsig_argument_identifiers(ctx, signature);
+    let arg_conversions = utils::fnsig_sandbox_argument_conversions(
+        ctx, signature, name, &counted, &arguments, batch,
+    );
+    let arg_cleanup = utils::fnsig_sandbox_argument_cleanup(
+        ctx, signature, &counted, &arguments, batch,
+    );
+    let ret_ty = utils::fnsig_return_ty_internal(ctx, signature);
+    let error = utils::sandbox_error_closure(ctx, name);
+    let bounds = utils::sandbox_safe_bounds(
+        &sandbox.runtime_path(),
+        &utils::sandbox_copied_types(ctx, signature, &arguments),
+    );
 
-        let mut code = Vec::new();
+    // Calls `callee` inside `self.__sandbox`, wrapping a returned pointer.
+    // Arguments are unwrapped beforehand, as handles need the sandbox to check
+    // that they belong to it. Fallible bindings use the `try_` variants of the
+    // wrappers and return their errors.
+    let call = match callee {
+        SandboxCallee::Direct(callee) | SandboxCallee::Shim(callee) => quote! {
+            #callee ( #( #arg_identifiers ),* )
+        },
+        SandboxCallee::ShimPointer { shim, target } => quote! {
+            #shim ( #target, #( #arg_identifiers ),* )
+        },
+    };
+    let call = utils::sandbox_enter(batch, call);
+    // A shim records the exception it caught, and returns a zeroed value,
+    // which must not be checked as a return value.
+    let call = if catch {
+        let runtime = sandbox.runtime_path();
+        let record = Ident::new(
+            &sandbox.exception_record_name(),
+            Span::call_site(),
+        );
+        let error = if fallible {
+            let error_ty = sandbox.error_type_path();
+            quote! { #error_ty::from_exception(#name, exception) }
+        } else {
+            quote! { exception }
+        };
+        quote! {{
+            extern "C" {
+                #[allow(non_upper_case_globals)]
+                static mut #record: #runtime::ExceptionRecord;
+            }
 
-        if !context.options().input_headers.is_empty() {
-            for header in &context.options().input_headers {
-                writeln!(code, "#include \"{}\"", header)?;
+            let ret = #call;
+            if let Some(exception) = #runtime::ExceptionRecord::take(
+                ::core::ptr::addr_of_mut!(#record),
+            ) {
+                return Err(#error);
             }
+            ret
+        }}
+    } else {
+        call
+    };
+    let is_handle = utils::is_opaque_pointer(ctx, signature.return_type());
+    let call = match ret_ty {
+        syn::Type::Ptr(_) if owned_string => {
+            let prefix = ctx.trait_prefix();
+            let string = sandbox.c_string_type_path();
+            let (_, dealloc) = sandbox.allocator_idents().unwrap();
+            let new = match (fallible, nullable) {
+                (true, true) => quote! {
+                    #string::try_new_nullable(#call as *mut _, #dealloc, &self.__sandbox)
+                        .map_err(#error)
+                },
+                (true, false) => quote! {
+                    #string::try_new(#call as *mut _, #dealloc, &self.__sandbox)
+                        .map_err(#error)
+                },
+                (false, true) => quote! {
+                    #string::new_nullable(#call as *mut _, #dealloc, &self.__sandbox)
+                },
+                (false, false) => quote! {
+                    #string::new(#call as *mut _, #dealloc, &self.__sandbox)
+                },
+            };
+            quote! {{
+                extern "C" {
+                    fn #dealloc(ptr: *mut ::#prefix::ffi::c_void);
+                }
 
-            writeln!(code)?;
+                #new
+            }}
         }
-
-        if !context.options().input_header_contents.is_empty() {
-            for (name, contents) in &context.options().input_header_contents {
-                writeln!(code, "// {}\n{}", name, contents)?;
+        syn::Type::Ptr(_) if is_handle => {
+            let handle = sandbox.handle_type_path();
+            match (fallible, nullable) {
+                (true, true) => quote! {
+                    Ok(#handle::new_nullable(#call, &self.__sandbox))
+                },
+                (true, false) => quote! {
+                    #handle::try_new(#call, &self.__sandbox).map_err(#error)
+                },
+                (false, true) => quote! {
+                    #handle::new_nullable(#call, &self.__sandbox)
+                },
+                (false, false) => quote! {
+                    #handle::new(#call, &self.__sandbox)
+                },
             }
-
-            writeln!(code)?;
         }
-
-        writeln!(code, "// Static wrappers\n")?;
-
-        for (id, wrap_as_variadic) in &result.items_to_serialize {
-            let item = context.resolve_item(*id);
-            item.serialize(context, wrap_as_variadic, &mut vec![], &mut code)?;
+        syn::Type::Ptr(ref t) => {
+            let ptr = if t.const_token.is_some() {
+                sandbox.ptr_type_path()
+            } else {
+                sandbox.ptr_mut_type_path()
+            };
+            match (fallible, nullable, &return_count) {
+                (true, true, Some(count)) => quote! {
+                    #ptr::try_new_nullable(#call)
+                        .and_then(|ptr| ptr.map(|ptr| ptr.try_as_slice(#count)).transpose())
+                        .map_err(#error)
+                },
+                (true, false, Some(count)) => quote! {
+                    #ptr::try_new(#call)
+                        .and_then(|ptr| ptr.try_as_slice(#count))
+                        .map_err(#error)
+                },
+                (true, true, None) => quote! {
+                    #ptr::try_new_nullable(#call).map_err(#error)
+                },
+                (true, false, None) => quote! {
+                    #ptr::try_new(#call).map_err(#error)
+                },
+                (false, true, Some(count)) => quote! {
+                    #ptr::new_nullable(#call).map(|ptr| ptr.as_slice(#count))
+                },
+                (false, false, Some(count)) => quote! {
+                    #ptr::new(#call).as_slice(#count)
+                },
+                (false, true, None) => quote! {
+                    #ptr::new_nullable(#call)
+                },
+                (false, false, None) => {
+                    quote! { #ptr::new(#call) }
+                }
+            }
         }
-
-        std::fs::write(source_path, code)?;
-
+        _ if fallible => quote! { Ok(#call) },
+        _ => call,
+    };
+    let call = if catch && !fallible {
+        quote! { Ok(#call) }
+    } else {
+        call
+    };
+    // The copies are freed and the out-parameters read even if the call
+    // fails, so a call returning early is made in a closure.
+    let call = if arg_cleanup.is_empty() && outs.is_empty() {
+        call
+    } else {
+        let returns_result = fallible || catch;
+        let is_unit = matches!(
+            ret_ty,
+            syn::Type::Tuple(syn::TypeTuple { ref elems, .. })
+                if elems.is_empty()
+        );
+        let outs = utils::fnsig_argument_identifiers(ctx, signature)
+            .into_iter()
+            .zip(&arguments)
+            .filter(|(_, arg)| arg.kind == SandboxArgumentKind::Out)
+            .map(|(ident, _)| ident)
+            .collect::<Vec<_>>();
+        let call = if returns_result {
+            quote! {
+                #[allow(clippy::redundant_closure_call)]
+                let __bindgen_ret = (|| #call)();
+            }
+        } else if is_unit {
+            quote! { #call; }
+        } else {
+            quote! { let __bindgen_ret = #call; }
+        };
+        let result = match (returns_result, is_unit) {
+            _ if outs.is_empty() => quote! { __bindgen_ret },
+            (true, true) => quote! {
+                __bindgen_ret.map(|()| ( #( #outs ),* ))
+            },
+            (true, false) => quote! {
+                __bindgen_ret.map(|__bindgen_ret| (__bindgen_ret, #( #outs ),* ))
+            },
+            (false, true) => quote! { ( #( #outs ),* ) },
+            (false, false) => quote! { (__bindgen_ret, #( #outs ),* ) },
+        };
+        quote! {{
+            #call
+            #( #arg_cleanup )*
+            #result
+        }}
+    };
+    let call = if arg_conversions.is_empty() {
+        call
+    } else {
+        quote! {{
+            #( #arg_conversions )*
+            #call
+        }}
+    };
+
+    SandboxedCall {
+        args,
+        ret,
+        bounds,
+        call,
+    }
+}
+
+/// Generates a method on the sandbox type that calls the virtual method
+/// `function_name` through the vtable `vtable` of the object it is passed.
+///
+/// Sandboxed code can overwrite the vtable pointer of an object it owns, so the
+/// vtable is only used if it lies in the sandbox's read-only data and all of
+/// its entries point into sandboxed code.
+fn sandbox_virtual_call_tokens(
+    ctx: &BindgenContext,
+    result: &mut CodegenResult<'_>,
+    sandbox: &SandboxConfig,
+    vtable: &Ident,
+    function_item: &Item,
+    signature: &FunctionSig,
+    function_name: &Ident,
+) -> proc_macro2::TokenStream {
+    let name = function_name.to_string();
+    let (callee, declaration) = if sandbox.catches_exceptions() {
+        let shim = format!("{}__sandbox_catch", name);
+        let shim_ident = ctx.rust_ident_raw(&shim);
+        result.sandbox_shims.push(SandboxShim {
+            function: function_item.id(),
+            name: shim,
+            target: None,
+        });
+        let prefix = ctx.trait_prefix();
+        let args = utils::fnsig_arguments(ctx, signature);
+        let ret = utils::fnsig_return_ty(ctx, signature);
+        let callee = SandboxCallee::ShimPointer {
+            shim: quote! { #shim_ident },
+            target: quote! {
+                vtable.#function_name as *const ::#prefix::ffi::c_void
+            },
+        };
+        let declaration = quote! {
+            extern "C" {
+                fn #shim_ident (
+                    target: *const ::#prefix::ffi::c_void,
+                    #( #args ),*
+                ) #ret;
+            }
+        };
+        (callee, declaration)
+    } else {
+        (SandboxCallee::Direct(quote! { (vtable.#function_name) }), quote! {})
+    };
+    let SandboxedCall {
+        args,
+        ret,
+        bounds,
+        call,
+    } = sandboxed_call(
+        ctx,
+        sandbox,
+        signature,
+        &name,
+        function_item.annotations(),
+        callee,
+        false,
+    );
+    let read_vtable = if utils::sandbox_is_fallible(ctx, &name) {
+        let error = utils::sandbox_error_closure(ctx, &name);
+        quote! { this.try_vtable::<#vtable>().map_err(#error)? }
+    } else {
+        quote! { this.vtable::<#vtable>() }
+    };
+    let sandbox_type = sandbox.sandbox_type_ident();
+
+    quote! {
+        impl #sandbox_type {
+            /// Calls the virtual method through the vtable of `this`.
+            pub fn #function_name(&mut self, #( #args ),* ) #ret #bounds {
+                #declaration
+
+                let vtable = unsafe { #read_vtable };
+                unsafe { #call }
+            }
+        }
+    }
+}
+
+/// Generates a method on the sandbox type that allocates an object in the sandbox and runs a C++
+/// constructor on it, or one that runs the destructor and frees the object. Returns `None` for
+/// other methods, or if no allocator is configured.
+fn sandbox_lifecycle_tokens(
+    ctx: &BindgenContext,
+    method: &Method,
+    function_item: &Item,
+    signature: &FunctionSig,
+    name: &str,
+    thunk: &Ident,
+) -> Option<proc_macro2::TokenStream> {
+    let sandbox = ctx.options().sandbox.as_ref()?;
+    let (alloc, dealloc) = sandbox.allocator_idents()?;
+    // The thunk is then a regular function.
+    if utils::skips_sandboxing(ctx, &thunk.to_string()) {
+        return None;
+    }
+    let (_, this_ty) = signature.argument_types().first()?;
+    let class = match utils::fnsig_argument_type(ctx, this_ty) {
+        syn::Type::Ptr(p) => *p.elem,
+        _ => return None,
+    };
+    let class_name = match class {
+        syn::Type::Path(ref path) => path.path.segments.last()?.ident.clone(),
+        _ => return None,
+    };
+
+    let prefix = ctx.trait_prefix();
+    let ptr_mut = sandbox.ptr_mut_type_path();
+    let sandbox_type = sandbox.sandbox_type_ident();
+    let fallible = utils::sandbox_is_fallible(ctx, &thunk.to_string());
+    // The thunks return a `Result` if they are fallible or catch exceptions.
+    let error_ty = if fallible {
+        Some(sandbox.error_type_path())
+    } else if sandbox.catches_exceptions() {
+        let runtime = sandbox.runtime_path();
+        Some(quote! { #runtime::CppException })
+    } else {
+        None
+    };
+    let ok = |tokens: proc_macro2::TokenStream| {
+        if error_ty.is_some() {
+            quote! { Ok(#tokens) }
+        } else {
+            tokens
+        }
+    };
+    let ret = |ty: proc_macro2::TokenStream| match error_ty {
+        Some(ref error_ty) => {
+            let ty = if ty.is_empty() { quote! { () } } else { ty };
+            quote! { -> ::#prefix::result::Result<#ty, #error_ty> }
+        }
+        None if ty.is_empty() => ty,
+        None => quote! { -> #ty },
+    };
+    let try_ = if error_ty.is_some() {
+        quote! { ? }
+    } else {
+        quote! {}
+    };
+
+    match method.kind() {
+        MethodKind::Constructor => {
+            // The allocator only guarantees the alignment of `malloc`.
+            let this_ty = ctx.resolve_type(*this_ty).canonical_type(ctx);
+            let layout = match *this_ty.kind() {
+                TypeKind::Pointer(pointee) => {
+                    ctx.resolve_type(pointee).layout(ctx)
+                }
+                _ => None,
+            };
+            if layout.map_or(false, |layout| layout.align > 16) {
+                return None;
+            }
+            let ident = ctx.rust_ident(format!("{}_{}", class_name, name));
+            let counted = utils::sandbox_counted_arguments(
+                ctx,
+                signature,
+                function_item.annotations(),
+            );
+            let arguments = utils::sandbox_arguments(
+                ctx,
+                signature,
+                &thunk.to_string(),
+                &counted,
+            );
+            let args = utils::fnsig_transformed_arguments(
+                ctx, signature, &counted, &arguments,
+            );
+            let arg_identifiers =
+                utils::fnsig_transformed_argument_identifiers(
+                    ctx, signature, &counted, &arguments,
+                );
+            let (args, arg_identifiers) = (&args[1..], &arg_identifiers[1..]);
+            let runtime = sandbox.runtime_path();
+            // A failed check returns an error if the method is fallible, and
+            // panics otherwise.
+            let fail = |kind: proc_macro2::TokenStream| {
+                if fallible {
+                    let error = sandbox.error_type_path();
+                    let function = ident.to_string();
+                    quote! { return Err(#error::new(#function, #kind)) }
+                } else {
+                    quote! { panic!("{}", #kind) }
+                }
+            };
+            let fail_null = if fallible {
+                fail(quote! { #runtime::SandboxErrorKind::Null })
+            } else {
+                let message =
+                    format!("could not allocate `{}` in the sandbox", class_name);
+                quote! { panic!(#message) }
+            };
+            let fail_check = fail(quote! { kind });
+            // The memory is freed again if the constructor fails.
+            let construct = if error_ty.is_some() {
+                quote! {
+                    if let Err(error) = self.#thunk(this, #( #arg_identifiers ),* ) {
+                        unsafe { self.__sandbox.call(move || #dealloc(raw)) };
+                        return Err(error);
+                    }
+                }
+            } else {
+                quote! {
+                    self.#thunk(this, #( #arg_identifiers ),* );
+                }
+            };
+            let ret = ret(quote! { #ptr_mut<#class> });
+            let this = ok(quote! { this });
+            Some(quote! {
+                impl #sandbox_type {
+                    /// Allocates an object in the sandbox and constructs it.
+                    pub fn #ident(&mut self, #( #args ),* ) #ret {
+                        extern "C" {
+                            fn #alloc(size: usize) -> *mut ::#prefix::ffi::c_void;
+                            fn #dealloc(ptr: *mut ::#prefix::ffi::c_void);
+                        }
+                        // Over-aligned classes are skipped above, unless their
+                        // layout is unknown.
+                        const _: () = assert!(
+                            ::#prefix::mem::align_of::<#class>() <= 16,
+                            "the class is over-aligned for the sandbox allocator"
+                        );
+
+                        let size = ::#prefix::mem::size_of::<#class>();
+                        let raw = unsafe { self.__sandbox.call(move || #alloc(size)) };
+                        if raw.is_null() {
+                            #fail_null;
+                        }
+                        let this = match #ptr_mut::try_new(raw.cast::<#class>()) {
+                            Ok(this) => this,
+                            Err(kind) => {
+                                unsafe { self.__sandbox.call(move || #dealloc(raw)) };
+                                #fail_check;
+                            }
+                        };
+                        #construct
+                        #this
+                    }
+                }
+            })
+        }
+        MethodKind::Destructor => {
+            let ident = ctx.rust_ident(format!("{}_delete", class_name));
+            let ret = ret(quote! {});
+            let done = ok(quote! {});
+            Some(quote! {
+                impl #sandbox_type {
+                    /// Destroys an object allocated in the sandbox and frees its memory.
+                    pub fn #ident(&mut self, this: #ptr_mut<#class>) #ret {
+                        extern "C" {
+                            fn #dealloc(ptr: *mut ::#prefix::ffi::c_void);
+                        }
+
+                        self.#thunk(this)#try_;
+                        let this = this.get().cast::<::#prefix::ffi::c_void>();
+                        unsafe { self.__sandbox.call(move || #dealloc(this)) }
+                        #done
+                    }
+                }
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Generates the sandbox type that the methods of sandboxed bindings are implemented on, along with
+/// its global instance if one is configured.
+fn sandbox_type_tokens(sandbox: &SandboxConfig) -> proc_macro2::TokenStream {
+    let runtime = sandbox.runtime_path();
+    let sandbox_type = sandbox.sandbox_type_ident();
+    let batch_type = sandbox.batch_type_ident();
+    let from_sandbox = match sandbox.initializer_path() {
+        Some(initializer) => quote! {
+            let mut this = #sandbox_type { __sandbox: sandbox };
+            #initializer(&mut this);
+            this
+        },
+        None => quote! { #sandbox_type { __sandbox: sandbox } },
+    };
+
+    let global = sandbox.global_ident().map(|global| {
+        quote! {
+            pub static #global: ::std::sync::LazyLock<::std::sync::Mutex<#sandbox_type>> =
+                ::std::sync::LazyLock::new(|| ::std::sync::Mutex::new(#sandbox_type::new()));
+        }
+    });
+
+    quote! {
+        pub struct #sandbox_type {
+            __sandbox: #runtime::Sandbox,
+        }
+
+        impl #sandbox_type {
+            /// Sets up the library in a new sandbox.
+            pub fn new() -> Self {
+                Self::from_sandbox(#runtime::Sandbox::new())
+            }
+
+            /// Sets up the library in `sandbox`.
+            pub fn from_sandbox(sandbox: #runtime::Sandbox) -> Self {
+                #from_sandbox
+            }
+
+            pub fn sandbox(&self) -> &#runtime::Sandbox {
+                &self.__sandbox
+            }
+
+            pub fn sandbox_mut(&mut self) -> &mut #runtime::Sandbox {
+                &mut self.__sandbox
+            }
+
+            /// Runs `f` inside the sandbox, so that the library functions it calls
+            /// through the batch share a single transition into the sandbox. `f`
+            /// cannot enter a sandbox again; doing so panics.
+            ///
+            /// # Safety
+            ///
+            /// `f` runs with the sandbox's protection and stack, so it must do no
+            /// more than call the library through the batch and inspect the
+            /// results, and must not reference data outside the sandbox.
+            pub unsafe fn batch<T: 'static>(
+                &mut self,
+                f: impl FnOnce(&mut #batch_type<'_>) -> T + 'static,
+            ) -> T {
+                unsafe {
+                    self.__sandbox.call_batch(move |sandbox| {
+                        f(&mut #batch_type { __sandbox: sandbox })
+                    })
+                }
+            }
+        }
+
+        impl Default for #sandbox_type {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        /// Calls the library from inside the sandbox, see `batch`. It has the same
+        /// methods for the library's functions as the sandbox type.
+        pub struct #batch_type<'a> {
+            __sandbox: &'a #runtime::Sandbox,
+        }
+
+        #global
+    }
+}
+
+/// Generates a module of marker types for the fields of a struct, implementing the runtime's
+/// `Field` trait so that sandbox pointers to the struct can be projected onto its fields.
+fn sandbox_field_tokens(
+    ctx: &BindgenContext,
+    sandbox: &SandboxConfig,
+    comp: &CompInfo,
+    canonical_name: &str,
+) -> proc_macro2::TokenStream {
+    let runtime = sandbox.runtime_path();
+    let struct_ident = ctx.rust_ident(canonical_name);
+    let module = ctx.rust_ident(format!("{}_fields", canonical_name));
+    let (markers, impls): (Vec<_>, Vec<_>) = comp
+        .fields()
+        .iter()
+        .filter_map(|field| match *field {
+            Field::DataMember(ref f) => Some(f),
+            // Bitfields don't start at byte offsets.
+            Field::Bitfields(_) => None,
+        })
+        .filter_map(|field| {
+            let name = ctx.rust_ident(field.name()?);
+            let offset = field.offset()? / 8;
+            let ty = field.ty().to_rust_ty_or_opaque(ctx, &());
+            let marker = quote! { pub struct #name; };
+            let field_impl = quote! {
+                unsafe impl #runtime::Field<#struct_ident> for #module::#name {
+                    type Type = #ty;
+                    const OFFSET: usize = #offset;
+                }
+            };
+            Some((marker, field_impl))
+        })
+        .unzip();
+    if markers.is_empty() {
+        return quote! {};
+    }
+
+    let doc = format!(
+        " Markers for projecting sandbox pointers onto the fields of [`{}`].",
+        canonical_name
+    );
+    quote! {
+        #[doc = #doc]
+        #[allow(non_camel_case_types)]
+        pub mod #module {
+            #( #markers )*
+        }
+
+        #( #impls )*
+    }
+}
+
+pub(crate) mod utils {
+    use super::serialize::CSerialize;
+    use super::{error, CodegenError, CodegenResult, ToRustTyOrOpaque};
+    use crate::callbacks::{
+        SandboxArgumentInfo, SandboxArgumentKind, SandboxErrorStyle,
+        SandboxOwnership,
+    };
+    use crate::ir::context::BindgenContext;
+    use crate::ir::context::TypeId;
+    use crate::ir::annotations::Annotations;
+    use crate::ir::function::{Abi, ClangAbi, CountedArgument, FunctionSig};
+    use crate::ir::int::IntKind;
+    use crate::ir::item::{IsOpaque, Item, ItemCanonicalPath};
+    use crate::ir::ty::TypeKind;
+    use crate::{args_are_cpp, file_is_cpp, HashSet};
+    use quote::ToTokens;
+    use std::borrow::Cow;
+    use std::io::Write;
+    use std::mem;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    pub(super) fn serialize_items(
+        result: &CodegenResult,
+        context: &BindgenContext,
+    ) -> Result<(), CodegenError> {
+        if result.items_to_serialize.is_empty() &&
+            result.sandbox_shims.is_empty()
+        {
+            return Ok(());
+        }
+
+        let path = context
+            .options()
+            .wrap_static_fns_path
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                std::env::temp_dir().join("bindgen").join("extern")
+            });
+
+        let dir = path.parent().unwrap();
+
+        if !dir.exists() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let is_cpp = args_are_cpp(&context.options().clang_args) ||
+            context
+                .options()
+                .input_headers
+                .iter()
+                .any(|h| file_is_cpp(h));
+
+        // The shims catching exceptions are always C++, so C headers and
+        // wrappers are given C linkage if there are any.
+        let c_linkage = !is_cpp && !result.sandbox_shims.is_empty();
+        let is_cpp = is_cpp || c_linkage;
+        let source_path = path.with_extension(if is_cpp { "cpp" } else { "c" });
+
+        let mut code = Vec::new();
+
+        if c_linkage {
+            writeln!(code, "extern \"C\" {{\n")?;
+        }
+
+        if !context.options().input_headers.is_empty() {
+            for header in &context.options().input_headers {
+                writeln!(code, "#include \"{}\"", header)?;
+            }
+
+            writeln!(code)?;
+        }
+
+        if !context.options().input_header_contents.is_empty() {
+            for (name, contents) in &context.options().input_header_contents {
+                writeln!(code, "// {}\n{}", name, contents)?;
+            }
+
+            writeln!(code)?;
+        }
+
+        writeln!(code, "// Static wrappers\n")?;
+
+        for (id, wrap_as_variadic) in &result.items_to_serialize {
+            let item = context.resolve_item(*id);
+            item.serialize(context, wrap_as_variadic, &mut vec![], &mut code)?;
+        }
+
+        if c_linkage {
+            writeln!(code, "\n}}")?;
+        }
+
+        if let Some(ref sandbox) = context.options().sandbox {
+            if !result.sandbox_shims.is_empty() {
+                writeln!(code, "\n// Exception-catching shims\n")?;
+                super::serialize::serialize_exception_record(
+                    &sandbox.exception_record_name(),
+                    &mut code,
+                )?;
+                for shim in &result.sandbox_shims {
+                    writeln!(code)?;
+                    shim.serialize(context, (), &mut vec![], &mut code)?;
+                }
+            }
+        }
+
+        std::fs::write(source_path, code)?;
+
         Ok(())
     }
 
@@ -5270,21 +6276,312 @@ pub(crate) mod utils {
         }
     }
 
+    /// How a sandboxed function's returned pointer is wrapped.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub(crate) enum SandboxReturn {
+        /// In a sandbox pointer or handle.
+        Pointer,
+        /// In a sandbox pointer to a slice of the known number of elements.
+        Slice,
+        /// In an owned string, which frees it when dropped.
+        OwnedString,
+    }
+
+    /// Whether `ty` is a pointer to a character type.
+    fn is_char_pointer(ctx: &BindgenContext, ty: TypeId) -> bool {
+        let ty = ctx.resolve_type(ty).canonical_type(ctx);
+        match *ty.kind() {
+            TypeKind::Pointer(pointee) => matches!(
+                *ctx.resolve_type(pointee).canonical_type(ctx).kind(),
+                TypeKind::Int(
+                    IntKind::Char { .. } | IntKind::SChar | IntKind::UChar
+                )
+            ),
+            _ => false,
+        }
+    }
+
+    /// Whether the sandboxed function `name` was configured to return a
+    /// string owned by the caller, and can: it must return a `char` pointer,
+    /// there must be an allocator to free the string with, and the library
+    /// must be linked statically.
+    pub(crate) fn sandbox_returns_owned_string(
+        ctx: &BindgenContext,
+        sig: &FunctionSig,
+        name: &str,
+    ) -> bool {
+        let sandbox = ctx.options().sandbox.as_ref().unwrap();
+        if !sandbox.is_owned_string_return(name) {
+            return false;
+        }
+        let usable = is_char_pointer(ctx, sig.return_type()) &&
+            sandbox.allocator_idents().is_some() &&
+            ctx.options().dynamic_library_name.is_none();
+        if !usable {
+            warn!("Ignoring the owned string returned by `{}`", name);
+        }
+        usable
+    }
+
+    /// The return type of a sandboxed function. A returned pointer is a slice
+    /// if the number of elements it points to is known, an owned string if
+    /// it is configured to be one, and is optional if it may be null. The
+    /// values of out-parameters are returned after it in a tuple. Fallible
+    /// bindings wrap the type in a `Result`.
     pub(crate) fn fnsig_transformed_return_ty(
         ctx: &BindgenContext,
         sig: &FunctionSig,
+        kind: SandboxReturn,
+        nullable: bool,
+        catch: bool,
+        fallible: bool,
+        outs: &[syn::Type],
     ) -> proc_macro2::TokenStream {
-        match fnsig_return_ty_internal(ctx, sig) {
+        let sandbox = ctx
+            .options()
+            .sandbox
+            .as_ref()
+            .expect("return types are only transformed in sandboxed bindings");
+        let prefix = ctx.trait_prefix();
+        let ty = match fnsig_return_ty_internal(ctx, sig) {
             syn::Type::Tuple(syn::TypeTuple { elems, .. })
                 if elems.is_empty() =>
             {
-                quote! {}
+                None
             }
-            ty => {
-                let ty = transform_type(ty);
-                quote! { -> #ty }
+            syn::Type::Ptr(p) => {
+                let ty = match kind {
+                    SandboxReturn::Pointer => transform_type(
+                        ctx,
+                        sig.return_type(),
+                        syn::Type::Ptr(p),
+                    ),
+                    SandboxReturn::Slice => {
+                        transform_slice_type(ctx, sig.return_type(), p)
+                    }
+                    SandboxReturn::OwnedString => sandbox.c_string_type_path(),
+                };
+                if nullable {
+                    Some(quote! { ::#prefix::option::Option<#ty> })
+                } else {
+                    Some(ty)
+                }
             }
-        }
+            ty => Some(transform_type(ctx, sig.return_type(), ty)),
+        };
+        let ty = match ty {
+            _ if outs.is_empty() => ty,
+            Some(ty) => Some(quote! { (#ty, #( #outs ),* ) }),
+            None => Some(quote! { ( #( #outs ),* ) }),
+        };
+        match ty {
+            _ if fallible => {
+                let ty = ty.unwrap_or_else(|| quote! { () });
+                let error = sandbox.error_type_path();
+                quote! { -> ::#prefix::result::Result<#ty, #error> }
+            }
+            _ if catch => {
+                let ty = ty.unwrap_or_else(|| quote! { () });
+                let runtime = sandbox.runtime_path();
+                quote! {
+                    -> ::#prefix::result::Result<#ty, #runtime::CppException>
+                }
+            }
+            Some(ty) => quote! { -> #ty },
+            None => quote! {},
+        }
+    }
+
+    /// A closure turning the failed check of a fallible sandboxed function
+    /// into its error.
+    pub(crate) fn sandbox_error_closure(
+        ctx: &BindgenContext,
+        name: &str,
+    ) -> proc_macro2::TokenStream {
+        let sandbox = ctx.options().sandbox.as_ref().unwrap();
+        let error = sandbox.error_type_path();
+        quote! { |kind| #error::new(#name, kind) }
+    }
+
+    /// Whether the sandboxed function `name` returns a `Result` for failed
+    /// checks, as the parse callbacks or else the configuration say.
+    pub(crate) fn sandbox_is_fallible(
+        ctx: &BindgenContext,
+        name: &str,
+    ) -> bool {
+        let sandbox = ctx.options().sandbox.as_ref().unwrap();
+        match ctx.options().last_callback(|cb| cb.sandbox_error_style(name)) {
+            Some(style) => style == SandboxErrorStyle::Result,
+            None => sandbox.is_fallible(),
+        }
+    }
+
+    /// Whether any of the parse callbacks leaves the function `name` out of
+    /// the sandbox.
+    pub(crate) fn skips_sandboxing(ctx: &BindgenContext, name: &str) -> bool {
+        ctx.options()
+            .parse_callbacks
+            .iter()
+            .any(|cb| cb.skip_sandboxing(name))
+    }
+
+    /// Calls into the sandbox, unless in a batch, which already runs inside
+    /// it.
+    pub(crate) fn sandbox_enter(
+        batch: bool,
+        call: proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        if batch {
+            call
+        } else {
+            quote! { self.__sandbox.call(move || #call) }
+        }
+    }
+
+    /// How a sandboxed function takes one of its arguments.
+    #[derive(Clone, Copy, Debug)]
+    pub(crate) struct SandboxArgument {
+        pub(crate) kind: SandboxArgumentKind,
+        /// Whether a pointer passed as is may be null.
+        pub(crate) nullable: bool,
+        /// Whether the function takes ownership of a copied-in argument.
+        pub(crate) transferred: bool,
+    }
+
+    /// How the sandboxed function `name` takes each of its arguments, as the
+    /// parse callbacks say. Arguments can only be copied in or out with an
+    /// allocator and without dynamic loading, and only if they point to
+    /// something other than `void` or an opaque record, though counted `void`
+    /// pointers can be copied in as bytes. Out-parameters must be mutable and
+    /// not counted.
+    pub(crate) fn sandbox_arguments(
+        ctx: &BindgenContext,
+        sig: &FunctionSig,
+        name: &str,
+        counted: &[CountedArgument],
+    ) -> Vec<SandboxArgument> {
+        let sandbox = ctx.options().sandbox.as_ref().unwrap();
+        let can_copy = sandbox.allocator_idents().is_some() &&
+            ctx.options().dynamic_library_name.is_none();
+        sig.argument_types()
+            .iter()
+            .enumerate()
+            .map(|(index, (arg_name, ty))| {
+                let info = || SandboxArgumentInfo {
+                    function: name,
+                    name: arg_name.as_deref(),
+                    index,
+                };
+                let mut argument = SandboxArgument {
+                    kind: SandboxArgumentKind::Pointer,
+                    nullable: false,
+                    transferred: false,
+                };
+                let p = match fnsig_argument_type(ctx, ty) {
+                    syn::Type::Ptr(p) => p,
+                    _ => return argument,
+                };
+                let is_counted =
+                    counted.iter().any(|arg| arg.pointer == index);
+                let is_plain = !is_opaque_pointer(ctx, *ty) &&
+                    !is_void_pointer(ctx, *ty);
+                let kind = ctx
+                    .options()
+                    .last_callback(|cb| cb.sandbox_argument_kind(info()))
+                    .unwrap_or(SandboxArgumentKind::Pointer);
+                let usable = match kind {
+                    SandboxArgumentKind::Pointer => true,
+                    SandboxArgumentKind::CopyIn => {
+                        can_copy && (is_plain || is_counted)
+                    }
+                    SandboxArgumentKind::Out => {
+                        can_copy &&
+                            is_plain &&
+                            !is_counted &&
+                            p.const_token.is_none()
+                    }
+                };
+                if !usable {
+                    warn!(
+                        "Passing argument {} of `{}` as a sandbox pointer",
+                        index, name
+                    );
+                    return argument;
+                }
+                let nullable = ctx
+                    .options()
+                    .last_callback(|cb| cb.sandbox_argument_nullable(info()));
+                let ownership = ctx
+                    .options()
+                    .last_callback(|cb| cb.sandbox_argument_ownership(info()));
+                argument.kind = kind;
+                argument.nullable = kind == SandboxArgumentKind::Pointer &&
+                    !is_counted &&
+                    nullable.unwrap_or(false);
+                argument.transferred = kind == SandboxArgumentKind::CopyIn &&
+                    ownership == Some(SandboxOwnership::Transferred);
+                argument
+            })
+            .collect()
+    }
+
+    /// The types of the values a sandboxed function leaves in its
+    /// out-parameters.
+    pub(crate) fn sandbox_out_types(
+        ctx: &BindgenContext,
+        sig: &FunctionSig,
+        arguments: &[SandboxArgument],
+    ) -> Vec<syn::Type> {
+        sig.argument_types()
+            .iter()
+            .zip(arguments)
+            .filter(|(_, arg)| arg.kind == SandboxArgumentKind::Out)
+            .filter_map(|((_, ty), _)| match fnsig_argument_type(ctx, ty) {
+                syn::Type::Ptr(p) => Some(*p.elem),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The types of the values a sandboxed function's out-parameters and
+    /// copied arguments are copied as, which are written to or read from
+    /// memory the sandbox can change.
+    pub(crate) fn sandbox_copied_types(
+        ctx: &BindgenContext,
+        sig: &FunctionSig,
+        arguments: &[SandboxArgument],
+    ) -> Vec<syn::Type> {
+        sig.argument_types()
+            .iter()
+            .zip(arguments)
+            .filter(|(_, arg)| arg.kind != SandboxArgumentKind::Pointer)
+            .filter_map(|((_, ty), _)| match fnsig_argument_type(ctx, ty) {
+                syn::Type::Ptr(ref p) => Some(slice_element_type(ctx, *ty, p)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// A `where` clause requiring each of `types` to be `SandboxSafe`, or
+    /// nothing if there are none. The bounds are higher-ranked, so that the
+    /// bindings still compile for a type that isn't, and only the method
+    /// using it can't be called.
+    pub(crate) fn sandbox_safe_bounds(
+        runtime: &proc_macro2::TokenStream,
+        types: &[syn::Type],
+    ) -> proc_macro2::TokenStream {
+        let mut seen = HashSet::default();
+        let types: Vec<_> = types
+            .iter()
+            .filter(|ty| seen.insert(ty.to_token_stream().to_string()))
+            .collect();
+        if types.is_empty() {
+            return quote! {};
+        }
+        quote! {
+            where
+                #( for<'a> #types: #runtime::SandboxSafe ),*
+        }
     }
 
     pub(crate) fn fnsig_argument_type(
@@ -5366,32 +6663,149 @@ pub(crate) mod utils {
         args
     }
 
-    pub(crate) fn transform_type(ty: syn::Type) -> proc_macro2::TokenStream {
+    /// Whether `ty` is a pointer to a record that Rust cannot look into, such
+    /// as an incomplete struct. Sandboxed bindings pass these as handles.
+    pub(crate) fn is_opaque_pointer(ctx: &BindgenContext, ty: TypeId) -> bool {
+        let ty = ctx.resolve_type(ty).canonical_type(ctx);
+        let pointee = match *ty.kind() {
+            TypeKind::Pointer(pointee) => pointee,
+            _ => return false,
+        };
+        let pointee = pointee
+            .into_resolver()
+            .through_type_refs()
+            .through_type_aliases()
+            .resolve(ctx);
+        pointee.is_opaque(ctx, &()) ||
+            matches!(
+                *pointee.expect_type().kind(),
+                TypeKind::Comp(ref info) if info.is_forward_declaration()
+            )
+    }
+
+    /// Wraps a pointer type in the sandbox pointer types, or in a handle if
+    /// it points to an opaque record.
+    pub(crate) fn transform_type(
+        ctx: &BindgenContext,
+        ty_id: TypeId,
+        ty: syn::Type,
+    ) -> proc_macro2::TokenStream {
+        let sandbox = ctx
+            .options()
+            .sandbox
+            .as_ref()
+            .expect("pointers are only transformed in sandboxed bindings");
         match ty {
             syn::Type::Ptr(p) => {
                 let t = p.elem;
-                if p.const_token.is_some() {
-                    quote! { mpk::SandboxPtr<#t> }
+                if is_opaque_pointer(ctx, ty_id) {
+                    let handle = sandbox.handle_type_path();
+                    quote! { #handle<#t> }
+                } else if p.const_token.is_some() {
+                    let ptr = sandbox.ptr_type_path();
+                    quote! { #ptr<#t> }
                 } else {
-                    quote! { mpk::SandboxPtrMut<#t> }
+                    let ptr_mut = sandbox.ptr_mut_type_path();
+                    quote! { #ptr_mut<#t> }
                 }
             },
             t => quote ! { #t }
         }
     }
 
-    pub(crate) fn fnsig_transformed_arguments_iter<
-        'a,
-        I: Iterator<Item = &'a (Option<String>, crate::ir::context::TypeId)>,
-    >(
+    /// Whether `ty` is a pointer to `void`.
+    fn is_void_pointer(ctx: &BindgenContext, ty: TypeId) -> bool {
+        let ty = ctx.resolve_type(ty).canonical_type(ctx);
+        match *ty.kind() {
+            TypeKind::Pointer(pointee) => matches!(
+                *ctx.resolve_type(pointee).canonical_type(ctx).kind(),
+                TypeKind::Void
+            ),
+            _ => false,
+        }
+    }
+
+    /// The element type of the slice that a counted pointer argument or
+    /// return value is passed as. `void` pointers are counted in bytes.
+    fn slice_element_type(
         ctx: &BindgenContext,
-        args_iter: I,
-        is_variadic: bool,
-    ) -> Vec<proc_macro2::TokenStream> {
+        ty_id: TypeId,
+        ty: &syn::TypePtr,
+    ) -> syn::Type {
+        if is_void_pointer(ctx, ty_id) {
+            syn::parse_quote! { u8 }
+        } else {
+            (*ty.elem).clone()
+        }
+    }
+
+    /// Wraps a counted pointer type in the sandbox pointer types as a slice.
+    pub(crate) fn transform_slice_type(
+        ctx: &BindgenContext,
+        ty_id: TypeId,
+        ty: syn::TypePtr,
+    ) -> proc_macro2::TokenStream {
+        let sandbox = ctx
+            .options()
+            .sandbox
+            .as_ref()
+            .expect("pointers are only transformed in sandboxed bindings");
+        let t = slice_element_type(ctx, ty_id, &ty);
+        if ty.const_token.is_some() {
+            let ptr = sandbox.ptr_type_path();
+            quote! { #ptr<[#t]> }
+        } else {
+            let ptr_mut = sandbox.ptr_mut_type_path();
+            quote! { #ptr_mut<[#t]> }
+        }
+    }
+
+    /// The counted pointer arguments of a sandboxed function that can be
+    /// passed as slices: the pointer must point to something other than an
+    /// opaque record, and the count must be an integer.
+    pub(crate) fn sandbox_counted_arguments(
+        ctx: &BindgenContext,
+        sig: &FunctionSig,
+        annotations: &Annotations,
+    ) -> Vec<CountedArgument> {
+        let args = sig.argument_types();
+        sig.counted_arguments(annotations)
+            .into_iter()
+            .filter(|arg| {
+                let (_, pointer) = args[arg.pointer];
+                let (_, count) = args[arg.count];
+                let usable = arg.pointer != arg.count &&
+                    matches!(
+                        fnsig_argument_type(ctx, &pointer),
+                        syn::Type::Ptr(_)
+                    ) &&
+                    !is_opaque_pointer(ctx, pointer) &&
+                    (arg.in_bytes || !is_void_pointer(ctx, pointer)) &&
+                    matches!(
+                        *ctx.resolve_type(count).canonical_type(ctx).kind(),
+                        TypeKind::Int(_)
+                    );
+                if !usable {
+                    warn!(
+                        "Ignoring the count of argument {} of `{}`",
+                        arg.pointer,
+                        sig.name()
+                    );
+                }
+                usable
+            })
+            .collect()
+    }
+
+    /// The identifiers of a function's arguments, naming unnamed ones.
+    fn fnsig_argument_idents(
+        ctx: &BindgenContext,
+        sig: &FunctionSig,
+    ) -> Vec<proc_macro2::Ident> {
         let mut unnamed_arguments = 0;
-        let mut args = args_iter
-            .map(|(name, ty)| {
-                let arg_ty = transform_type(fnsig_argument_type(ctx, ty));
+        sig.argument_types()
+            .iter()
+            .map(|(name, _)| {
                 let arg_name = match *name {
                     Some(ref name) => ctx.rust_mangle(name).into_owned(),
                     None => {
@@ -5401,19 +6815,9 @@ pub(crate) mod utils {
                 };
 
                 assert!(!arg_name.is_empty());
-                let arg_name = ctx.rust_ident(arg_name);
-
-                quote! {
-                    #arg_name : #arg_ty
-                }
+                ctx.rust_ident(arg_name)
             })
-            .collect::<Vec<_>>();
-
-        if is_variadic {
-            args.push(quote! { ... })
-        }
-
-        args
+            .collect()
     }
 
     pub(crate) fn fnsig_arguments(
@@ -5427,15 +6831,77 @@ pub(crate) mod utils {
         )
     }
 
+    /// Whether the sandboxed binding of a function takes its argument `i`,
+    /// which it doesn't if the argument is a count or an out-parameter.
+    fn is_transformed_argument(
+        i: usize,
+        counted: &[CountedArgument],
+        arguments: &[SandboxArgument],
+    ) -> bool {
+        !counted.iter().any(|arg| arg.count == i) &&
+            arguments[i].kind != SandboxArgumentKind::Out
+    }
+
+    /// The identifiers of the arguments of a sandboxed function, leaving out
+    /// the counts of counted pointers and out-parameters.
+    pub(crate) fn fnsig_transformed_argument_identifiers(
+        ctx: &BindgenContext,
+        sig: &FunctionSig,
+        counted: &[CountedArgument],
+        arguments: &[SandboxArgument],
+    ) -> Vec<proc_macro2::Ident> {
+        fnsig_argument_idents(ctx, sig)
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| is_transformed_argument(*i, counted, arguments))
+            .map(|(_, ident)| ident)
+            .collect()
+    }
+
+    /// The arguments of a sandboxed function. Counted pointers are taken as
+    /// slices, and their counts are left out, as are out-parameters. Copied-in
+    /// arguments are taken as references to memory of the caller, and other
+    /// pointers that may be null as options.
     pub(crate) fn fnsig_transformed_arguments(
         ctx: &BindgenContext,
         sig: &FunctionSig,
+        counted: &[CountedArgument],
+        arguments: &[SandboxArgument],
     ) -> Vec<proc_macro2::TokenStream> {
-        fnsig_transformed_arguments_iter(
-            ctx,
-            sig.argument_types().iter(),
-            sig.is_variadic(),
-        )
+        let prefix = ctx.trait_prefix();
+        sig.argument_types()
+            .iter()
+            .zip(fnsig_argument_idents(ctx, sig))
+            .enumerate()
+            .filter(|(i, _)| is_transformed_argument(*i, counted, arguments))
+            .map(|(i, ((_, ty), arg_name))| {
+                let is_counted = counted.iter().any(|arg| arg.pointer == i);
+                let arg_ty = match fnsig_argument_type(ctx, ty) {
+                    syn::Type::Ptr(p)
+                        if arguments[i].kind ==
+                            SandboxArgumentKind::CopyIn =>
+                    {
+                        let t = slice_element_type(ctx, *ty, &p);
+                        if is_counted {
+                            quote! { &[#t] }
+                        } else {
+                            quote! { &#t }
+                        }
+                    }
+                    syn::Type::Ptr(p) if is_counted => {
+                        transform_slice_type(ctx, *ty, p)
+                    }
+                    arg_ty if arguments[i].nullable => {
+                        let arg_ty = transform_type(ctx, *ty, arg_ty);
+                        quote! { ::#prefix::option::Option<#arg_ty> }
+                    }
+                    arg_ty => transform_type(ctx, *ty, arg_ty),
+                };
+                quote! {
+                    #arg_name : #arg_ty
+                }
+            })
+            .collect()
     }
 
     pub(crate) fn fnsig_argument_identifiers(
@@ -5467,39 +6933,371 @@ pub(crate) mod utils {
         args
     }
 
-    pub(crate) fn fnsig_transformed_argument_identifiers(
+    /// The variable holding the sandbox copy of argument `i`.
+    fn sandbox_copy_ident(i: usize) -> proc_macro2::Ident {
+        proc_macro2::Ident::new(
+            &format!("__bindgen_arg{}", i),
+            proc_macro2::Span::call_site(),
+        )
+    }
+
+    /// Statements unwrapping the sandbox pointers and handles passed to a
+    /// sandboxed function into raw pointers, shadowing the arguments. Counts
+    /// are computed from the length of their slices first, checking that
+    /// slices sharing a count have the same length. Copied-in arguments and
+    /// out-parameters are allocated in the sandbox last, so that a failed
+    /// check doesn't leak them.
+    pub(crate) fn fnsig_sandbox_argument_conversions(
         ctx: &BindgenContext,
         sig: &FunctionSig,
+        name: &str,
+        counted: &[CountedArgument],
+        arguments: &[SandboxArgument],
+        batch: bool,
     ) -> Vec<proc_macro2::TokenStream> {
-        let mut unnamed_arguments = 0;
-        let args = sig
-            .argument_types()
-            .iter()
-            .map(|&(ref name, ty)| {
-                let arg_name = match *name {
-                    Some(ref name) => ctx.rust_mangle(name).into_owned(),
-                    None => {
-                        unnamed_arguments += 1;
-                        format!("arg{}", unnamed_arguments)
+        let sandbox = ctx.options().sandbox.as_ref().unwrap();
+        let fallible = sandbox_is_fallible(ctx, name);
+        let error = sandbox_error_closure(ctx, name);
+        let error_ty = sandbox.error_type_path();
+        let error_kind = sandbox.error_kind_type_path();
+        let prefix = ctx.trait_prefix();
+        let args = sig.argument_types();
+        let idents = fnsig_argument_idents(ctx, sig);
+
+        // The length of a counted slice, in the unit of its count.
+        let length = |arg: &CountedArgument| {
+            let pointer = &idents[arg.pointer];
+            let ty = args[arg.pointer].1;
+            match fnsig_argument_type(ctx, &ty) {
+                syn::Type::Ptr(ref p)
+                    if arg.in_bytes && !is_void_pointer(ctx, ty) =>
+                {
+                    let t = &p.elem;
+                    quote! {
+                        #pointer.len() * ::#prefix::mem::size_of::<#t>()
                     }
-                };
+                }
+                _ => quote! { #pointer.len() },
+            }
+        };
 
-                assert!(!arg_name.is_empty());
-                let arg_name = ctx.rust_ident(arg_name);
+        let mut conversions = vec![];
+        let mut seen: Vec<&CountedArgument> = vec![];
+        for arg in counted {
+            let len = length(arg);
+            let count = &idents[arg.count];
+            match seen.iter().find(|other| other.count == arg.count) {
+                Some(other) => {
+                    let other_len = length(other);
+                    let message = format!(
+                        "`{}` and `{}` must have the same length",
+                        idents[other.pointer], idents[arg.pointer]
+                    );
+                    conversions.push(if fallible {
+                        quote! {
+                            if #other_len != #len {
+                                return Err(#error_ty::new(#name, #error_kind::LengthMismatch));
+                            }
+                        }
+                    } else {
+                        quote! {
+                            assert_eq!(#other_len, #len, #message);
+                        }
+                    });
+                }
+                None => {
+                    let count_ty =
+                        fnsig_argument_type(ctx, &args[arg.count].1);
+                    let value = if is_usize(&count_ty) {
+                        len
+                    } else if fallible {
+                        quote! {
+                            <#count_ty as ::#prefix::convert::TryFrom<usize>>::try_from(#len)
+                                .map_err(|_| #error_ty::new(#name, #error_kind::LengthOverflow))?
+                        }
+                    } else {
+                        let message = format!(
+                            "`{}` is too long for `{}`",
+                            idents[arg.pointer], count
+                        );
+                        quote! {
+                            <#count_ty as ::#prefix::convert::TryFrom<usize>>::try_from(#len)
+                                .expect(#message)
+                        }
+                    };
+                    conversions.push(quote! {
+                        let #count = #value;
+                    });
+                }
+            }
+            seen.push(arg);
+        }
 
-                if matches!(fnsig_argument_type(ctx, &ty), syn::Type::Ptr(_)) {
-                    quote! {
-                        #arg_name.get()
+        for (i, (_, ty)) in args.iter().enumerate() {
+            let arg_name = &idents[i];
+            let is_const = match fnsig_argument_type(ctx, ty) {
+                syn::Type::Ptr(p) => p.const_token.is_some(),
+                _ => continue,
+            };
+            if arguments[i].kind != SandboxArgumentKind::Pointer {
+                continue;
+            }
+            let conversion = if counted.iter().any(|arg| arg.pointer == i) {
+                let as_ptr = match fnsig_argument_type(ctx, ty) {
+                    syn::Type::Ptr(p) if p.const_token.is_some() => {
+                        quote! { as_ptr }
                     }
+                    _ => quote! { as_mut_ptr },
+                };
+                if is_void_pointer(ctx, *ty) {
+                    quote! { #arg_name.#as_ptr().cast() }
+                } else {
+                    quote! { #arg_name.#as_ptr() }
+                }
+            } else if is_opaque_pointer(ctx, *ty) && fallible {
+                quote! {
+                    #arg_name.try_get(&self.__sandbox).map_err(#error)?
+                }
+            } else if is_opaque_pointer(ctx, *ty) {
+                quote! { #arg_name.get(&self.__sandbox) }
+            } else {
+                quote! { #arg_name.get() }
+            };
+            let conversion = if arguments[i].nullable {
+                let null = if is_const && !is_opaque_pointer(ctx, *ty) {
+                    quote! { null }
                 } else {
+                    quote! { null_mut }
+                };
+                quote! {
+                    match #arg_name {
+                        Some(#arg_name) => #conversion,
+                        None => ::#prefix::ptr::#null(),
+                    }
+                }
+            } else {
+                conversion
+            };
+            conversions.push(quote! {
+                let #arg_name = #conversion;
+            });
+        }
+
+        let copies = args
+            .iter()
+            .zip(arguments)
+            .enumerate()
+            .filter(|(_, (_, arg))| arg.kind != SandboxArgumentKind::Pointer)
+            .collect::<Vec<_>>();
+        if copies.is_empty() {
+            return conversions;
+        }
+        let (alloc, dealloc) = sandbox.allocator_idents().unwrap();
+        let ptr_mut = sandbox.ptr_mut_type_path();
+        conversions.push(quote! {
+            extern "C" {
+                fn #alloc(size: usize) -> *mut ::#prefix::ffi::c_void;
+                fn #dealloc(ptr: *mut ::#prefix::ffi::c_void);
+            }
+        });
+        // The statements freeing the copies made so far, which a failed check
+        // runs before returning.
+        let mut frees = vec![];
+        for (i, ((_, ty), arg)) in copies {
+            let arg_name = &idents[i];
+            let copy = sandbox_copy_ident(i);
+            let t = match fnsig_argument_type(ctx, ty) {
+                syn::Type::Ptr(ref p) => slice_element_type(ctx, *ty, p),
+                _ => unreachable!(),
+            };
+            let is_counted = counted.iter().any(|arg| arg.pointer == i);
+            // Memory returned by the allocator is checked like any other
+            // pointer from the sandbox before it is written to.
+            let check = match (fallible, is_counted) {
+                (true, true) => sandbox_checked_copy(
                     quote! {
-                        #arg_name
+                        #ptr_mut::try_new(#copy.cast::<#t>())
+                            .and_then(|ptr| ptr.try_as_slice(#arg_name.len()))
+                    },
+                    &error,
+                    &frees,
+                ),
+                (true, false) => sandbox_checked_copy(
+                    quote! { #ptr_mut::try_new(#copy.cast::<#t>()) },
+                    &error,
+                    &frees,
+                ),
+                (false, true) => quote! {
+                    #ptr_mut::new(#copy.cast::<#t>()).as_slice(#arg_name.len())
+                },
+                (false, false) => quote! {
+                    #ptr_mut::new(#copy.cast::<#t>())
+                },
+            };
+            let ptr = if is_counted {
+                quote! { #copy.as_mut_ptr() }
+            } else {
+                quote! { #copy.get() }
+            };
+            let free = sandbox_enter(batch, quote! { #dealloc(#ptr.cast()) });
+            frees.push(quote! { #free; });
+            conversions.push(if arg.kind == SandboxArgumentKind::Out {
+                let alloc = sandbox_enter(batch, quote! { #alloc(#copy) });
+                quote! {
+                    let #copy = ::#prefix::mem::size_of::<#t>();
+                    let #copy = #alloc;
+                    let #copy = #check;
+                    ::#prefix::ptr::write_bytes(#copy.get(), 0, 1);
+                    let #arg_name = #copy.get();
+                }
+            } else {
+                // `malloc(0)` may return null, so at least a byte is
+                // allocated for empty slices.
+                let alloc = sandbox_enter(batch, quote! { #alloc(#copy) });
+                let (src, dst, len) = if is_counted {
+                    (
+                        quote! { #arg_name.as_ptr() },
+                        quote! { #copy.as_mut_ptr() },
+                        quote! { #arg_name.len() },
+                    )
+                } else {
+                    (
+                        quote! { #arg_name },
+                        quote! { #copy.get() },
+                        quote! { 1 },
+                    )
+                };
+                quote! {
+                    let #copy = ::#prefix::mem::size_of_val(#arg_name).max(1);
+                    let #copy = #alloc;
+                    let #copy = #check;
+                    ::#prefix::ptr::copy_nonoverlapping(#src, #dst, #len);
+                    let #arg_name = #dst as _;
+                }
+            });
+        }
+        conversions
+    }
+
+    /// Checks the copy of an argument the sandbox's allocator returned, with
+    /// `check` evaluating to the checked copy or the failed check. If the
+    /// check fails, the `frees` of the copies made before are run and the
+    /// error is returned, as nothing else would free them.
+    pub(crate) fn sandbox_checked_copy(
+        check: proc_macro2::TokenStream,
+        error: &proc_macro2::TokenStream,
+        frees: &[proc_macro2::TokenStream],
+    ) -> proc_macro2::TokenStream {
+        quote! {
+            match #check {
+                Ok(copy) => copy,
+                Err(kind) => {
+                    #( #frees )*
+                    return Err((#error)(kind));
+                }
+            }
+        }
+    }
+
+    /// Statements run after a sandboxed function returns, which free the
+    /// copies of arguments it doesn't take ownership of, and read the values
+    /// of out-parameters into variables named after them before freeing
+    /// those.
+    pub(crate) fn fnsig_sandbox_argument_cleanup(
+        ctx: &BindgenContext,
+        sig: &FunctionSig,
+        counted: &[CountedArgument],
+        arguments: &[SandboxArgument],
+        batch: bool,
+    ) -> Vec<proc_macro2::TokenStream> {
+        let sandbox = ctx.options().sandbox.as_ref().unwrap();
+        let prefix = ctx.trait_prefix();
+        let idents = fnsig_argument_idents(ctx, sig);
+        let dealloc = match sandbox.allocator_idents() {
+            Some((_, dealloc)) => dealloc,
+            None => return vec![],
+        };
+        arguments
+            .iter()
+            .enumerate()
+            .filter_map(|(i, arg)| {
+                let arg_name = &idents[i];
+                let copy = sandbox_copy_ident(i);
+                match arg.kind {
+                    SandboxArgumentKind::Out => {
+                        let free = sandbox_enter(
+                            batch,
+                            quote! { #dealloc(#copy.get().cast()) },
+                        );
+                        Some(quote! {
+                            let #arg_name = ::#prefix::ptr::read(#copy.get());
+                            #free;
+                        })
+                    }
+                    SandboxArgumentKind::CopyIn if !arg.transferred => {
+                        let ptr = if counted.iter().any(|arg| arg.pointer == i)
+                        {
+                            quote! { #copy.as_mut_ptr() }
+                        } else {
+                            quote! { #copy.get() }
+                        };
+                        let free = sandbox_enter(
+                            batch,
+                            quote! { #dealloc(#ptr.cast()) },
+                        );
+                        Some(quote! { #free; })
                     }
+                    _ => None,
                 }
             })
-            .collect::<Vec<_>>();
+            .collect()
+    }
 
-        args
+    /// The number of elements a sandboxed function's returned pointer points
+    /// to, if it is known: either an argument or a literal.
+    pub(crate) fn fnsig_sandbox_return_count(
+        ctx: &BindgenContext,
+        sig: &FunctionSig,
+        annotations: &Annotations,
+    ) -> Option<proc_macro2::TokenStream> {
+        let count = annotations.return_count()?;
+        let ret_ty = fnsig_return_ty_internal(ctx, sig);
+        if !matches!(ret_ty, syn::Type::Ptr(_)) ||
+            is_opaque_pointer(ctx, sig.return_type()) ||
+            is_void_pointer(ctx, sig.return_type())
+        {
+            warn!("Ignoring the return count of `{}`", sig.name());
+            return None;
+        }
+
+        if let Ok(count) = count.parse::<usize>() {
+            let count = proc_macro2::Literal::usize_unsuffixed(count);
+            return Some(quote! { #count });
+        }
+        let position = sig
+            .argument_types()
+            .iter()
+            .position(|(name, _)| name.as_deref() == Some(count));
+        match position {
+            Some(i) => {
+                let count = &fnsig_argument_idents(ctx, sig)[i];
+                let count_ty =
+                    fnsig_argument_type(ctx, &sig.argument_types()[i].1);
+                if is_usize(&count_ty) {
+                    Some(quote! { #count })
+                } else {
+                    Some(quote! { #count as usize })
+                }
+            }
+            None => {
+                warn!("Function `{}` has no argument `{}`", sig.name(), count);
+                None
+            }
+        }
+    }
+
+    fn is_usize(ty: &syn::Type) -> bool {
+        matches!(ty, syn::Type::Path(p) if p.path.is_ident("usize"))
     }
 
     pub(crate) fn fnsig_block(
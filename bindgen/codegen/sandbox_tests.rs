@@ -0,0 +1,141 @@
+//! Tests of the code generated for sandboxed bindings.
+
+use super::{sandbox_type_tokens, utils};
+use crate::SandboxConfig;
+
+#[test]
+fn copied_types_must_be_sandbox_safe() {
+    let runtime = quote! { ::mpk };
+    let types: Vec<syn::Type> = vec![
+        syn::parse_quote! { Point },
+        syn::parse_quote! { u8 },
+        syn::parse_quote! { Point },
+    ];
+    assert_eq!(
+        utils::sandbox_safe_bounds(&runtime, &types).to_string(),
+        quote! {
+            where
+                for<'a> Point: ::mpk::SandboxSafe,
+                for<'a> u8: ::mpk::SandboxSafe
+        }
+        .to_string(),
+    );
+}
+
+#[test]
+fn calls_without_copies_are_unbounded() {
+    let runtime = quote! { ::mpk };
+    assert!(utils::sandbox_safe_bounds(&runtime, &[]).is_empty());
+}
+
+#[test]
+fn batch_calls_the_library_directly() {
+    let call = quote! { f(x) };
+    assert_eq!(
+        utils::sandbox_enter(false, call.clone()).to_string(),
+        quote! { self.__sandbox.call(move || f(x)) }.to_string(),
+    );
+    assert_eq!(
+        utils::sandbox_enter(true, call.clone()).to_string(),
+        call.to_string(),
+    );
+}
+
+#[test]
+fn sandbox_type_with_batch_and_global() {
+    let sandbox = SandboxConfig::new()
+        .sandbox_type("Lib")
+        .global("LIB")
+        .initializer("crate::init");
+    assert_eq!(
+        sandbox_type_tokens(&sandbox).to_string(),
+        quote! {
+            pub struct Lib {
+                __sandbox: mpk::Sandbox,
+            }
+
+            impl Lib {
+                /// Sets up the library in a new sandbox.
+                pub fn new() -> Self {
+                    Self::from_sandbox(mpk::Sandbox::new())
+                }
+
+                /// Sets up the library in `sandbox`.
+                pub fn from_sandbox(sandbox: mpk::Sandbox) -> Self {
+                    let mut this = Lib { __sandbox: sandbox };
+                    crate::init(&mut this);
+                    this
+                }
+
+                pub fn sandbox(&self) -> &mpk::Sandbox {
+                    &self.__sandbox
+                }
+
+                pub fn sandbox_mut(&mut self) -> &mut mpk::Sandbox {
+                    &mut self.__sandbox
+                }
+
+                /// Runs `f` inside the sandbox, so that the library functions it calls
+                /// through the batch share a single transition into the sandbox. `f`
+                /// cannot enter a sandbox again; doing so panics.
+                ///
+                /// # Safety
+                ///
+                /// `f` runs with the sandbox's protection and stack, so it must do no
+                /// more than call the library through the batch and inspect the
+                /// results, and must not reference data outside the sandbox.
+                pub unsafe fn batch<T: 'static>(
+                    &mut self,
+                    f: impl FnOnce(&mut LibBatch<'_>) -> T + 'static,
+                ) -> T {
+                    unsafe {
+                        self.__sandbox.call_batch(move |sandbox| {
+                            f(&mut LibBatch { __sandbox: sandbox })
+                        })
+                    }
+                }
+            }
+
+            impl Default for Lib {
+                fn default() -> Self {
+                    Self::new()
+                }
+            }
+
+            /// Calls the library from inside the sandbox, see `batch`. It has the same
+            /// methods for the library's functions as the sandbox type.
+            pub struct LibBatch<'a> {
+                __sandbox: &'a mpk::Sandbox,
+            }
+
+            pub static LIB: ::std::sync::LazyLock<::std::sync::Mutex<Lib>> =
+                ::std::sync::LazyLock::new(
+                    || ::std::sync::Mutex::new(Lib::new())
+                );
+        }
+        .to_string(),
+    );
+}
+
+#[test]
+fn failed_copies_free_earlier_copies() {
+    let error = quote! { |kind| mpk::SandboxError::new("f", kind) };
+    let frees = vec![quote! { free(first); }, quote! { free(second); }];
+    assert_eq!(
+        utils::sandbox_checked_copy(quote! { check(third) }, &error, &frees)
+            .to_string(),
+        quote! {
+            match check(third) {
+                Ok(copy) => copy,
+                Err(kind) => {
+                    free(first);
+                    free(second);
+                    return Err(
+                        (|kind| mpk::SandboxError::new("f", kind))(kind)
+                    );
+                }
+            }
+        }
+        .to_string(),
+    );
+}
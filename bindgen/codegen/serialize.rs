@@ -10,7 +10,7 @@ use crate::ir::item::ItemCanonicalName;
 use crate::ir::item_kind::ItemKind;
 use crate::ir::ty::{FloatKind, Type, TypeKind};
 
-use super::{CodegenError, WrapAsVariadic};
+use super::{CodegenError, SandboxShim, WrapAsVariadic};
 
 fn get_loc(item: &Item) -> String {
     item.location()
@@ -198,6 +198,191 @@ impl<'a> CSerialize<'a> for Function {
     }
 }
 
+/// Writes the definitions shared by the exception-catching shims of sandboxed
+/// bindings: the global named `record` where they record a caught exception,
+/// with the layout of the runtime's `ExceptionRecord`, the function doing so,
+/// and the value to return after catching an exception.
+///
+/// Sandboxed libraries are usually compiled without the standard include
+/// directories, so the parts of the C++ ABI that are needed are declared here:
+/// `std::exception` if `<exception>` is not available, and the type of the
+/// current exception, whose `type_info` has the Itanium C++ ABI layout.
+pub(super) fn serialize_exception_record<W: Write>(
+    record: &str,
+    writer: &mut W,
+) -> Result<(), CodegenError> {
+    writeln!(
+        writer,
+        r#"#if __has_include(<exception>)
+#include <exception>
+#else
+namespace std {{
+class exception {{
+public:
+    virtual ~exception() noexcept;
+    virtual const char *what() const noexcept;
+}};
+}}
+#endif
+
+struct bindgen_sandbox_exception {{
+    unsigned int caught;
+    char type_name[128];
+    char what[256];
+}};
+
+extern "C" {{ bindgen_sandbox_exception {record}; }}
+
+// The layout of `std::type_info` in the Itanium C++ ABI.
+struct bindgen_sandbox_type_info {{
+    const void *vtable;
+    const char *name;
+}};
+
+extern "C" const bindgen_sandbox_type_info *bindgen_sandbox_exception_type() noexcept
+    __asm__("__cxa_current_exception_type");
+
+static void bindgen_sandbox_copy(char *dst, const char *src, unsigned long size) {{
+    unsigned long i = 0;
+    for (; src && src[i] && i + 1 < size; i++) dst[i] = src[i];
+    dst[i] = 0;
+}}
+
+// Records the exception being handled, so must be called from a handler.
+static void bindgen_sandbox_catch() noexcept {{
+    const bindgen_sandbox_type_info *type = bindgen_sandbox_exception_type();
+    const char *name = type ? type->name : "";
+    // A leading `*` marks type names that are local to a translation unit.
+    if (name[0] == '*') name++;
+    {record}.caught = 1;
+    bindgen_sandbox_copy({record}.type_name, name, sizeof {record}.type_name);
+    try {{
+        throw;
+    }} catch (const std::exception &e) {{
+        bindgen_sandbox_copy({record}.what, e.what(), sizeof {record}.what);
+    }} catch (...) {{
+        {record}.what[0] = 0;
+    }}
+}}
+
+// The value a shim returns after catching an exception, for any return type.
+// It is never used, so references are null and values are zeroed.
+template <typename T> struct bindgen_sandbox_unused {{
+    static T get() noexcept {{
+        union storage {{
+            T value;
+            char bytes[sizeof(T)];
+            storage() noexcept : bytes() {{}}
+            ~storage() {{}}
+        }} ret;
+        return static_cast<T &&>(ret.value);
+    }}
+}};
+
+template <typename T> struct bindgen_sandbox_unused<T &> {{
+    static T &get() noexcept {{ return *static_cast<T *>(nullptr); }}
+}};
+
+template <typename T> struct bindgen_sandbox_unused<T &&> {{
+    static T &&get() noexcept {{
+        return static_cast<T &&>(*static_cast<T *>(nullptr));
+    }}
+}};
+"#
+    )?;
+
+    Ok(())
+}
+
+impl<'a> CSerialize<'a> for SandboxShim {
+    type Extra = ();
+
+    fn serialize<W: Write>(
+        &self,
+        ctx: &BindgenContext,
+        (): Self::Extra,
+        _stack: &mut Vec<String>,
+        writer: &mut W,
+    ) -> Result<(), CodegenError> {
+        let item = ctx.resolve_item(self.function);
+        let function = item.expect_function();
+        let signature = match ctx.resolve_type(function.signature()).kind() {
+            TypeKind::Function(signature) => signature,
+            _ => unreachable!(),
+        };
+
+        // Arguments are renamed, since C++ functions have a `this` argument.
+        let args = signature
+            .argument_types()
+            .iter()
+            .enumerate()
+            .map(|(idx, (_, type_id))| (format!("arg_{}", idx), *type_id))
+            .collect::<Vec<_>>();
+        let ret_item = ctx.resolve_item(signature.return_type());
+        let ret_ty = ret_item.expect_type();
+
+        const INDENT: &str = "    ";
+
+        // Declare the called function as `NAME__sandbox_target`, either as
+        // the target symbol or as the type of the function pointer.
+        let target = format!("{}__sandbox_target", self.name);
+        match self.target {
+            Some(_) => write!(writer, "extern \"C\" ")?,
+            None => write!(writer, "typedef ")?,
+        }
+        ret_ty.serialize(ctx, ret_item, &mut vec![target.clone()], writer)?;
+        write!(writer, "(")?;
+        serialize_args(&args, ctx, writer)?;
+        match self.target {
+            Some(ref symbol) => writeln!(writer, ") __asm__(\"{}\");", symbol)?,
+            None => writeln!(writer, ");")?,
+        }
+
+        // Write `extern "C" RET NAME__sandbox_catch(ARGS) noexcept {`.
+        write!(writer, "extern \"C\" ")?;
+        ret_ty.serialize(ctx, ret_item, &mut vec![self.name.clone()], writer)?;
+        write!(writer, "(")?;
+        let callee = if self.target.is_some() {
+            target
+        } else {
+            write!(writer, "{} *target", target)?;
+            if !args.is_empty() {
+                write!(writer, ", ")?;
+            }
+            "target".to_owned()
+        };
+        if self.target.is_some() || !args.is_empty() {
+            serialize_args(&args, ctx, writer)?;
+        }
+        writeln!(writer, ") noexcept {{")?;
+
+        // Write `try { return callee(args); } catch (...) { ... }`.
+        write!(writer, "{INDENT}try {{ return {}(", callee)?;
+        serialize_sep(", ", args.iter(), ctx, writer, |(name, _), _, buf| {
+            write!(buf, "{}", name).map_err(From::from)
+        })?;
+        writeln!(writer, "); }} catch (...) {{ bindgen_sandbox_catch(); }}")?;
+
+        // The value returned after an exception is never used, so any will
+        // do, but the return type may be a reference or lack a default
+        // constructor.
+        if !ret_ty.is_void() {
+            write!(
+                writer,
+                "{INDENT}return bindgen_sandbox_unused<decltype({}(",
+                callee
+            )?;
+            serialize_sep(", ", args.iter(), ctx, writer, |(name, _), _, buf| {
+                write!(buf, "{}", name).map_err(From::from)
+            })?;
+            writeln!(writer, "))>::get();")?;
+        }
+        writeln!(writer, "}}")?;
+
+        Ok(())
+    }
+}
+
 impl<'a> CSerialize<'a> for TypeId {
     type Extra = ();
 
@@ -440,3 +625,180 @@ fn serialize_sep<
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    use super::serialize_exception_record;
+
+    /// A shim of the form `SandboxShim` serializes, for a function taking an
+    /// `int` and throwing both a standard and a non-standard exception. The
+    /// library provides its own `operator delete`, which the deleting
+    /// destructor of the exception class calls.
+    const SHIM: &str = r#"
+void operator delete(void *, unsigned long) noexcept {}
+
+namespace std {
+class runtime_error : public exception {
+public:
+    const char *what() const noexcept override { return "negative"; }
+};
+}
+
+extern "C" int check__sandbox_target(int arg_0) __asm__("check");
+extern "C" int check__sandbox_catch(int arg_0) noexcept {
+    try { return check__sandbox_target(arg_0); } catch (...) { bindgen_sandbox_catch(); }
+    return bindgen_sandbox_unused<decltype(check__sandbox_target(arg_0))>::get();
+}
+
+extern "C" int check(int x) {
+    if (x < 0) throw std::runtime_error();
+    if (x > 100) throw x;
+    return x;
+}
+"#;
+
+    /// Shims of methods returning a reference, and a class without a default
+    /// constructor, which cannot return a value-initialized placeholder after
+    /// an exception.
+    const METHOD_SHIMS: &str = r#"
+class Buffer {
+public:
+    explicit Buffer(int size) : size(size) {}
+    Buffer &append(int byte);
+    Buffer copy() const;
+private:
+    int size;
+};
+
+extern "C" Buffer &append__sandbox_target(Buffer *arg_0, int arg_1) __asm__("_ZN6Buffer6appendEi");
+extern "C" Buffer &append__sandbox_catch(Buffer *arg_0, int arg_1) noexcept {
+    try { return append__sandbox_target(arg_0, arg_1); } catch (...) { bindgen_sandbox_catch(); }
+    return bindgen_sandbox_unused<decltype(append__sandbox_target(arg_0, arg_1))>::get();
+}
+
+typedef Buffer copy__sandbox_target(const Buffer *arg_0);
+extern "C" Buffer copy__sandbox_catch(copy__sandbox_target *target, const Buffer *arg_0) noexcept {
+    try { return target(arg_0); } catch (...) { bindgen_sandbox_catch(); }
+    return bindgen_sandbox_unused<decltype(target(arg_0))>::get();
+}
+"#;
+
+    fn compile(name: &str, flags: &[&str]) -> (PathBuf, bool) {
+        compile_shims(name, SHIM, flags)
+    }
+
+    fn compile_shims(
+        name: &str,
+        shims: &str,
+        flags: &[&str],
+    ) -> (PathBuf, bool) {
+        let dir = std::env::temp_dir()
+            .join(format!("bindgen-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("shims.cpp");
+        let mut code = Vec::new();
+        serialize_exception_record("record", &mut code).unwrap();
+        code.extend_from_slice(shims.as_bytes());
+        std::fs::write(&source, code).unwrap();
+
+        let obj = dir.join("shims.o");
+        let status = Command::new("c++")
+            .args(flags)
+            .arg("-I")
+            .arg(&dir)
+            .arg("-c")
+            .arg("-o")
+            .arg(&obj)
+            .arg(&source)
+            .status()
+            .unwrap();
+        (obj, status.success())
+    }
+
+    #[test]
+    fn exception_record_compiles_without_standard_headers() {
+        // Like `-nostdlibinc`, which GCC does not support.
+        let (obj, success) =
+            compile("record-nostdinc", &["-nostdinc++", "-nostdinc", "-O2"]);
+        assert!(success);
+
+        // The record only needs the C++ runtime, not the standard library
+        // headers or any of its other symbols.
+        let output =
+            Command::new("nm").arg("-u").arg(&obj).output().unwrap();
+        let undefined = String::from_utf8(output.stdout).unwrap();
+        for symbol in undefined.split_whitespace().filter(|s| *s != "U") {
+            assert!(
+                symbol.starts_with("__cxa_") ||
+                    symbol.starts_with("_ZTI") ||
+                    symbol.starts_with("_ZTV") ||
+                    symbol.starts_with("_ZNSt9exception") ||
+                    symbol == "__gxx_personality_v0" ||
+                    symbol == "_Unwind_Resume",
+                "unexpected import {}",
+                symbol
+            );
+        }
+        assert!(undefined.contains("__cxa_current_exception_type"));
+    }
+
+    #[test]
+    fn exception_record_compiles_with_standard_headers() {
+        let (_, success) = compile("record-stdinc", &["-O2"]);
+        assert!(success);
+    }
+
+    #[test]
+    fn shims_return_references_and_classes() {
+        let (_, success) = compile_shims(
+            "record-methods",
+            METHOD_SHIMS,
+            &["-nostdinc++", "-O2", "-Wall", "-Werror"],
+        );
+        assert!(success);
+    }
+
+    #[test]
+    fn exception_record_records_exceptions() {
+        let (obj, success) =
+            compile("record-run", &["-nostdinc++", "-O2", "-fPIC"]);
+        assert!(success);
+        let main = obj.with_file_name("main.c");
+        std::fs::write(
+            &main,
+            r#"
+            #include <string.h>
+            extern struct { unsigned caught; char type_name[128]; char what[256]; } record;
+            int check__sandbox_catch(int);
+            int main(void) {
+                if (check__sandbox_catch(7) != 7 || record.caught) return 1;
+                check__sandbox_catch(-1);
+                if (!record.caught || strcmp(record.type_name, "St13runtime_error")
+                    || strcmp(record.what, "negative")) return 2;
+                check__sandbox_catch(1000);
+                if (strcmp(record.type_name, "i") || record.what[0]) return 3;
+                return 0;
+            }
+            "#,
+        )
+        .unwrap();
+        let exe = obj.with_file_name("main");
+        let status = Command::new("c++")
+            .arg("-x")
+            .arg("c")
+            .arg(&main)
+            .arg("-x")
+            .arg("none")
+            .arg(&obj)
+            .arg("-lstdc++")
+            .arg("-o")
+            .arg(&exe)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(Command::new(&exe).status().unwrap().code(), Some(0));
+    }
+}
@@ -107,6 +107,25 @@ pub(crate) struct Annotations {
     constify_enum_variant: bool,
     /// List of explicit derives for this type.
     derives: Vec<String>,
+    /// Pointer arguments of a function paired with the argument holding the
+    /// number of elements they point to, and whether that number is in bytes.
+    ///
+    /// This is controlled by the `counted_by` and `sized_by` attributes, this
+    /// way:
+    ///
+    /// ```c
+    /// /** <div rustbindgen counted_by="buf:len"></div> */
+    /// void fill(int *buf, size_t len);
+    /// ```
+    ///
+    /// Sandboxed bindings take such arguments as a single slice.
+    counted_by: Vec<(String, String, bool)>,
+    /// The number of elements a function's returned pointer points to, either
+    /// as the name of an argument or as a literal.
+    return_count: Option<String>,
+    /// Whether a function's returned pointer is never null (`nonnull`) or may
+    /// be null (`nullable`), overriding what its declaration says.
+    returns_nonnull: Option<bool>,
 }
 
 fn parse_accessor(s: &str) -> FieldAccessorKind {
@@ -199,6 +218,22 @@ impl Annotations {
         self.visibility_kind
     }
 
+    /// The pointer arguments paired with the argument counting their elements
+    /// (or bytes, if the flag is set).
+    pub(crate) fn counted_by(&self) -> &[(String, String, bool)] {
+        &self.counted_by
+    }
+
+    /// The number of elements the returned pointer points to.
+    pub(crate) fn return_count(&self) -> Option<&str> {
+        self.return_count.as_deref()
+    }
+
+    /// Whether the returned pointer is never null, if annotated.
+    pub(crate) fn returns_nonnull(&self) -> Option<bool> {
+        self.returns_nonnull
+    }
+
     /// What kind of accessors should we provide for this type's fields?
     pub(crate) fn accessor_kind(&self) -> Option<FieldAccessorKind> {
         self.accessor_kind
@@ -239,6 +274,27 @@ impl Annotations {
                         self.accessor_kind = Some(parse_accessor(&attr.value))
                     }
                     "constant" => self.constify_enum_variant = true,
+                    "counted_by" | "sized_by" => {
+                        let in_bytes = attr.name == "sized_by";
+                        for pair in attr.value.split(',') {
+                            match pair.split_once(':') {
+                                Some((ptr, count)) => self.counted_by.push((
+                                    ptr.trim().to_owned(),
+                                    count.trim().to_owned(),
+                                    in_bytes,
+                                )),
+                                None => warn!(
+                                    "Ignoring malformed `{}` annotation: `{}`",
+                                    attr.name, pair
+                                ),
+                            }
+                        }
+                    }
+                    "nonnull" => self.returns_nonnull = Some(true),
+                    "nullable" => self.returns_nonnull = Some(false),
+                    "return_count" => {
+                        self.return_count = Some(attr.value.trim().to_owned())
+                    }
                     _ => {}
                 }
             }
@@ -1,6 +1,7 @@
 //! Intermediate representation for C/C++ functions and methods.
 
 use super::comp::MethodKind;
+use super::annotations::Annotations;
 use super::context::{BindgenContext, TypeId};
 use super::dot::DotAttributes;
 use super::item::Item;
@@ -281,6 +282,10 @@ pub(crate) struct FunctionSig {
     /// declared.
     argument_types: Vec<(Option<String>, TypeId)>,
 
+    /// Pointer arguments whose length is given by another argument, as
+    /// declared with `counted_by` or `sized_by` attributes.
+    counted_arguments: Vec<CountedArgument>,
+
     /// Whether this function is variadic.
     is_variadic: bool,
     is_divergent: bool,
@@ -288,10 +293,52 @@ pub(crate) struct FunctionSig {
     /// Whether this function's return value must be used.
     must_use: bool,
 
+    /// Whether this function is declared never to return a null pointer,
+    /// with `_Nonnull` or `returns_nonnull`.
+    returns_nonnull: bool,
+
     /// The ABI of this function.
     abi: ClangAbi,
 }
 
+/// A pointer argument that points to as many elements, or bytes, as another
+/// argument of the same function says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CountedArgument {
+    /// The index of the pointer argument.
+    pub(crate) pointer: usize,
+    /// The index of the argument holding the count.
+    pub(crate) count: usize,
+    /// Whether the count is in bytes rather than in elements.
+    pub(crate) in_bytes: bool,
+}
+
+/// Finds a `counted_by(count)` or `sized_by(count)` attribute among the
+/// tokens of a parameter, returning the name of `count` and whether it is in
+/// bytes. The attribute's `__counted_by` style macro spellings are accepted
+/// too, as are the `_or_null` variants.
+fn count_attribute(param: &clang::Cursor) -> Option<(String, bool)> {
+    let tokens: Vec<_> = param.tokens().iter().collect();
+    tokens.windows(4).find_map(|window| {
+        let spelling = |i: usize| {
+            String::from_utf8_lossy(window[i].spelling()).into_owned()
+        };
+        let attribute = spelling(0);
+        let in_bytes = match attribute
+            .trim_matches('_')
+            .trim_end_matches("_or_null")
+        {
+            "counted_by" => false,
+            "sized_by" => true,
+            _ => return None,
+        };
+        if spelling(1) != "(" || spelling(3) != ")" {
+            return None;
+        }
+        Some((spelling(2), in_bytes))
+    })
+}
+
 fn get_abi(cc: CXCallingConv) -> ClangAbi {
     use clang_sys::*;
     match cc {
@@ -553,6 +600,34 @@ impl FunctionSig {
             }
         }
 
+        let counted_arguments = if ctx.options().sandbox.is_some() {
+            let position = |name: &str| {
+                args.iter()
+                    .position(|(arg, _)| arg.as_deref() == Some(name))
+            };
+            let params = cursor.args().unwrap_or_default();
+            params
+                .iter()
+                .filter_map(|param| {
+                    let (count, in_bytes) = count_attribute(param)?;
+                    Some(CountedArgument {
+                        pointer: position(&param.spelling())?,
+                        count: position(&count)?,
+                        in_bytes,
+                    })
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        // Like `noreturn` above, nullability qualifiers are kept in the
+        // spelling of the return type.
+        let returns_nonnull = ctx.options().sandbox.is_some() &&
+            (ty.ret_type()
+                .map_or(false, |ret| ret.spelling().contains("_Nonnull")) ||
+                cursor.has_attrs(&[Attribute::RETURNS_NONNULL])[0]);
+
         let ty_ret_type = if kind == CXCursor_ObjCInstanceMethodDecl ||
             kind == CXCursor_ObjCClassMethodDecl
         {
@@ -592,9 +667,11 @@ impl FunctionSig {
             name: spelling,
             return_type: ret,
             argument_types: args,
+            counted_arguments,
             is_variadic: ty.is_variadic(),
             is_divergent,
             must_use,
+            returns_nonnull,
             abi,
         })
     }
@@ -609,6 +686,45 @@ impl FunctionSig {
         &self.argument_types
     }
 
+    /// Whether this function is declared never to return a null pointer.
+    pub(crate) fn returns_nonnull(&self) -> bool {
+        self.returns_nonnull
+    }
+
+    /// Get the pointer arguments whose length is given by another argument,
+    /// from both clang attributes and the `counted_by` and `sized_by`
+    /// annotations on the function.
+    pub(crate) fn counted_arguments(
+        &self,
+        annotations: &Annotations,
+    ) -> Vec<CountedArgument> {
+        let position = |name: &str| {
+            let position = self
+                .argument_types
+                .iter()
+                .position(|(arg, _)| arg.as_deref() == Some(name));
+            if position.is_none() {
+                warn!("Function `{}` has no argument `{}`", self.name, name);
+            }
+            position
+        };
+
+        let mut counted = self.counted_arguments.clone();
+        for (pointer, count, in_bytes) in annotations.counted_by() {
+            if let (Some(pointer), Some(count)) =
+                (position(pointer), position(count))
+            {
+                counted.retain(|arg| arg.pointer != pointer);
+                counted.push(CountedArgument {
+                    pointer,
+                    count,
+                    in_bytes: *in_bytes,
+                });
+            }
+        }
+        counted
+    }
+
     /// Get this function signature's ABI.
     pub(crate) fn abi(
         &self,
@@ -48,6 +48,7 @@ mod features;
 mod ir;
 mod parse;
 mod regex_set;
+mod sandbox;
 
 pub use codegen::{
     AliasVariation, EnumVariation, MacroTypeVariation, NonCopyUnionStyle,
@@ -58,6 +59,7 @@ pub use features::{RustTarget, LATEST_STABLE_RUST};
 pub use ir::annotations::FieldVisibilityKind;
 pub use ir::function::Abi;
 pub use regex_set::RegexSet;
+pub use sandbox::SandboxConfig;
 
 use codegen::CodegenError;
 use features::RustFeatures;
@@ -1339,6 +1341,55 @@ fn commandline_flag_unit_test_function() {
     assert!(test_cases.iter().all(|x| command_line_flags.contains(x)));
 }
 
+#[test]
+fn commandline_flag_sandbox() {
+    let flags = crate::builder().command_line_flags();
+    assert!(!flags.iter().any(|flag| flag.starts_with("--sandbox")));
+
+    let flags = crate::builder()
+        .sandbox(SandboxConfig::new().sandbox_type("Cmark"))
+        .command_line_flags();
+    let expected = ["--sandbox", "--sandbox-type", "Cmark"];
+    assert!(flags.windows(3).any(|window| window == expected));
+    assert!(!flags.contains(&"--sandbox-runtime".to_owned()));
+
+    let flags = crate::builder()
+        .sandbox(SandboxConfig::new().global("CMARK"))
+        .command_line_flags();
+    let expected = ["--sandbox", "--sandbox-global", "CMARK"];
+    assert!(flags.windows(3).any(|window| window == expected));
+
+    let flags = crate::builder()
+        .sandbox(SandboxConfig::new().nonnull_return("cmark_parse_document"))
+        .command_line_flags();
+    let expected = ["--sandbox-nonnull-return", "cmark_parse_document"];
+    assert!(flags.windows(2).any(|window| window == expected));
+
+    let flags = crate::builder()
+        .sandbox(SandboxConfig::new().owned_string_return("cmark_render_html"))
+        .command_line_flags();
+    let expected = ["--sandbox-owned-string-return", "cmark_render_html"];
+    assert!(flags.windows(2).any(|window| window == expected));
+
+    let flags = crate::builder()
+        .sandbox(SandboxConfig::new().fallible(true))
+        .command_line_flags();
+    assert!(flags.contains(&"--sandbox-fallible".to_owned()));
+
+    let flags = crate::builder()
+        .sandbox(
+            SandboxConfig::new().allocator("sandbox_alloc", "sandbox_free"),
+        )
+        .command_line_flags();
+    let expected = ["--sandbox-allocator", "sandbox_alloc,sandbox_free"];
+    assert!(flags.windows(2).any(|window| window == expected));
+
+    let flags = crate::builder()
+        .sandbox(SandboxConfig::new().catch_exceptions(true))
+        .command_line_flags();
+    assert!(flags.contains(&"--sandbox-catch-exceptions".to_owned()));
+}
+
 #[test]
 fn test_rust_to_clang_target() {
     assert_eq!(
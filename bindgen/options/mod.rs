@@ -12,6 +12,7 @@ use crate::codegen::{
 use crate::deps::DepfileSpec;
 use crate::features::{RustFeatures, RustTarget};
 use crate::regex_set::RegexSet;
+use crate::sandbox::SandboxConfig;
 use crate::Abi;
 use crate::Builder;
 use crate::CodegenConfig;
@@ -1816,6 +1817,26 @@ options! {
         },
         as_args: "--dynamic-link-require-all",
     },
+    /// The configuration of sandboxed bindings, if they are enabled.
+    sandbox: Option<SandboxConfig> {
+        methods: {
+            /// Generate sandboxed bindings, which call functions inside a sandbox through methods
+            /// on the sandbox type and wrap pointers in sandbox pointer types. See
+            /// [`SandboxConfig`] for details.
+            ///
+            /// This option is disabled by default, in which case the usual `extern` declarations
+            /// are generated.
+            pub fn sandbox(mut self, config: SandboxConfig) -> Self {
+                self.options.sandbox = Some(config);
+                self
+            }
+        },
+        as_args: |config, args| {
+            if let Some(config) = config {
+                config.as_args(args);
+            }
+        },
+    },
     /// Whether to only make generated bindings `pub` if the items would be publicly accessible by
     /// C++.
     respect_cxx_access_specs: bool {
@@ -0,0 +1,312 @@
+//! Configuration of sandboxed bindings.
+use std::str::FromStr;
+
+use proc_macro2::{Ident, TokenStream};
+
+/// Configuration for generating sandboxed bindings, enabled with [`Builder::sandbox`].
+///
+/// Sandboxed bindings call each C function inside a sandbox through a safe method on the sandbox
+/// type, instead of declaring it in an `extern` block. Pointer arguments and return values are
+/// wrapped in the runtime's sandbox pointer types, and function pointers in its `SandboxFnPtr`.
+///
+/// Unless the bindings are dynamically loaded, the sandbox type is generated along with them, so
+/// several sets of sandboxed bindings with distinct type names can live in one crate.
+///
+/// [`Builder::sandbox`]: crate::Builder::sandbox
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxConfig {
+    runtime: String,
+    sandbox_type: String,
+    ptr_type: Option<String>,
+    ptr_mut_type: Option<String>,
+    global: Option<String>,
+    initializer: Option<String>,
+    nonnull_returns: Vec<String>,
+    owned_string_returns: Vec<String>,
+    fallible: bool,
+    allocator: Option<(String, String)>,
+    catch_exceptions: bool,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        SandboxConfig {
+            runtime: "mpk".to_owned(),
+            sandbox_type: "Sandboxed".to_owned(),
+            ptr_type: None,
+            ptr_mut_type: None,
+            global: None,
+            initializer: None,
+            nonnull_returns: Vec::new(),
+            owned_string_returns: Vec::new(),
+            fallible: false,
+            allocator: None,
+            catch_exceptions: false,
+        }
+    }
+}
+
+impl SandboxConfig {
+    /// Creates a configuration using the `mpk` runtime and a sandbox type named `Sandboxed`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the path of the runtime crate, which provides `Sandbox`, `Library` and `Imports`.
+    ///
+    /// The default is `mpk`.
+    pub fn runtime<T: Into<String>>(mut self, path: T) -> Self {
+        self.runtime = path.into();
+        self
+    }
+
+    /// Set the name of the generated sandbox type, which the generated methods are implemented on.
+    ///
+    /// The default is `Sandboxed`.
+    pub fn sandbox_type<T: Into<String>>(mut self, name: T) -> Self {
+        self.sandbox_type = name.into();
+        self
+    }
+
+    /// Set the types wrapping `*const T` and `*mut T` pointers into the sandbox, respectively.
+    ///
+    /// The defaults are the runtime's `SandboxPtr` and `SandboxPtrMut`.
+    pub fn pointer_types<T: Into<String>, U: Into<String>>(
+        mut self,
+        ptr: T,
+        ptr_mut: U,
+    ) -> Self {
+        self.ptr_type = Some(ptr.into());
+        self.ptr_mut_type = Some(ptr_mut.into());
+        self
+    }
+
+    /// Also generate a `static` of the given name, holding an instance of the sandbox type behind a
+    /// `Mutex` that is created on first use.
+    pub fn global<T: Into<String>>(mut self, name: T) -> Self {
+        self.global = Some(name.into());
+        self
+    }
+
+    /// Set a function taking `&mut` sandbox type, which the generated constructors call to set up
+    /// the library in a new sandbox before returning it.
+    pub fn initializer<T: Into<String>>(mut self, path: T) -> Self {
+        self.initializer = Some(path.into());
+        self
+    }
+
+    /// Treat the pointer returned by the named function as never null, so that its method returns
+    /// the pointer itself rather than an `Option`. A null pointer then panics.
+    ///
+    /// Pointers are otherwise only assumed non-null if the function is declared with `_Nonnull`,
+    /// `returns_nonnull`, or a `<div rustbindgen nonnull></div>` annotation.
+    pub fn nonnull_return<T: Into<String>>(mut self, function: T) -> Self {
+        self.nonnull_returns.push(function.into());
+        self
+    }
+
+    /// Treat the `char *` returned by the named function as a string that the caller owns, so
+    /// that its method returns the runtime's `SandboxCString`, which frees the string with the
+    /// deallocation function of [`SandboxConfig::allocator`] when it is dropped. This needs an
+    /// allocator, and is ignored for dynamically loaded libraries.
+    pub fn owned_string_return<T: Into<String>>(mut self, function: T) -> Self {
+        self.owned_string_returns.push(function.into());
+        self
+    }
+
+    /// Make the generated methods return a `Result` with the runtime's `SandboxError`, saying
+    /// which check failed in which function, instead of panicking when an argument or return
+    /// value fails a check. [`ParseCallbacks::sandbox_error_style`] can override this per function.
+    ///
+    /// [`ParseCallbacks::sandbox_error_style`]: crate::callbacks::ParseCallbacks::sandbox_error_style
+    pub fn fallible(mut self, fallible: bool) -> Self {
+        self.fallible = fallible;
+        self
+    }
+
+    /// Set the C functions allocating and freeing memory inside the sandbox, with the signatures
+    /// `void *alloc(size_t size)` and `void dealloc(void *ptr)`. Both must be exported by the
+    /// sandboxed library. Like `malloc`, the allocator must return memory aligned to 16 bytes, or
+    /// null if it is exhausted; classes with a greater alignment get no `Class_new` method.
+    ///
+    /// With an allocator, each C++ constructor also gets a `Class_new` method that places the new
+    /// object in sandbox memory, and the destructor a `Class_delete` method that destroys and frees
+    /// such an object. Arguments can also be copied into the sandbox or used as out-parameters, see
+    /// [`SandboxArgumentKind`](crate::callbacks::SandboxArgumentKind).
+    pub fn allocator<T: Into<String>, U: Into<String>>(mut self, alloc: T, dealloc: U) -> Self {
+        self.allocator = Some((alloc.into(), dealloc.into()));
+        self
+    }
+
+    /// Catch C++ exceptions inside the sandbox, so that they do not unwind through the sandbox
+    /// boundary. Each function is then called through a generated C++ shim, written along with
+    /// the wrappers of `wrap_static_fns` and compiled into the sandboxed library, and its method
+    /// returns an exception as an error carrying its type and `what()` text.
+    pub fn catch_exceptions(mut self, catch: bool) -> Self {
+        self.catch_exceptions = catch;
+        self
+    }
+
+    /// Whether C++ exceptions are caught inside the sandbox.
+    pub fn catches_exceptions(&self) -> bool {
+        self.catch_exceptions
+    }
+
+    /// The path of the runtime crate.
+    pub(crate) fn runtime_path(&self) -> TokenStream {
+        parse_path(&self.runtime)
+    }
+
+    /// The name of the sandbox type.
+    pub(crate) fn sandbox_type_ident(&self) -> Ident {
+        parse_ident(&self.sandbox_type)
+    }
+
+    /// The name of the type making a batch of calls inside the sandbox.
+    pub(crate) fn batch_type_ident(&self) -> Ident {
+        parse_ident(&format!("{}Batch", self.sandbox_type))
+    }
+
+    /// The name of the global sandbox instance, if one is generated.
+    pub(crate) fn global_ident(&self) -> Option<Ident> {
+        self.global.as_deref().map(parse_ident)
+    }
+
+    /// The path of the initializer function, if any.
+    pub(crate) fn initializer_path(&self) -> Option<TokenStream> {
+        self.initializer.as_deref().map(parse_path)
+    }
+
+    /// Whether the named function was configured never to return null.
+    pub(crate) fn is_nonnull_return(&self, function: &str) -> bool {
+        self.nonnull_returns.iter().any(|name| name == function)
+    }
+
+    /// Whether the string returned by the named function was configured to be owned by the caller.
+    pub(crate) fn is_owned_string_return(&self, function: &str) -> bool {
+        self.owned_string_returns.iter().any(|name| name == function)
+    }
+
+    /// The names of the allocation and deallocation functions, if set.
+    pub(crate) fn allocator_idents(&self) -> Option<(Ident, Ident)> {
+        self.allocator
+            .as_ref()
+            .map(|(alloc, dealloc)| (parse_ident(alloc), parse_ident(dealloc)))
+    }
+
+    /// Whether the generated methods return a `Result`.
+    pub(crate) fn is_fallible(&self) -> bool {
+        self.fallible
+    }
+
+    /// The error type of fallible methods.
+    pub(crate) fn error_type_path(&self) -> TokenStream {
+        let runtime = self.runtime_path();
+        quote! { #runtime::SandboxError }
+    }
+
+    /// The type saying which check failed in an error.
+    pub(crate) fn error_kind_type_path(&self) -> TokenStream {
+        let runtime = self.runtime_path();
+        quote! { #runtime::SandboxErrorKind }
+    }
+
+    /// The name of the global in the sandboxed library recording the exception a shim caught.
+    pub(crate) fn exception_record_name(&self) -> String {
+        format!("{}__sandbox_exception", self.sandbox_type)
+    }
+
+    /// The wrapper type for `*const T`.
+    pub(crate) fn ptr_type_path(&self) -> TokenStream {
+        match self.ptr_type {
+            Some(ref ty) => parse_path(ty),
+            None => {
+                let runtime = self.runtime_path();
+                quote! { #runtime::SandboxPtr }
+            }
+        }
+    }
+
+    /// The wrapper type for `*mut T`.
+    pub(crate) fn ptr_mut_type_path(&self) -> TokenStream {
+        match self.ptr_mut_type {
+            Some(ref ty) => parse_path(ty),
+            None => {
+                let runtime = self.runtime_path();
+                quote! { #runtime::SandboxPtrMut }
+            }
+        }
+    }
+
+    /// The handle type for pointers to opaque records.
+    pub(crate) fn handle_type_path(&self) -> TokenStream {
+        let runtime = self.runtime_path();
+        quote! { #runtime::SandboxHandle }
+    }
+
+    /// The owned type for strings returned by the sandbox.
+    pub(crate) fn c_string_type_path(&self) -> TokenStream {
+        let runtime = self.runtime_path();
+        quote! { #runtime::SandboxCString }
+    }
+
+    /// The wrapper type for function pointers.
+    pub(crate) fn fn_ptr_type_path(&self) -> TokenStream {
+        let runtime = self.runtime_path();
+        quote! { #runtime::SandboxFnPtr }
+    }
+
+    pub(crate) fn as_args(&self, args: &mut Vec<String>) {
+        let default = Self::default();
+        args.push("--sandbox".to_owned());
+        if self.runtime != default.runtime {
+            args.push("--sandbox-runtime".to_owned());
+            args.push(self.runtime.clone());
+        }
+        if self.sandbox_type != default.sandbox_type {
+            args.push("--sandbox-type".to_owned());
+            args.push(self.sandbox_type.clone());
+        }
+        if let (Some(ptr), Some(ptr_mut)) = (&self.ptr_type, &self.ptr_mut_type)
+        {
+            args.push("--sandbox-pointer-types".to_owned());
+            args.push(format!("{},{}", ptr, ptr_mut));
+        }
+        if let Some(ref global) = self.global {
+            args.push("--sandbox-global".to_owned());
+            args.push(global.clone());
+        }
+        if let Some(ref initializer) = self.initializer {
+            args.push("--sandbox-initializer".to_owned());
+            args.push(initializer.clone());
+        }
+        if self.fallible {
+            args.push("--sandbox-fallible".to_owned());
+        }
+        if let Some((ref alloc, ref dealloc)) = self.allocator {
+            args.push("--sandbox-allocator".to_owned());
+            args.push(format!("{},{}", alloc, dealloc));
+        }
+        if self.catch_exceptions {
+            args.push("--sandbox-catch-exceptions".to_owned());
+        }
+        for function in &self.nonnull_returns {
+            args.push("--sandbox-nonnull-return".to_owned());
+            args.push(function.clone());
+        }
+        for function in &self.owned_string_returns {
+            args.push("--sandbox-owned-string-return".to_owned());
+            args.push(function.clone());
+        }
+    }
+}
+
+fn parse_path(path: &str) -> TokenStream {
+    TokenStream::from_str(path)
+        .unwrap_or_else(|_| panic!("`{}` is not a valid Rust path", path))
+}
+
+fn parse_ident(name: &str) -> Ident {
+    syn::parse_str(name)
+        .unwrap_or_else(|_| panic!("`{}` is not a valid Rust identifier", name))
+}
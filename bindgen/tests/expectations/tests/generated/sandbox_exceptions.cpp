@@ -0,0 +1,89 @@
+#include "tests/headers/sandbox-exceptions.hpp"
+
+// Static wrappers
+
+
+// Exception-catching shims
+
+#if __has_include(<exception>)
+#include <exception>
+#else
+namespace std {
+class exception {
+public:
+    virtual ~exception() noexcept;
+    virtual const char *what() const noexcept;
+};
+}
+#endif
+
+struct bindgen_sandbox_exception {
+    unsigned int caught;
+    char type_name[128];
+    char what[256];
+};
+
+extern "C" { bindgen_sandbox_exception Sandboxed__sandbox_exception; }
+
+// The layout of `std::type_info` in the Itanium C++ ABI.
+struct bindgen_sandbox_type_info {
+    const void *vtable;
+    const char *name;
+};
+
+extern "C" const bindgen_sandbox_type_info *bindgen_sandbox_exception_type() noexcept
+    __asm__("__cxa_current_exception_type");
+
+static void bindgen_sandbox_copy(char *dst, const char *src, unsigned long size) {
+    unsigned long i = 0;
+    for (; src && src[i] && i + 1 < size; i++) dst[i] = src[i];
+    dst[i] = 0;
+}
+
+// Records the exception being handled, so must be called from a handler.
+static void bindgen_sandbox_catch() noexcept {
+    const bindgen_sandbox_type_info *type = bindgen_sandbox_exception_type();
+    const char *name = type ? type->name : "";
+    // A leading `*` marks type names that are local to a translation unit.
+    if (name[0] == '*') name++;
+    Sandboxed__sandbox_exception.caught = 1;
+    bindgen_sandbox_copy(Sandboxed__sandbox_exception.type_name, name, sizeof Sandboxed__sandbox_exception.type_name);
+    try {
+        throw;
+    } catch (const std::exception &e) {
+        bindgen_sandbox_copy(Sandboxed__sandbox_exception.what, e.what(), sizeof Sandboxed__sandbox_exception.what);
+    } catch (...) {
+        Sandboxed__sandbox_exception.what[0] = 0;
+    }
+}
+
+// The value a shim returns after catching an exception, for any return type.
+// It is never used, so references are null and values are zeroed.
+template <typename T> struct bindgen_sandbox_unused {
+    static T get() noexcept {
+        union storage {
+            T value;
+            char bytes[sizeof(T)];
+            storage() noexcept : bytes() {}
+            ~storage() {}
+        } ret;
+        return static_cast<T &&>(ret.value);
+    }
+};
+
+template <typename T> struct bindgen_sandbox_unused<T &> {
+    static T &get() noexcept { return *static_cast<T *>(nullptr); }
+};
+
+template <typename T> struct bindgen_sandbox_unused<T &&> {
+    static T &&get() noexcept {
+        return static_cast<T &&>(*static_cast<T *>(nullptr));
+    }
+};
+
+
+extern "C" int parse__sandbox_catch__sandbox_target(int arg_0) __asm__("_Z5parsei");
+extern "C" int parse__sandbox_catch(int arg_0) noexcept {
+    try { return parse__sandbox_catch__sandbox_target(arg_0); } catch (...) { bindgen_sandbox_catch(); }
+    return bindgen_sandbox_unused<decltype(parse__sandbox_catch__sandbox_target(arg_0))>::get();
+}
@@ -0,0 +1,5 @@
+#include "tests/headers/sandbox-wrap-static-fns.h"
+
+// Static wrappers
+
+int twice__extern(int x) { return twice(x); }
@@ -0,0 +1,111 @@
+/* automatically generated by rust-bindgen 0.69.1 */
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Db {
+    _unused: [u8; 0],
+}
+impl Lib {
+    pub fn db_open(&mut self) -> ::std::option::Option<mpk::SandboxHandle<Db>> {
+        extern "C" {
+            fn db_open() -> *mut Db;
+        }
+        unsafe {
+            mpk::SandboxHandle::new_nullable(
+                self.__sandbox.call(move || db_open()),
+                &self.__sandbox,
+            )
+        }
+    }
+}
+impl LibBatch<'_> {
+    pub fn db_open(&mut self) -> ::std::option::Option<mpk::SandboxHandle<Db>> {
+        extern "C" {
+            fn db_open() -> *mut Db;
+        }
+        unsafe { mpk::SandboxHandle::new_nullable(db_open(), &self.__sandbox) }
+    }
+}
+impl Lib {
+    pub fn db_read(
+        &mut self,
+        db: mpk::SandboxHandle<Db>,
+        out: mpk::SandboxPtrMut<::std::os::raw::c_int>,
+    ) {
+        extern "C" {
+            fn db_read(db: *mut Db, out: *mut ::std::os::raw::c_int);
+        }
+        unsafe {
+            {
+                let db = db.get(&self.__sandbox);
+                let out = out.get();
+                self.__sandbox.call(move || db_read(db, out))
+            }
+        }
+    }
+}
+impl LibBatch<'_> {
+    pub fn db_read(
+        &mut self,
+        db: mpk::SandboxHandle<Db>,
+        out: mpk::SandboxPtrMut<::std::os::raw::c_int>,
+    ) {
+        extern "C" {
+            fn db_read(db: *mut Db, out: *mut ::std::os::raw::c_int);
+        }
+        unsafe {
+            {
+                let db = db.get(&self.__sandbox);
+                let out = out.get();
+                db_read(db, out)
+            }
+        }
+    }
+}
+pub struct Lib {
+    __sandbox: mpk::Sandbox,
+}
+impl Lib {
+    #[doc = " Sets up the library in a new sandbox."]
+    pub fn new() -> Self {
+        Self::from_sandbox(mpk::Sandbox::new())
+    }
+    #[doc = " Sets up the library in `sandbox`."]
+    pub fn from_sandbox(sandbox: mpk::Sandbox) -> Self {
+        Lib { __sandbox: sandbox }
+    }
+    pub fn sandbox(&self) -> &mpk::Sandbox {
+        &self.__sandbox
+    }
+    pub fn sandbox_mut(&mut self) -> &mut mpk::Sandbox {
+        &mut self.__sandbox
+    }
+    #[doc = " Runs `f` inside the sandbox, so that the library functions it calls"]
+    #[doc = " through the batch share a single transition into the sandbox. `f`"]
+    #[doc = " cannot enter a sandbox again; doing so panics."]
+    #[doc = ""]
+    #[doc = " # Safety"]
+    #[doc = ""]
+    #[doc = " `f` runs with the sandbox's protection and stack, so it must do no"]
+    #[doc = " more than call the library through the batch and inspect the"]
+    #[doc = " results, and must not reference data outside the sandbox."]
+    pub unsafe fn batch<T: 'static>(
+        &mut self,
+        f: impl FnOnce(&mut LibBatch<'_>) -> T + 'static,
+    ) -> T {
+        unsafe {
+            self.__sandbox
+                .call_batch(move |sandbox| f(&mut LibBatch { __sandbox: sandbox }))
+        }
+    }
+}
+impl Default for Lib {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[doc = " Calls the library from inside the sandbox, see `batch`. It has the same"]
+#[doc = " methods for the library's functions as the sandbox type."]
+pub struct LibBatch<'a> {
+    __sandbox: &'a mpk::Sandbox,
+}
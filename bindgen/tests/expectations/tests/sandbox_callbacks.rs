@@ -0,0 +1,154 @@
+/* automatically generated by rust-bindgen 0.69.1 */
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Point {
+    pub x: ::std::os::raw::c_int,
+    pub y: ::std::os::raw::c_int,
+}
+#[doc = " Markers for projecting sandbox pointers onto the fields of [`Point`]."]
+#[allow(non_camel_case_types)]
+pub mod Point_fields {
+    pub struct x;
+    pub struct y;
+}
+unsafe impl mpk::Field<Point> for Point_fields::x {
+    type Type = ::std::os::raw::c_int;
+    const OFFSET: usize = 0usize;
+}
+unsafe impl mpk::Field<Point> for Point_fields::y {
+    type Type = ::std::os::raw::c_int;
+    const OFFSET: usize = 4usize;
+}
+impl Sandboxed {
+    pub fn measure(&mut self, point: &Point) -> (::std::os::raw::c_int, ::std::os::raw::c_int)
+    where
+        for<'a> Point: mpk::SandboxSafe,
+        for<'a> ::std::os::raw::c_int: mpk::SandboxSafe,
+    {
+        extern "C" {
+            fn measure(
+                point: *const Point,
+                sum: *mut ::std::os::raw::c_int,
+            ) -> ::std::os::raw::c_int;
+        }
+        unsafe {
+            {
+                extern "C" {
+                    fn sandbox_alloc(size: usize) -> *mut ::std::ffi::c_void;
+                    fn sandbox_free(ptr: *mut ::std::ffi::c_void);
+                }
+                let __bindgen_arg0 = ::std::mem::size_of_val(point).max(1);
+                let __bindgen_arg0 = self.__sandbox.call(move || sandbox_alloc(__bindgen_arg0));
+                let __bindgen_arg0 = mpk::SandboxPtrMut::new(__bindgen_arg0.cast::<Point>());
+                ::std::ptr::copy_nonoverlapping(point, __bindgen_arg0.get(), 1);
+                let point = __bindgen_arg0.get() as _;
+                let __bindgen_arg1 = ::std::mem::size_of::<::std::os::raw::c_int>();
+                let __bindgen_arg1 = self.__sandbox.call(move || sandbox_alloc(__bindgen_arg1));
+                let __bindgen_arg1 =
+                    mpk::SandboxPtrMut::new(__bindgen_arg1.cast::<::std::os::raw::c_int>());
+                ::std::ptr::write_bytes(__bindgen_arg1.get(), 0, 1);
+                let sum = __bindgen_arg1.get();
+                {
+                    let __bindgen_ret = self.__sandbox.call(move || measure(point, sum));
+                    self.__sandbox
+                        .call(move || sandbox_free(__bindgen_arg0.get().cast()));
+                    let sum = ::std::ptr::read(__bindgen_arg1.get());
+                    self.__sandbox
+                        .call(move || sandbox_free(__bindgen_arg1.get().cast()));
+                    (__bindgen_ret, sum)
+                }
+            }
+        }
+    }
+}
+impl SandboxedBatch<'_> {
+    pub fn measure(&mut self, point: &Point) -> (::std::os::raw::c_int, ::std::os::raw::c_int)
+    where
+        for<'a> Point: mpk::SandboxSafe,
+        for<'a> ::std::os::raw::c_int: mpk::SandboxSafe,
+    {
+        extern "C" {
+            fn measure(
+                point: *const Point,
+                sum: *mut ::std::os::raw::c_int,
+            ) -> ::std::os::raw::c_int;
+        }
+        unsafe {
+            {
+                extern "C" {
+                    fn sandbox_alloc(size: usize) -> *mut ::std::ffi::c_void;
+                    fn sandbox_free(ptr: *mut ::std::ffi::c_void);
+                }
+                let __bindgen_arg0 = ::std::mem::size_of_val(point).max(1);
+                let __bindgen_arg0 = sandbox_alloc(__bindgen_arg0);
+                let __bindgen_arg0 = mpk::SandboxPtrMut::new(__bindgen_arg0.cast::<Point>());
+                ::std::ptr::copy_nonoverlapping(point, __bindgen_arg0.get(), 1);
+                let point = __bindgen_arg0.get() as _;
+                let __bindgen_arg1 = ::std::mem::size_of::<::std::os::raw::c_int>();
+                let __bindgen_arg1 = sandbox_alloc(__bindgen_arg1);
+                let __bindgen_arg1 =
+                    mpk::SandboxPtrMut::new(__bindgen_arg1.cast::<::std::os::raw::c_int>());
+                ::std::ptr::write_bytes(__bindgen_arg1.get(), 0, 1);
+                let sum = __bindgen_arg1.get();
+                {
+                    let __bindgen_ret = measure(point, sum);
+                    sandbox_free(__bindgen_arg0.get().cast());
+                    let sum = ::std::ptr::read(__bindgen_arg1.get());
+                    sandbox_free(__bindgen_arg1.get().cast());
+                    (__bindgen_ret, sum)
+                }
+            }
+        }
+    }
+}
+extern "C" {
+    pub fn now() -> ::std::os::raw::c_long;
+}
+pub struct Sandboxed {
+    __sandbox: mpk::Sandbox,
+}
+impl Sandboxed {
+    #[doc = " Sets up the library in a new sandbox."]
+    pub fn new() -> Self {
+        Self::from_sandbox(mpk::Sandbox::new())
+    }
+    #[doc = " Sets up the library in `sandbox`."]
+    pub fn from_sandbox(sandbox: mpk::Sandbox) -> Self {
+        Sandboxed { __sandbox: sandbox }
+    }
+    pub fn sandbox(&self) -> &mpk::Sandbox {
+        &self.__sandbox
+    }
+    pub fn sandbox_mut(&mut self) -> &mut mpk::Sandbox {
+        &mut self.__sandbox
+    }
+    #[doc = " Runs `f` inside the sandbox, so that the library functions it calls"]
+    #[doc = " through the batch share a single transition into the sandbox. `f`"]
+    #[doc = " cannot enter a sandbox again; doing so panics."]
+    #[doc = ""]
+    #[doc = " # Safety"]
+    #[doc = ""]
+    #[doc = " `f` runs with the sandbox's protection and stack, so it must do no"]
+    #[doc = " more than call the library through the batch and inspect the"]
+    #[doc = " results, and must not reference data outside the sandbox."]
+    pub unsafe fn batch<T: 'static>(
+        &mut self,
+        f: impl FnOnce(&mut SandboxedBatch<'_>) -> T + 'static,
+    ) -> T {
+        unsafe {
+            self.__sandbox
+                .call_batch(move |sandbox| f(&mut SandboxedBatch { __sandbox: sandbox }))
+        }
+    }
+}
+impl Default for Sandboxed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[doc = " Calls the library from inside the sandbox, see `batch`. It has the same"]
+#[doc = " methods for the library's functions as the sandbox type."]
+pub struct SandboxedBatch<'a> {
+    __sandbox: &'a mpk::Sandbox,
+}
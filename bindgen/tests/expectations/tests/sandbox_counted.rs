@@ -0,0 +1,100 @@
+/* automatically generated by rust-bindgen 0.69.1 */
+
+impl Sandboxed {
+    pub fn fill(&mut self, buf: mpk::SandboxPtrMut<[::std::os::raw::c_int]>) {
+        extern "C" {
+            fn fill(buf: *mut ::std::os::raw::c_int, len: ::std::os::raw::c_int);
+        }
+        unsafe {
+            {
+                let len =
+                    <::std::os::raw::c_int as ::std::convert::TryFrom<usize>>::try_from(buf.len())
+                        .expect("`buf` is too long for `len`");
+                let buf = buf.as_mut_ptr();
+                self.__sandbox.call(move || fill(buf, len))
+            }
+        }
+    }
+}
+impl SandboxedBatch<'_> {
+    pub fn fill(&mut self, buf: mpk::SandboxPtrMut<[::std::os::raw::c_int]>) {
+        extern "C" {
+            fn fill(buf: *mut ::std::os::raw::c_int, len: ::std::os::raw::c_int);
+        }
+        unsafe {
+            {
+                let len =
+                    <::std::os::raw::c_int as ::std::convert::TryFrom<usize>>::try_from(buf.len())
+                        .expect("`buf` is too long for `len`");
+                let buf = buf.as_mut_ptr();
+                fill(buf, len)
+            }
+        }
+    }
+}
+impl Sandboxed {
+    pub fn table(&mut self) -> ::std::option::Option<mpk::SandboxPtr<[::std::os::raw::c_int]>> {
+        extern "C" {
+            fn table() -> *const ::std::os::raw::c_int;
+        }
+        unsafe {
+            mpk::SandboxPtr::new_nullable(self.__sandbox.call(move || table()))
+                .map(|ptr| ptr.as_slice(4))
+        }
+    }
+}
+impl SandboxedBatch<'_> {
+    pub fn table(&mut self) -> ::std::option::Option<mpk::SandboxPtr<[::std::os::raw::c_int]>> {
+        extern "C" {
+            fn table() -> *const ::std::os::raw::c_int;
+        }
+        unsafe { mpk::SandboxPtr::new_nullable(table()).map(|ptr| ptr.as_slice(4)) }
+    }
+}
+pub struct Sandboxed {
+    __sandbox: mpk::Sandbox,
+}
+impl Sandboxed {
+    #[doc = " Sets up the library in a new sandbox."]
+    pub fn new() -> Self {
+        Self::from_sandbox(mpk::Sandbox::new())
+    }
+    #[doc = " Sets up the library in `sandbox`."]
+    pub fn from_sandbox(sandbox: mpk::Sandbox) -> Self {
+        Sandboxed { __sandbox: sandbox }
+    }
+    pub fn sandbox(&self) -> &mpk::Sandbox {
+        &self.__sandbox
+    }
+    pub fn sandbox_mut(&mut self) -> &mut mpk::Sandbox {
+        &mut self.__sandbox
+    }
+    #[doc = " Runs `f` inside the sandbox, so that the library functions it calls"]
+    #[doc = " through the batch share a single transition into the sandbox. `f`"]
+    #[doc = " cannot enter a sandbox again; doing so panics."]
+    #[doc = ""]
+    #[doc = " # Safety"]
+    #[doc = ""]
+    #[doc = " `f` runs with the sandbox's protection and stack, so it must do no"]
+    #[doc = " more than call the library through the batch and inspect the"]
+    #[doc = " results, and must not reference data outside the sandbox."]
+    pub unsafe fn batch<T: 'static>(
+        &mut self,
+        f: impl FnOnce(&mut SandboxedBatch<'_>) -> T + 'static,
+    ) -> T {
+        unsafe {
+            self.__sandbox
+                .call_batch(move |sandbox| f(&mut SandboxedBatch { __sandbox: sandbox }))
+        }
+    }
+}
+impl Default for Sandboxed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[doc = " Calls the library from inside the sandbox, see `batch`. It has the same"]
+#[doc = " methods for the library's functions as the sandbox type."]
+pub struct SandboxedBatch<'a> {
+    __sandbox: &'a mpk::Sandbox,
+}
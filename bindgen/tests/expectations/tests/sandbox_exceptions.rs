@@ -0,0 +1,99 @@
+/* automatically generated by rust-bindgen 0.69.1 */
+
+impl Sandboxed {
+    pub fn parse(
+        &mut self,
+        value: ::std::os::raw::c_int,
+    ) -> ::std::result::Result<::std::os::raw::c_int, mpk::CppException> {
+        extern "C" {
+            fn parse__sandbox_catch(value: ::std::os::raw::c_int) -> ::std::os::raw::c_int;
+        }
+        unsafe {
+            Ok({
+                extern "C" {
+                    #[allow(non_upper_case_globals)]
+                    static mut Sandboxed__sandbox_exception: mpk::ExceptionRecord;
+                }
+                let ret = self.__sandbox.call(move || parse__sandbox_catch(value));
+                if let Some(exception) = mpk::ExceptionRecord::take(::core::ptr::addr_of_mut!(
+                    Sandboxed__sandbox_exception
+                )) {
+                    return Err(exception);
+                }
+                ret
+            })
+        }
+    }
+}
+impl SandboxedBatch<'_> {
+    pub fn parse(
+        &mut self,
+        value: ::std::os::raw::c_int,
+    ) -> ::std::result::Result<::std::os::raw::c_int, mpk::CppException> {
+        extern "C" {
+            fn parse__sandbox_catch(value: ::std::os::raw::c_int) -> ::std::os::raw::c_int;
+        }
+        unsafe {
+            Ok({
+                extern "C" {
+                    #[allow(non_upper_case_globals)]
+                    static mut Sandboxed__sandbox_exception: mpk::ExceptionRecord;
+                }
+                let ret = parse__sandbox_catch(value);
+                if let Some(exception) = mpk::ExceptionRecord::take(::core::ptr::addr_of_mut!(
+                    Sandboxed__sandbox_exception
+                )) {
+                    return Err(exception);
+                }
+                ret
+            })
+        }
+    }
+}
+pub struct Sandboxed {
+    __sandbox: mpk::Sandbox,
+}
+impl Sandboxed {
+    #[doc = " Sets up the library in a new sandbox."]
+    pub fn new() -> Self {
+        Self::from_sandbox(mpk::Sandbox::new())
+    }
+    #[doc = " Sets up the library in `sandbox`."]
+    pub fn from_sandbox(sandbox: mpk::Sandbox) -> Self {
+        Sandboxed { __sandbox: sandbox }
+    }
+    pub fn sandbox(&self) -> &mpk::Sandbox {
+        &self.__sandbox
+    }
+    pub fn sandbox_mut(&mut self) -> &mut mpk::Sandbox {
+        &mut self.__sandbox
+    }
+    #[doc = " Runs `f` inside the sandbox, so that the library functions it calls"]
+    #[doc = " through the batch share a single transition into the sandbox. `f`"]
+    #[doc = " cannot enter a sandbox again; doing so panics."]
+    #[doc = ""]
+    #[doc = " # Safety"]
+    #[doc = ""]
+    #[doc = " `f` runs with the sandbox's protection and stack, so it must do no"]
+    #[doc = " more than call the library through the batch and inspect the"]
+    #[doc = " results, and must not reference data outside the sandbox."]
+    pub unsafe fn batch<T: 'static>(
+        &mut self,
+        f: impl FnOnce(&mut SandboxedBatch<'_>) -> T + 'static,
+    ) -> T {
+        unsafe {
+            self.__sandbox
+                .call_batch(move |sandbox| f(&mut SandboxedBatch { __sandbox: sandbox }))
+        }
+    }
+}
+impl Default for Sandboxed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[doc = " Calls the library from inside the sandbox, see `batch`. It has the same"]
+#[doc = " methods for the library's functions as the sandbox type."]
+pub struct SandboxedBatch<'a> {
+    __sandbox: &'a mpk::Sandbox,
+}
@@ -0,0 +1,124 @@
+/* automatically generated by rust-bindgen 0.69.1 */
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Db {
+    _unused: [u8; 0],
+}
+impl Sandboxed {
+    pub fn db_count(
+        &mut self,
+        db: mpk::SandboxHandle<Db>,
+    ) -> ::std::result::Result<::std::os::raw::c_int, mpk::SandboxError> {
+        extern "C" {
+            fn db_count(db: *mut Db) -> ::std::os::raw::c_int;
+        }
+        unsafe {
+            {
+                let db = db
+                    .try_get(&self.__sandbox)
+                    .map_err(|kind| mpk::SandboxError::new("db_count", kind))?;
+                Ok(self.__sandbox.call(move || db_count(db)))
+            }
+        }
+    }
+}
+impl SandboxedBatch<'_> {
+    pub fn db_count(
+        &mut self,
+        db: mpk::SandboxHandle<Db>,
+    ) -> ::std::result::Result<::std::os::raw::c_int, mpk::SandboxError> {
+        extern "C" {
+            fn db_count(db: *mut Db) -> ::std::os::raw::c_int;
+        }
+        unsafe {
+            {
+                let db = db
+                    .try_get(&self.__sandbox)
+                    .map_err(|kind| mpk::SandboxError::new("db_count", kind))?;
+                Ok(db_count(db))
+            }
+        }
+    }
+}
+impl Sandboxed {
+    pub fn find(
+        &mut self,
+        key: ::std::os::raw::c_int,
+    ) -> ::std::result::Result<
+        ::std::option::Option<mpk::SandboxPtrMut<::std::os::raw::c_int>>,
+        mpk::SandboxError,
+    > {
+        extern "C" {
+            fn find(key: ::std::os::raw::c_int) -> *mut ::std::os::raw::c_int;
+        }
+        unsafe {
+            mpk::SandboxPtrMut::try_new_nullable(self.__sandbox.call(move || find(key)))
+                .map_err(|kind| mpk::SandboxError::new("find", kind))
+        }
+    }
+}
+impl SandboxedBatch<'_> {
+    pub fn find(
+        &mut self,
+        key: ::std::os::raw::c_int,
+    ) -> ::std::result::Result<
+        ::std::option::Option<mpk::SandboxPtrMut<::std::os::raw::c_int>>,
+        mpk::SandboxError,
+    > {
+        extern "C" {
+            fn find(key: ::std::os::raw::c_int) -> *mut ::std::os::raw::c_int;
+        }
+        unsafe {
+            mpk::SandboxPtrMut::try_new_nullable(find(key))
+                .map_err(|kind| mpk::SandboxError::new("find", kind))
+        }
+    }
+}
+pub struct Sandboxed {
+    __sandbox: mpk::Sandbox,
+}
+impl Sandboxed {
+    #[doc = " Sets up the library in a new sandbox."]
+    pub fn new() -> Self {
+        Self::from_sandbox(mpk::Sandbox::new())
+    }
+    #[doc = " Sets up the library in `sandbox`."]
+    pub fn from_sandbox(sandbox: mpk::Sandbox) -> Self {
+        Sandboxed { __sandbox: sandbox }
+    }
+    pub fn sandbox(&self) -> &mpk::Sandbox {
+        &self.__sandbox
+    }
+    pub fn sandbox_mut(&mut self) -> &mut mpk::Sandbox {
+        &mut self.__sandbox
+    }
+    #[doc = " Runs `f` inside the sandbox, so that the library functions it calls"]
+    #[doc = " through the batch share a single transition into the sandbox. `f`"]
+    #[doc = " cannot enter a sandbox again; doing so panics."]
+    #[doc = ""]
+    #[doc = " # Safety"]
+    #[doc = ""]
+    #[doc = " `f` runs with the sandbox's protection and stack, so it must do no"]
+    #[doc = " more than call the library through the batch and inspect the"]
+    #[doc = " results, and must not reference data outside the sandbox."]
+    pub unsafe fn batch<T: 'static>(
+        &mut self,
+        f: impl FnOnce(&mut SandboxedBatch<'_>) -> T + 'static,
+    ) -> T {
+        unsafe {
+            self.__sandbox
+                .call_batch(move |sandbox| f(&mut SandboxedBatch { __sandbox: sandbox }))
+        }
+    }
+}
+impl Default for Sandboxed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[doc = " Calls the library from inside the sandbox, see `batch`. It has the same"]
+#[doc = " methods for the library's functions as the sandbox type."]
+pub struct SandboxedBatch<'a> {
+    __sandbox: &'a mpk::Sandbox,
+}
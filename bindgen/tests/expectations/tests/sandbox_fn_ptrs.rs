@@ -0,0 +1,125 @@
+/* automatically generated by rust-bindgen 0.69.1 */
+
+pub type compare_fn = ::std::option::Option<
+    mpk::SandboxFnPtr<
+        unsafe extern "C" fn(
+            a: ::std::os::raw::c_int,
+            b: ::std::os::raw::c_int,
+        ) -> ::std::os::raw::c_int,
+    >,
+>;
+impl Sandboxed {
+    pub fn apply(
+        &mut self,
+        cmp: compare_fn,
+        a: ::std::os::raw::c_int,
+        b: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int {
+        extern "C" {
+            fn apply(
+                cmp: compare_fn,
+                a: ::std::os::raw::c_int,
+                b: ::std::os::raw::c_int,
+            ) -> ::std::os::raw::c_int;
+        }
+        unsafe { self.__sandbox.call(move || apply(cmp, a, b)) }
+    }
+}
+impl SandboxedBatch<'_> {
+    pub fn apply(
+        &mut self,
+        cmp: compare_fn,
+        a: ::std::os::raw::c_int,
+        b: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int {
+        extern "C" {
+            fn apply(
+                cmp: compare_fn,
+                a: ::std::os::raw::c_int,
+                b: ::std::os::raw::c_int,
+            ) -> ::std::os::raw::c_int;
+        }
+        unsafe { apply(cmp, a, b) }
+    }
+}
+impl Sandboxed {
+    pub fn on_event(
+        &mut self,
+        handler: ::std::option::Option<
+            mpk::SandboxFnPtr<unsafe extern "C" fn(code: ::std::os::raw::c_int)>,
+        >,
+    ) {
+        extern "C" {
+            fn on_event(
+                handler: ::std::option::Option<
+                    mpk::SandboxFnPtr<unsafe extern "C" fn(code: ::std::os::raw::c_int)>,
+                >,
+            );
+        }
+        unsafe { self.__sandbox.call(move || on_event(handler)) }
+    }
+}
+impl SandboxedBatch<'_> {
+    pub fn on_event(
+        &mut self,
+        handler: ::std::option::Option<
+            mpk::SandboxFnPtr<unsafe extern "C" fn(code: ::std::os::raw::c_int)>,
+        >,
+    ) {
+        extern "C" {
+            fn on_event(
+                handler: ::std::option::Option<
+                    mpk::SandboxFnPtr<unsafe extern "C" fn(code: ::std::os::raw::c_int)>,
+                >,
+            );
+        }
+        unsafe { on_event(handler) }
+    }
+}
+pub struct Sandboxed {
+    __sandbox: mpk::Sandbox,
+}
+impl Sandboxed {
+    #[doc = " Sets up the library in a new sandbox."]
+    pub fn new() -> Self {
+        Self::from_sandbox(mpk::Sandbox::new())
+    }
+    #[doc = " Sets up the library in `sandbox`."]
+    pub fn from_sandbox(sandbox: mpk::Sandbox) -> Self {
+        Sandboxed { __sandbox: sandbox }
+    }
+    pub fn sandbox(&self) -> &mpk::Sandbox {
+        &self.__sandbox
+    }
+    pub fn sandbox_mut(&mut self) -> &mut mpk::Sandbox {
+        &mut self.__sandbox
+    }
+    #[doc = " Runs `f` inside the sandbox, so that the library functions it calls"]
+    #[doc = " through the batch share a single transition into the sandbox. `f`"]
+    #[doc = " cannot enter a sandbox again; doing so panics."]
+    #[doc = ""]
+    #[doc = " # Safety"]
+    #[doc = ""]
+    #[doc = " `f` runs with the sandbox's protection and stack, so it must do no"]
+    #[doc = " more than call the library through the batch and inspect the"]
+    #[doc = " results, and must not reference data outside the sandbox."]
+    pub unsafe fn batch<T: 'static>(
+        &mut self,
+        f: impl FnOnce(&mut SandboxedBatch<'_>) -> T + 'static,
+    ) -> T {
+        unsafe {
+            self.__sandbox
+                .call_batch(move |sandbox| f(&mut SandboxedBatch { __sandbox: sandbox }))
+        }
+    }
+}
+impl Default for Sandboxed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[doc = " Calls the library from inside the sandbox, see `batch`. It has the same"]
+#[doc = " methods for the library's functions as the sandbox type."]
+pub struct SandboxedBatch<'a> {
+    __sandbox: &'a mpk::Sandbox,
+}
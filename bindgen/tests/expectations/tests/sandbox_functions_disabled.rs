@@ -0,0 +1,11 @@
+/* automatically generated by rust-bindgen 0.69.1 */
+
+extern "C" {
+    pub fn add(a: ::std::os::raw::c_int, b: ::std::os::raw::c_int) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn fill(out: *mut ::std::os::raw::c_int, value: ::std::os::raw::c_int);
+}
+extern "C" {
+    pub fn reset();
+}
@@ -0,0 +1,87 @@
+/* automatically generated by rust-bindgen 0.69.1 */
+
+impl Sandboxed {
+    pub fn counter_ptr(&self) -> mpk::SandboxPtrMut<::std::os::raw::c_int> {
+        extern "C" {
+            static mut counter: ::std::os::raw::c_int;
+        }
+        #[allow(unused_unsafe)]
+        let ptr = unsafe { ::std::ptr::addr_of_mut!(counter) };
+        mpk::SandboxPtrMut::new(ptr)
+    }
+    pub fn counter(&self) -> &::std::os::raw::c_int
+    where
+        for<'a> ::std::os::raw::c_int: mpk::SandboxSafe,
+    {
+        self.counter_ptr().as_ref(&self.__sandbox)
+    }
+    pub fn counter_mut(&mut self) -> &mut ::std::os::raw::c_int
+    where
+        for<'a> ::std::os::raw::c_int: mpk::SandboxSafe,
+    {
+        self.counter_ptr().as_mut(&mut self.__sandbox)
+    }
+}
+impl Sandboxed {
+    pub fn limit_ptr(&self) -> mpk::SandboxPtr<::std::os::raw::c_int> {
+        extern "C" {
+            static limit: ::std::os::raw::c_int;
+        }
+        #[allow(unused_unsafe)]
+        let ptr = unsafe { ::std::ptr::addr_of!(limit) };
+        mpk::SandboxPtr::new(ptr)
+    }
+    pub fn limit(&self) -> &::std::os::raw::c_int
+    where
+        for<'a> ::std::os::raw::c_int: mpk::SandboxSafe,
+    {
+        self.limit_ptr().as_ref(&self.__sandbox)
+    }
+}
+pub struct Sandboxed {
+    __sandbox: mpk::Sandbox,
+}
+impl Sandboxed {
+    #[doc = " Sets up the library in a new sandbox."]
+    pub fn new() -> Self {
+        Self::from_sandbox(mpk::Sandbox::new())
+    }
+    #[doc = " Sets up the library in `sandbox`."]
+    pub fn from_sandbox(sandbox: mpk::Sandbox) -> Self {
+        Sandboxed { __sandbox: sandbox }
+    }
+    pub fn sandbox(&self) -> &mpk::Sandbox {
+        &self.__sandbox
+    }
+    pub fn sandbox_mut(&mut self) -> &mut mpk::Sandbox {
+        &mut self.__sandbox
+    }
+    #[doc = " Runs `f` inside the sandbox, so that the library functions it calls"]
+    #[doc = " through the batch share a single transition into the sandbox. `f`"]
+    #[doc = " cannot enter a sandbox again; doing so panics."]
+    #[doc = ""]
+    #[doc = " # Safety"]
+    #[doc = ""]
+    #[doc = " `f` runs with the sandbox's protection and stack, so it must do no"]
+    #[doc = " more than call the library through the batch and inspect the"]
+    #[doc = " results, and must not reference data outside the sandbox."]
+    pub unsafe fn batch<T: 'static>(
+        &mut self,
+        f: impl FnOnce(&mut SandboxedBatch<'_>) -> T + 'static,
+    ) -> T {
+        unsafe {
+            self.__sandbox
+                .call_batch(move |sandbox| f(&mut SandboxedBatch { __sandbox: sandbox }))
+        }
+    }
+}
+impl Default for Sandboxed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[doc = " Calls the library from inside the sandbox, see `batch`. It has the same"]
+#[doc = " methods for the library's functions as the sandbox type."]
+pub struct SandboxedBatch<'a> {
+    __sandbox: &'a mpk::Sandbox,
+}
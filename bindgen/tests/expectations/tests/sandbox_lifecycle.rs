@@ -0,0 +1,166 @@
+/* automatically generated by rust-bindgen 0.69.1 */
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct Counter {
+    pub value: ::std::os::raw::c_int,
+}
+#[doc = " Markers for projecting sandbox pointers onto the fields of [`Counter`]."]
+#[allow(non_camel_case_types)]
+pub mod Counter_fields {
+    pub struct value;
+}
+unsafe impl mpk::Field<Counter> for Counter_fields::value {
+    type Type = ::std::os::raw::c_int;
+    const OFFSET: usize = 0usize;
+}
+impl Sandboxed {
+    pub fn Counter_Counter(
+        &mut self,
+        this: mpk::SandboxPtrMut<Counter>,
+        start: ::std::os::raw::c_int,
+    ) {
+        extern "C" {
+            #[link_name = "\u{1}_ZN7CounterC1Ei"]
+            fn Counter_Counter(this: *mut Counter, start: ::std::os::raw::c_int);
+        }
+        unsafe {
+            {
+                let this = this.get();
+                self.__sandbox.call(move || Counter_Counter(this, start))
+            }
+        }
+    }
+}
+impl SandboxedBatch<'_> {
+    pub fn Counter_Counter(
+        &mut self,
+        this: mpk::SandboxPtrMut<Counter>,
+        start: ::std::os::raw::c_int,
+    ) {
+        extern "C" {
+            #[link_name = "\u{1}_ZN7CounterC1Ei"]
+            fn Counter_Counter(this: *mut Counter, start: ::std::os::raw::c_int);
+        }
+        unsafe {
+            {
+                let this = this.get();
+                Counter_Counter(this, start)
+            }
+        }
+    }
+}
+impl Sandboxed {
+    #[doc = " Allocates an object in the sandbox and constructs it."]
+    pub fn Counter_new(&mut self, start: ::std::os::raw::c_int) -> mpk::SandboxPtrMut<Counter> {
+        extern "C" {
+            fn sandbox_alloc(size: usize) -> *mut ::std::ffi::c_void;
+            fn sandbox_free(ptr: *mut ::std::ffi::c_void);
+        }
+        const _: () = assert!(
+            ::std::mem::align_of::<Counter>() <= 16,
+            "the class is over-aligned for the sandbox allocator"
+        );
+        let size = ::std::mem::size_of::<Counter>();
+        let raw = unsafe { self.__sandbox.call(move || sandbox_alloc(size)) };
+        if raw.is_null() {
+            panic!("could not allocate `Counter` in the sandbox");
+        }
+        let this = match mpk::SandboxPtrMut::try_new(raw.cast::<Counter>()) {
+            Ok(this) => this,
+            Err(kind) => {
+                unsafe { self.__sandbox.call(move || sandbox_free(raw)) };
+                panic!("{}", kind);
+            }
+        };
+        self.Counter_Counter(this, start);
+        this
+    }
+}
+impl Sandboxed {
+    pub fn Counter_Counter_destructor(&mut self, this: mpk::SandboxPtrMut<Counter>) {
+        extern "C" {
+            #[link_name = "\u{1}_ZN7CounterD1Ev"]
+            fn Counter_Counter_destructor(this: *mut Counter);
+        }
+        unsafe {
+            {
+                let this = this.get();
+                self.__sandbox
+                    .call(move || Counter_Counter_destructor(this))
+            }
+        }
+    }
+}
+impl SandboxedBatch<'_> {
+    pub fn Counter_Counter_destructor(&mut self, this: mpk::SandboxPtrMut<Counter>) {
+        extern "C" {
+            #[link_name = "\u{1}_ZN7CounterD1Ev"]
+            fn Counter_Counter_destructor(this: *mut Counter);
+        }
+        unsafe {
+            {
+                let this = this.get();
+                Counter_Counter_destructor(this)
+            }
+        }
+    }
+}
+impl Sandboxed {
+    #[doc = " Destroys an object allocated in the sandbox and frees its memory."]
+    pub fn Counter_delete(&mut self, this: mpk::SandboxPtrMut<Counter>) {
+        extern "C" {
+            fn sandbox_free(ptr: *mut ::std::ffi::c_void);
+        }
+        self.Counter_Counter_destructor(this);
+        let this = this.get().cast::<::std::ffi::c_void>();
+        unsafe { self.__sandbox.call(move || sandbox_free(this)) }
+    }
+}
+pub struct Sandboxed {
+    __sandbox: mpk::Sandbox,
+}
+impl Sandboxed {
+    #[doc = " Sets up the library in a new sandbox."]
+    pub fn new() -> Self {
+        Self::from_sandbox(mpk::Sandbox::new())
+    }
+    #[doc = " Sets up the library in `sandbox`."]
+    pub fn from_sandbox(sandbox: mpk::Sandbox) -> Self {
+        Sandboxed { __sandbox: sandbox }
+    }
+    pub fn sandbox(&self) -> &mpk::Sandbox {
+        &self.__sandbox
+    }
+    pub fn sandbox_mut(&mut self) -> &mut mpk::Sandbox {
+        &mut self.__sandbox
+    }
+    #[doc = " Runs `f` inside the sandbox, so that the library functions it calls"]
+    #[doc = " through the batch share a single transition into the sandbox. `f`"]
+    #[doc = " cannot enter a sandbox again; doing so panics."]
+    #[doc = ""]
+    #[doc = " # Safety"]
+    #[doc = ""]
+    #[doc = " `f` runs with the sandbox's protection and stack, so it must do no"]
+    #[doc = " more than call the library through the batch and inspect the"]
+    #[doc = " results, and must not reference data outside the sandbox."]
+    pub unsafe fn batch<T: 'static>(
+        &mut self,
+        f: impl FnOnce(&mut SandboxedBatch<'_>) -> T + 'static,
+    ) -> T {
+        unsafe {
+            self.__sandbox
+                .call_batch(move |sandbox| f(&mut SandboxedBatch { __sandbox: sandbox }))
+        }
+    }
+}
+impl Default for Sandboxed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[doc = " Calls the library from inside the sandbox, see `batch`. It has the same"]
+#[doc = " methods for the library's functions as the sandbox type."]
+pub struct SandboxedBatch<'a> {
+    __sandbox: &'a mpk::Sandbox,
+}
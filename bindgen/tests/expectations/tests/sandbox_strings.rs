@@ -0,0 +1,127 @@
+/* automatically generated by rust-bindgen 0.69.1 */
+
+impl Sandboxed {
+    pub fn describe(
+        &mut self,
+        id: ::std::os::raw::c_int,
+    ) -> ::std::option::Option<mpk::SandboxCString> {
+        extern "C" {
+            fn describe(id: ::std::os::raw::c_int) -> *mut ::std::os::raw::c_char;
+        }
+        unsafe {
+            {
+                extern "C" {
+                    fn sandbox_free(ptr: *mut ::std::ffi::c_void);
+                }
+                mpk::SandboxCString::new_nullable(
+                    self.__sandbox.call(move || describe(id)) as *mut _,
+                    sandbox_free,
+                    &self.__sandbox,
+                )
+            }
+        }
+    }
+}
+impl SandboxedBatch<'_> {
+    pub fn describe(
+        &mut self,
+        id: ::std::os::raw::c_int,
+    ) -> ::std::option::Option<mpk::SandboxCString> {
+        extern "C" {
+            fn describe(id: ::std::os::raw::c_int) -> *mut ::std::os::raw::c_char;
+        }
+        unsafe {
+            {
+                extern "C" {
+                    fn sandbox_free(ptr: *mut ::std::ffi::c_void);
+                }
+                mpk::SandboxCString::new_nullable(
+                    describe(id) as *mut _,
+                    sandbox_free,
+                    &self.__sandbox,
+                )
+            }
+        }
+    }
+}
+impl Sandboxed {
+    pub fn name_of(&mut self, id: ::std::os::raw::c_int) -> mpk::SandboxCString {
+        extern "C" {
+            fn name_of(id: ::std::os::raw::c_int) -> *mut ::std::os::raw::c_char;
+        }
+        unsafe {
+            {
+                extern "C" {
+                    fn sandbox_free(ptr: *mut ::std::ffi::c_void);
+                }
+                mpk::SandboxCString::new(
+                    self.__sandbox.call(move || name_of(id)) as *mut _,
+                    sandbox_free,
+                    &self.__sandbox,
+                )
+            }
+        }
+    }
+}
+impl SandboxedBatch<'_> {
+    pub fn name_of(&mut self, id: ::std::os::raw::c_int) -> mpk::SandboxCString {
+        extern "C" {
+            fn name_of(id: ::std::os::raw::c_int) -> *mut ::std::os::raw::c_char;
+        }
+        unsafe {
+            {
+                extern "C" {
+                    fn sandbox_free(ptr: *mut ::std::ffi::c_void);
+                }
+                mpk::SandboxCString::new(name_of(id) as *mut _, sandbox_free, &self.__sandbox)
+            }
+        }
+    }
+}
+pub struct Sandboxed {
+    __sandbox: mpk::Sandbox,
+}
+impl Sandboxed {
+    #[doc = " Sets up the library in a new sandbox."]
+    pub fn new() -> Self {
+        Self::from_sandbox(mpk::Sandbox::new())
+    }
+    #[doc = " Sets up the library in `sandbox`."]
+    pub fn from_sandbox(sandbox: mpk::Sandbox) -> Self {
+        Sandboxed { __sandbox: sandbox }
+    }
+    pub fn sandbox(&self) -> &mpk::Sandbox {
+        &self.__sandbox
+    }
+    pub fn sandbox_mut(&mut self) -> &mut mpk::Sandbox {
+        &mut self.__sandbox
+    }
+    #[doc = " Runs `f` inside the sandbox, so that the library functions it calls"]
+    #[doc = " through the batch share a single transition into the sandbox. `f`"]
+    #[doc = " cannot enter a sandbox again; doing so panics."]
+    #[doc = ""]
+    #[doc = " # Safety"]
+    #[doc = ""]
+    #[doc = " `f` runs with the sandbox's protection and stack, so it must do no"]
+    #[doc = " more than call the library through the batch and inspect the"]
+    #[doc = " results, and must not reference data outside the sandbox."]
+    pub unsafe fn batch<T: 'static>(
+        &mut self,
+        f: impl FnOnce(&mut SandboxedBatch<'_>) -> T + 'static,
+    ) -> T {
+        unsafe {
+            self.__sandbox
+                .call_batch(move |sandbox| f(&mut SandboxedBatch { __sandbox: sandbox }))
+        }
+    }
+}
+impl Default for Sandboxed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[doc = " Calls the library from inside the sandbox, see `batch`. It has the same"]
+#[doc = " methods for the library's functions as the sandbox type."]
+pub struct SandboxedBatch<'a> {
+    __sandbox: &'a mpk::Sandbox,
+}
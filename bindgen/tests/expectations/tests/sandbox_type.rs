@@ -0,0 +1,69 @@
+/* automatically generated by rust-bindgen 0.69.1 */
+
+impl Lib {
+    pub fn version(&mut self) -> ::std::os::raw::c_int {
+        extern "C" {
+            fn version() -> ::std::os::raw::c_int;
+        }
+        unsafe { self.__sandbox.call(move || version()) }
+    }
+}
+impl LibBatch<'_> {
+    pub fn version(&mut self) -> ::std::os::raw::c_int {
+        extern "C" {
+            fn version() -> ::std::os::raw::c_int;
+        }
+        unsafe { version() }
+    }
+}
+pub struct Lib {
+    __sandbox: mpk::Sandbox,
+}
+impl Lib {
+    #[doc = " Sets up the library in a new sandbox."]
+    pub fn new() -> Self {
+        Self::from_sandbox(mpk::Sandbox::new())
+    }
+    #[doc = " Sets up the library in `sandbox`."]
+    pub fn from_sandbox(sandbox: mpk::Sandbox) -> Self {
+        let mut this = Lib { __sandbox: sandbox };
+        crate::init(&mut this);
+        this
+    }
+    pub fn sandbox(&self) -> &mpk::Sandbox {
+        &self.__sandbox
+    }
+    pub fn sandbox_mut(&mut self) -> &mut mpk::Sandbox {
+        &mut self.__sandbox
+    }
+    #[doc = " Runs `f` inside the sandbox, so that the library functions it calls"]
+    #[doc = " through the batch share a single transition into the sandbox. `f`"]
+    #[doc = " cannot enter a sandbox again; doing so panics."]
+    #[doc = ""]
+    #[doc = " # Safety"]
+    #[doc = ""]
+    #[doc = " `f` runs with the sandbox's protection and stack, so it must do no"]
+    #[doc = " more than call the library through the batch and inspect the"]
+    #[doc = " results, and must not reference data outside the sandbox."]
+    pub unsafe fn batch<T: 'static>(
+        &mut self,
+        f: impl FnOnce(&mut LibBatch<'_>) -> T + 'static,
+    ) -> T {
+        unsafe {
+            self.__sandbox
+                .call_batch(move |sandbox| f(&mut LibBatch { __sandbox: sandbox }))
+        }
+    }
+}
+impl Default for Lib {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[doc = " Calls the library from inside the sandbox, see `batch`. It has the same"]
+#[doc = " methods for the library's functions as the sandbox type."]
+pub struct LibBatch<'a> {
+    __sandbox: &'a mpk::Sandbox,
+}
+pub static LIB: ::std::sync::LazyLock<::std::sync::Mutex<Lib>> =
+    ::std::sync::LazyLock::new(|| ::std::sync::Mutex::new(Lib::new()));
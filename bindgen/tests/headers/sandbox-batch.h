@@ -0,0 +1,4 @@
+struct Db;
+
+struct Db *db_open(void);
+void db_read(struct Db *db, int *out);
@@ -0,0 +1,7 @@
+struct Point {
+    int x;
+    int y;
+};
+
+int measure(const struct Point *point, int *sum);
+long now(void);
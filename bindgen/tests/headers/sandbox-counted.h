@@ -0,0 +1,5 @@
+/** <div rustbindgen counted_by="buf:len"></div> */
+void fill(int *buf, int len);
+
+/** <div rustbindgen return_count="4"></div> */
+const int *table(void);
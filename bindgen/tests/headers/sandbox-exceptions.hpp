@@ -0,0 +1 @@
+int parse(int value);
@@ -0,0 +1,4 @@
+struct Db;
+
+int db_count(struct Db *db);
+int *find(int key);
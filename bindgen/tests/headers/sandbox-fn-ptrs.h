@@ -0,0 +1,4 @@
+typedef int (*compare_fn)(int a, int b);
+
+int apply(compare_fn cmp, int a, int b);
+void on_event(void (*handler)(int code));
@@ -0,0 +1,3 @@
+int add(int a, int b);
+void fill(int *out, int value);
+void reset(void);
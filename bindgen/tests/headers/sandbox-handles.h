@@ -0,0 +1,4 @@
+struct Db;
+
+struct Db *db_open(void);
+int db_count(struct Db *db);
@@ -0,0 +1,7 @@
+class Counter {
+public:
+    Counter(int start);
+    ~Counter();
+
+    int value;
+};
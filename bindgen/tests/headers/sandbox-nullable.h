@@ -0,0 +1,5 @@
+int *find(int key);
+int *first(void);
+
+/** <div rustbindgen nonnull></div> */
+int *last(void);
@@ -0,0 +1,2 @@
+char *describe(int id);
+char *name_of(int id);
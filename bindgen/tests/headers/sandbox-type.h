@@ -0,0 +1 @@
+int version(void);
@@ -0,0 +1,2 @@
+int log_msg(const char *fmt, ...);
+int log_level(void);
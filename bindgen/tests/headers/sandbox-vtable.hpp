@@ -0,0 +1,4 @@
+class Shape {
+public:
+    virtual int area();
+};
@@ -0,0 +1,270 @@
+//! Generates bindings for the headers in `tests/headers` and compares them
+//! with the expectations in `tests/expectations/tests`, ignoring formatting.
+//! Set `BINDGEN_OVERWRITE_EXPECTED` to update the expectations instead.
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use bindgen::callbacks::{
+    ParseCallbacks, SandboxArgumentInfo, SandboxArgumentKind,
+};
+use bindgen::{Builder, SandboxConfig};
+use quote::ToTokens;
+
+/// Generates bindings for `header` with the options `configure` sets, and
+/// compares them with the expectation `expected`. Layout tests and comments
+/// are left out to keep the expectations short.
+fn check(
+    header: &str,
+    expected: &str,
+    configure: impl FnOnce(Builder) -> Builder,
+) {
+    let builder = bindgen::builder()
+        .header(format!("tests/headers/{}", header))
+        .layout_tests(false)
+        .generate_comments(false);
+    let actual = configure(builder)
+        .generate()
+        .unwrap_or_else(|_| {
+            panic!("could not generate bindings for `{}`", header)
+        })
+        .to_string();
+
+    let expected = Path::new("tests/expectations/tests").join(expected);
+    if env::var_os("BINDGEN_OVERWRITE_EXPECTED").is_some() {
+        fs::write(&expected, actual).unwrap();
+        return;
+    }
+    let expected_source =
+        fs::read_to_string(&expected).unwrap_or_else(|err| {
+            panic!("could not read {}: {}", expected.display(), err)
+        });
+    assert!(
+        tokens(&actual) == tokens(&expected_source),
+        "the bindings for `{}` differ from {}:\n{}",
+        header,
+        expected.display(),
+        actual
+    );
+}
+
+/// Compares the source file bindgen wrote to `generated` with the
+/// expectation of the same name in `tests/expectations/tests/generated`.
+#[cfg(feature = "experimental")]
+fn check_generated(generated: &Path) {
+    let expected = Path::new("tests/expectations/tests/generated")
+        .join(generated.file_name().unwrap());
+    let actual = fs::read_to_string(generated).unwrap_or_else(|err| {
+        panic!("could not read {}: {}", generated.display(), err)
+    });
+    if env::var_os("BINDGEN_OVERWRITE_EXPECTED").is_some() {
+        fs::write(&expected, actual).unwrap();
+        return;
+    }
+    let expected_source =
+        fs::read_to_string(&expected).unwrap_or_else(|err| {
+            panic!("could not read {}: {}", expected.display(), err)
+        });
+    assert!(
+        actual == expected_source,
+        "{} differs from {}:\n{}",
+        generated.display(),
+        expected.display(),
+        actual
+    );
+}
+
+/// The path, without extension, that the source file of a test generating
+/// one is written to.
+#[cfg(feature = "experimental")]
+fn generated_path(name: &str) -> std::path::PathBuf {
+    Path::new(env!("OUT_DIR")).join(name)
+}
+
+fn tokens(source: &str) -> String {
+    syn::parse_file(source)
+        .expect("bindings must parse")
+        .into_token_stream()
+        .to_string()
+}
+
+#[test]
+fn sandbox_functions() {
+    check("sandbox-functions.h", "sandbox_functions.rs", |builder| {
+        builder.sandbox(SandboxConfig::new())
+    });
+}
+
+#[test]
+fn sandbox_functions_disabled() {
+    check(
+        "sandbox-functions.h",
+        "sandbox_functions_disabled.rs",
+        |builder| builder,
+    );
+}
+
+#[test]
+fn sandbox_type() {
+    check("sandbox-type.h", "sandbox_type.rs", |builder| {
+        builder.sandbox(
+            SandboxConfig::new()
+                .sandbox_type("Lib")
+                .global("LIB")
+                .initializer("crate::init"),
+        )
+    });
+}
+
+#[test]
+fn sandbox_globals() {
+    check("sandbox-globals.h", "sandbox_globals.rs", |builder| {
+        builder.sandbox(SandboxConfig::new())
+    });
+}
+
+#[test]
+fn sandbox_fields() {
+    check("sandbox-fields.h", "sandbox_fields.rs", |builder| {
+        builder.sandbox(SandboxConfig::new())
+    });
+}
+
+#[test]
+fn sandbox_handles() {
+    check("sandbox-handles.h", "sandbox_handles.rs", |builder| {
+        builder.sandbox(SandboxConfig::new())
+    });
+}
+
+#[test]
+fn sandbox_fn_ptrs() {
+    check("sandbox-fn-ptrs.h", "sandbox_fn_ptrs.rs", |builder| {
+        builder.sandbox(SandboxConfig::new())
+    });
+}
+
+#[test]
+fn sandbox_variadic() {
+    check("sandbox-variadic.h", "sandbox_variadic.rs", |builder| {
+        builder.sandbox(SandboxConfig::new())
+    });
+}
+
+#[test]
+#[cfg(feature = "experimental")]
+fn sandbox_wrap_static_fns() {
+    let generated = generated_path("sandbox_wrap_static_fns");
+    check(
+        "sandbox-wrap-static-fns.h",
+        "sandbox_wrap_static_fns.rs",
+        |builder| {
+            builder
+                .sandbox(SandboxConfig::new())
+                .wrap_static_fns(true)
+                .wrap_static_fns_path(&generated)
+        },
+    );
+    check_generated(&generated.with_extension("c"));
+}
+
+#[test]
+fn sandbox_counted() {
+    check("sandbox-counted.h", "sandbox_counted.rs", |builder| {
+        builder.sandbox(SandboxConfig::new())
+    });
+}
+
+#[test]
+fn sandbox_nullable() {
+    check("sandbox-nullable.h", "sandbox_nullable.rs", |builder| {
+        builder.sandbox(SandboxConfig::new().nonnull_return("first"))
+    });
+}
+
+#[test]
+fn sandbox_fallible() {
+    check("sandbox-fallible.h", "sandbox_fallible.rs", |builder| {
+        builder.sandbox(SandboxConfig::new().fallible(true))
+    });
+}
+
+#[test]
+fn sandbox_lifecycle() {
+    check("sandbox-lifecycle.hpp", "sandbox_lifecycle.rs", |builder| {
+        builder.sandbox(
+            SandboxConfig::new().allocator("sandbox_alloc", "sandbox_free"),
+        )
+    });
+}
+
+#[test]
+fn sandbox_vtable() {
+    check("sandbox-vtable.hpp", "sandbox_vtable.rs", |builder| {
+        builder.vtable_generation(true).sandbox(SandboxConfig::new())
+    });
+}
+
+#[test]
+#[cfg(feature = "experimental")]
+fn sandbox_exceptions() {
+    let generated = generated_path("sandbox_exceptions");
+    check("sandbox-exceptions.hpp", "sandbox_exceptions.rs", |builder| {
+        builder
+            .sandbox(SandboxConfig::new().catch_exceptions(true))
+            .wrap_static_fns_path(&generated)
+    });
+    check_generated(&generated.with_extension("cpp"));
+}
+
+#[test]
+fn sandbox_batch() {
+    check("sandbox-batch.h", "sandbox_batch.rs", |builder| {
+        builder.sandbox(SandboxConfig::new().sandbox_type("Lib"))
+    });
+}
+
+/// Copies the point `measure` takes into the sandbox, reads its sum back out,
+/// and leaves `now` out of the sandbox.
+#[derive(Debug)]
+struct SandboxCallbacks;
+
+impl ParseCallbacks for SandboxCallbacks {
+    fn skip_sandboxing(&self, function: &str) -> bool {
+        function == "now"
+    }
+
+    fn sandbox_argument_kind(
+        &self,
+        info: SandboxArgumentInfo<'_>,
+    ) -> Option<SandboxArgumentKind> {
+        match (info.function, info.name) {
+            ("measure", Some("point")) => Some(SandboxArgumentKind::CopyIn),
+            ("measure", Some("sum")) => Some(SandboxArgumentKind::Out),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn sandbox_callbacks() {
+    let sandbox =
+        SandboxConfig::new().allocator("sandbox_alloc", "sandbox_free");
+    check("sandbox-callbacks.h", "sandbox_callbacks.rs", |builder| {
+        builder
+            .sandbox(sandbox)
+            .parse_callbacks(Box::new(SandboxCallbacks))
+    });
+}
+
+#[test]
+fn sandbox_strings() {
+    let sandbox = SandboxConfig::new()
+        .allocator("sandbox_alloc", "sandbox_free")
+        .owned_string_return("describe")
+        .owned_string_return("name_of")
+        .nonnull_return("name_of");
+    check("sandbox-strings.h", "sandbox_strings.rs", |builder| {
+        builder.sandbox(sandbox)
+    });
+}
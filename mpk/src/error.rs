@@ -0,0 +1,109 @@
+use std::fmt;
+
+use crate::CppException;
+
+/// The check that a value crossing the sandbox boundary failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SandboxErrorKind {
+    /// A pointer that must not be null was null.
+    Null,
+    /// A pointer was not aligned for the type it points to.
+    Misaligned,
+    /// A pointer or slice lies outside the sandbox.
+    OutOfSandbox,
+    /// A handle was passed to a sandbox other than the one that created it.
+    WrongSandbox,
+    /// Slices passed for the same length argument have different lengths.
+    LengthMismatch,
+    /// A slice is too long for the type of its length argument.
+    LengthOverflow,
+    /// The library does not export the function.
+    MissingSymbol,
+    /// A C++ object's vtable lies outside the sandbox's read-only data.
+    VtableOutOfSandbox,
+    /// A function pointer points outside the sandbox's code.
+    CodeOutOfSandbox,
+    /// The function threw a C++ exception, which was caught inside the sandbox.
+    Exception,
+    /// A string is not terminated inside the sandbox.
+    Unterminated,
+}
+
+impl fmt::Display for SandboxErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SandboxErrorKind::Null => "pointer is null",
+            SandboxErrorKind::Misaligned => "pointer is misaligned",
+            SandboxErrorKind::OutOfSandbox => "memory lies outside the sandbox",
+            SandboxErrorKind::WrongSandbox => "handle belongs to a different sandbox",
+            SandboxErrorKind::LengthMismatch => "slices sharing a length have different lengths",
+            SandboxErrorKind::LengthOverflow => "slice is too long for its length argument",
+            SandboxErrorKind::MissingSymbol => "function not exported by library",
+            SandboxErrorKind::VtableOutOfSandbox => {
+                "vtable lies outside the sandbox's read-only data"
+            }
+            SandboxErrorKind::CodeOutOfSandbox => "function pointer points outside the sandbox",
+            SandboxErrorKind::Exception => "C++ exception thrown",
+            SandboxErrorKind::Unterminated => "string is not terminated inside the sandbox",
+        })
+    }
+}
+
+/// An error returned by a fallible sandboxed binding, saying which check failed in which function.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SandboxError {
+    function: &'static str,
+    kind: SandboxErrorKind,
+    exception: Option<CppException>,
+}
+
+impl SandboxError {
+    pub fn new(function: &'static str, kind: SandboxErrorKind) -> Self {
+        SandboxError {
+            function,
+            kind,
+            exception: None,
+        }
+    }
+
+    /// An error of kind [`SandboxErrorKind::Exception`] for an exception `function` threw.
+    pub fn from_exception(function: &'static str, exception: CppException) -> Self {
+        SandboxError {
+            function,
+            kind: SandboxErrorKind::Exception,
+            exception: Some(exception),
+        }
+    }
+
+    /// The name of the bound function whose arguments or return value failed the check.
+    pub fn function(&self) -> &'static str {
+        self.function
+    }
+
+    pub fn kind(&self) -> SandboxErrorKind {
+        self.kind
+    }
+
+    /// The C++ exception the function threw, if that is the error.
+    pub fn cpp_exception(&self) -> Option<&CppException> {
+        self.exception.as_ref()
+    }
+}
+
+impl fmt::Display for SandboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.exception {
+            Some(ref exception) => write!(f, "`{}`: {}", self.function, exception),
+            None => write!(f, "`{}`: {}", self.function, self.kind),
+        }
+    }
+}
+
+impl std::error::Error for SandboxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.exception
+            .as_ref()
+            .map(|e| e as &(dyn std::error::Error + 'static))
+    }
+}
@@ -0,0 +1,70 @@
+//! C++ exceptions caught inside the sandbox.
+use std::{ffi::CStr, fmt};
+
+/// The exception a shim generated by bindgen (see `SandboxConfig::catch_exceptions`) caught inside
+/// the sandbox, instead of letting it unwind through the sandbox boundary.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CppException {
+    type_name: String,
+    what: String,
+}
+
+impl CppException {
+    /// The name of the exception's type as given by `std::type_info::name`, which is mangled on
+    /// most platforms, e.g. `St13runtime_error`.
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    /// The text returned by `what()`, or an empty string if the exception is not derived from
+    /// `std::exception`.
+    pub fn what(&self) -> &str {
+        &self.what
+    }
+}
+
+impl fmt::Display for CppException {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "C++ exception `{}`", self.type_name)?;
+        if !self.what.is_empty() {
+            write!(f, ": {}", self.what)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CppException {}
+
+/// Where the shims of a sandboxed library record the exception they caught. This lives in the
+/// library's sandbox data, so every field is checked when it is read.
+#[repr(C)]
+pub struct ExceptionRecord {
+    caught: u32,
+    type_name: [u8; 128],
+    what: [u8; 256],
+}
+
+impl ExceptionRecord {
+    /// Returns the exception recorded since the last call, if any, and clears the record.
+    ///
+    /// # Safety
+    ///
+    /// `record` must point to the exception record of a sandboxed library, and no sandboxed code
+    /// may run concurrently.
+    pub unsafe fn take(record: *mut ExceptionRecord) -> Option<CppException> {
+        let record = &mut *record;
+        if record.caught == 0 {
+            return None;
+        }
+        record.caught = 0;
+        let text = |bytes: &[u8]| {
+            CStr::from_bytes_until_nul(bytes)
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| String::from_utf8_lossy(bytes).into_owned())
+        };
+        Some(CppException {
+            type_name: text(&record.type_name),
+            what: text(&record.what),
+        })
+    }
+}
@@ -1,14 +1,19 @@
+mod error;
+mod exception;
+mod library;
+pub mod region;
 mod sandbox;
+mod string;
 
-use std::ptr::addr_of;
+use std::ptr::NonNull;
 
 use bytemuck::AnyBitPattern;
+pub use error::{SandboxError, SandboxErrorKind};
+pub use exception::{CppException, ExceptionRecord};
+pub use library::{Imports, Library};
+use region::RegionKind;
 pub use sandbox::Sandbox;
-
-extern "C" {
-    static _SANDBOX_START_: libc::c_void;
-    static _SANDBOX_END_: libc::c_void;
-}
+pub use string::SandboxCString;
 
 /// A `SandboxSafe` type is one that lives inside a sandbox, but can be referenced by safe code
 /// outside of the sandbox.
@@ -23,24 +28,116 @@ pub unsafe trait SandboxSafe {}
 unsafe impl<T: AnyBitPattern> SandboxSafe for T {}
 unsafe impl<T: AnyBitPattern> SandboxSafe for [T] {}
 
+/// A field of the struct `S`, used to project sandbox pointers to `S` onto the field with
+/// [`SandboxPtr::field`]. Sandboxed bindings implement this for a marker type per struct field.
+///
+/// # Safety
+///
+/// `OFFSET` must be the offset in bytes of a field of type `Type` within `S`.
+pub unsafe trait Field<S> {
+    type Type;
+    const OFFSET: usize;
+}
+
 /// A pointer to a value that lives inside of a sandbox.
-#[derive(Clone, Copy)]
 pub struct SandboxPtr<T: ?Sized>(*const T);
 
+impl<T: ?Sized> Clone for SandboxPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: ?Sized> Copy for SandboxPtr<T> {}
+
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub fn validate_sandbox_ptr<T>(ptr: *const T) {
-    assert!(!ptr.is_null());
-    if std::mem::size_of::<T>() != 0 {
-        /*unsafe {
-            assert!(
-                addr_of!(_SANDBOX_START_) <= ptr as *const libc::c_void
-                    && addr_of!(_SANDBOX_END_) >= ptr.add(1) as *const libc::c_void,
-                "pointer points outside the sandbox"
-            );
-        }*/
+    if let Err(kind) = check_sandbox_ptr(ptr) {
+        panic!("{kind}");
+    }
+}
+
+/// Like [`validate_sandbox_ptr`], but returns the failed check instead of panicking.
+///
+/// The value must lie in the sandbox's data or read-only data. Pointers to zero-sized types are not
+/// checked against the sandbox's regions, since they cannot be used to access any memory.
+pub fn check_sandbox_ptr<T>(ptr: *const T) -> Result<(), SandboxErrorKind> {
+    check_pointee(ptr, 1, READABLE)
+}
+
+/// The regions a `SandboxPtr` may point into.
+const READABLE: &[RegionKind] = &[RegionKind::Data, RegionKind::ReadOnly];
+/// The regions a `SandboxPtrMut` may point into.
+const WRITABLE: &[RegionKind] = &[RegionKind::Data];
+
+/// Checks that the `len` elements starting at `ptr` are non-null, aligned, and lie in one region
+/// of one of the given kinds.
+fn check_pointee<T>(
+    ptr: *const T,
+    len: usize,
+    kinds: &[RegionKind],
+) -> Result<(), SandboxErrorKind> {
+    if ptr.is_null() {
+        return Err(SandboxErrorKind::Null);
     }
     if (ptr as usize) & (std::mem::align_of::<T>() - 1) != 0 {
-        panic!("pointer is misaligned");
+        return Err(SandboxErrorKind::Misaligned);
+    }
+    check_region(ptr, len, kinds)
+}
+
+/// Checks that the `len` elements starting at `ptr` lie in one region of one of the given kinds.
+/// Ranges that wrap around the end of the address space lie outside the sandbox.
+fn check_region<T>(
+    ptr: *const T,
+    len: usize,
+    kinds: &[RegionKind],
+) -> Result<(), SandboxErrorKind> {
+    let start = ptr as usize;
+    let Some(size) = len.checked_mul(std::mem::size_of::<T>()) else {
+        return Err(SandboxErrorKind::OutOfSandbox);
+    };
+    if size == 0
+        || start
+            .checked_add(size)
+            .is_some_and(|end| kinds.iter().any(|&kind| region::contains(start, end, kind)))
+    {
+        Ok(())
+    } else {
+        Err(SandboxErrorKind::OutOfSandbox)
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+    impl<T> Sealed for T {}
+    impl<T> Sealed for [T] {}
+}
+
+/// A type a sandbox pointer can point to: a sized type or a slice. This trait is sealed.
+pub trait Pointee: private::Sealed {
+    #[doc(hidden)]
+    fn check(ptr: *const Self, kinds: &[RegionKind]) -> Result<(), SandboxErrorKind>;
+}
+
+impl<T> Pointee for T {
+    fn check(ptr: *const Self, kinds: &[RegionKind]) -> Result<(), SandboxErrorKind> {
+        check_pointee(ptr, 1, kinds)
+    }
+}
+
+impl<T> Pointee for [T] {
+    fn check(ptr: *const Self, kinds: &[RegionKind]) -> Result<(), SandboxErrorKind> {
+        // An empty slice may be dangling, but must still be aligned.
+        let (ptr, len) = (ptr as *const T, ptr.len());
+        if len == 0 {
+            return if ptr.is_aligned() {
+                Ok(())
+            } else {
+                Err(SandboxErrorKind::Misaligned)
+            };
+        }
+        check_pointee(ptr, len, kinds)
     }
 }
 
@@ -53,6 +150,35 @@ impl<T: ?Sized> SandboxPtr<T> {
         SandboxPtr(ptr)
     }
 
+    /// Like [`SandboxPtr::new`], but returns `None` for a null pointer instead of panicking.
+    pub fn new_nullable(ptr: *const T) -> Option<Self>
+    where
+        T: Sized,
+    {
+        (!ptr.is_null()).then(|| Self::new(ptr))
+    }
+
+    /// Like [`SandboxPtr::new`], but returns the failed check instead of panicking.
+    pub fn try_new(ptr: *const T) -> Result<Self, SandboxErrorKind>
+    where
+        T: Sized,
+    {
+        check_sandbox_ptr(ptr)?;
+        Ok(SandboxPtr(ptr))
+    }
+
+    /// Like [`SandboxPtr::new_nullable`], but returns the failed check instead of panicking.
+    pub fn try_new_nullable(ptr: *const T) -> Result<Option<Self>, SandboxErrorKind>
+    where
+        T: Sized,
+    {
+        if ptr.is_null() {
+            Ok(None)
+        } else {
+            Self::try_new(ptr).map(Some)
+        }
+    }
+
     /// Creates a SandboxPtr without checking its validity.
     ///
     /// # Safety
@@ -66,11 +192,23 @@ impl<T: ?Sized> SandboxPtr<T> {
         self.0
     }
 
-    pub fn as_ref<'a>(&self, _sandbox: &'a Sandbox) -> &'a T
+    /// Returns a reference to the value, which lives as long as the borrow of the sandbox keeps
+    /// sandboxed code from running. Panics if the value does not lie inside the sandbox.
+    pub fn as_ref<'a>(&self, sandbox: &'a Sandbox) -> &'a T
     where
-        T: SandboxSafe,
+        T: SandboxSafe + Pointee,
     {
-        unsafe { self.0.as_ref().unwrap() }
+        self.try_as_ref(sandbox)
+            .unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxPtr::as_ref`], but returns the failed check instead of panicking.
+    pub fn try_as_ref<'a>(&self, _sandbox: &'a Sandbox) -> Result<&'a T, SandboxErrorKind>
+    where
+        T: SandboxSafe + Pointee,
+    {
+        T::check(self.0, READABLE)?;
+        Ok(unsafe { &*self.0 })
     }
 
     pub fn as_slice(&self, len: usize) -> SandboxPtr<[T]>
@@ -78,11 +216,81 @@ impl<T: ?Sized> SandboxPtr<T> {
         T: Sized,
         T: SandboxSafe,
     {
-        unsafe {
-            assert!(len == 0 || addr_of!(_SANDBOX_START_) <= self.0 as *const libc::c_void);
-            assert!(len == 0 || addr_of!(_SANDBOX_END_) >= self.0.add(len) as *const libc::c_void);
-        }
-        SandboxPtr(std::ptr::slice_from_raw_parts(self.0, len))
+        self.try_as_slice(len)
+            .unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxPtr::as_slice`], but returns the failed check instead of panicking.
+    pub fn try_as_slice(&self, len: usize) -> Result<SandboxPtr<[T]>, SandboxErrorKind>
+    where
+        T: Sized,
+        T: SandboxSafe,
+    {
+        let slice = std::ptr::slice_from_raw_parts(self.0, len);
+        <[T]>::check(slice, READABLE)?;
+        Ok(SandboxPtr(slice))
+    }
+}
+
+impl<T> SandboxPtr<[T]> {
+    /// The number of elements in the slice.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A pointer to the first element of the slice.
+    pub fn as_ptr(&self) -> *const T {
+        self.0 as *const T
+    }
+}
+
+impl<S> SandboxPtr<S> {
+    /// Projects the pointer to the field `F` of the struct it points to.
+    pub fn field<F: Field<S>>(&self) -> SandboxPtr<F::Type> {
+        SandboxPtr(self.0.wrapping_byte_add(F::OFFSET) as *const F::Type)
+    }
+}
+
+impl<T> SandboxPtr<*const T> {
+    /// Reads the pointer this points to, or `None` if it is null. Panics if this or the pointer
+    /// read does not point into the sandbox.
+    ///
+    /// Borrowing the sandbox keeps sandboxed code from running, and so from changing the value,
+    /// while it is read.
+    pub fn read(&self, sandbox: &Sandbox) -> Option<SandboxPtr<T>> {
+        self.try_read(sandbox)
+            .unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxPtr::read`], but returns the failed check instead of panicking.
+    pub fn try_read(&self, _sandbox: &Sandbox) -> Result<Option<SandboxPtr<T>>, SandboxErrorKind> {
+        check_sandbox_ptr(self.0)?;
+        SandboxPtr::try_new_nullable(unsafe { self.0.read() })
+    }
+}
+
+impl<T> SandboxPtr<*mut T> {
+    /// Reads the pointer this points to, or `None` if it is null. Panics if this or the pointer
+    /// read does not point into the sandbox.
+    ///
+    /// Borrowing the sandbox keeps sandboxed code from running, and so from changing the value,
+    /// while it is read.
+    pub fn read(&self, sandbox: &Sandbox) -> Option<SandboxPtrMut<T>> {
+        self.try_read(sandbox)
+            .unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxPtr::read`], but returns the failed check instead of panicking.
+    pub fn try_read(
+        &self,
+        _sandbox: &Sandbox,
+    ) -> Result<Option<SandboxPtrMut<T>>, SandboxErrorKind> {
+        check_sandbox_ptr(self.0)?;
+        SandboxPtrMut::try_new_nullable(unsafe { self.0.read() })
     }
 }
 
@@ -93,35 +301,93 @@ impl SandboxPtr<std::ffi::c_char> {
 }
 
 /// A mutable pointer to a value that lives inside of a sandbox.
-#[derive(Clone, Copy)]
 pub struct SandboxPtrMut<T: ?Sized>(*mut T);
 
+impl<T: ?Sized> Clone for SandboxPtrMut<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: ?Sized> Copy for SandboxPtrMut<T> {}
+
 impl<T: ?Sized> SandboxPtrMut<T> {
-    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn new(ptr: *mut T) -> Self
     where
         T: Sized,
     {
-        validate_sandbox_ptr(ptr);
-        SandboxPtrMut(ptr)
+        Self::try_new(ptr).unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxPtrMut::new`], but returns `None` for a null pointer instead of panicking.
+    pub fn new_nullable(ptr: *mut T) -> Option<Self>
+    where
+        T: Sized,
+    {
+        (!ptr.is_null()).then(|| Self::new(ptr))
+    }
+
+    /// Like [`SandboxPtrMut::new`], but returns the failed check instead of panicking.
+    pub fn try_new(ptr: *mut T) -> Result<Self, SandboxErrorKind>
+    where
+        T: Sized,
+    {
+        check_pointee(ptr, 1, WRITABLE)?;
+        Ok(SandboxPtrMut(ptr))
+    }
+
+    /// Like [`SandboxPtrMut::new_nullable`], but returns the failed check instead of panicking.
+    pub fn try_new_nullable(ptr: *mut T) -> Result<Option<Self>, SandboxErrorKind>
+    where
+        T: Sized,
+    {
+        if ptr.is_null() {
+            Ok(None)
+        } else {
+            Self::try_new(ptr).map(Some)
+        }
     }
 
     pub fn get(&self) -> *mut T {
         self.0
     }
 
-    pub fn as_ref<'a>(&self, _sandbox: &'a Sandbox) -> &'a T
+    /// Returns a reference to the value, which lives as long as the borrow of the sandbox keeps
+    /// sandboxed code from running. Panics if the value does not lie in the sandbox's data.
+    pub fn as_ref<'a>(&self, sandbox: &'a Sandbox) -> &'a T
     where
-        T: SandboxSafe,
+        T: SandboxSafe + Pointee,
     {
-        unsafe { self.0.as_ref().unwrap() }
+        self.try_as_ref(sandbox)
+            .unwrap_or_else(|kind| panic!("{kind}"))
     }
 
-    pub fn as_mut<'a>(&self, _sandbox: &'a mut Sandbox) -> &'a mut T
+    /// Like [`SandboxPtrMut::as_ref`], but returns the failed check instead of panicking.
+    pub fn try_as_ref<'a>(&self, _sandbox: &'a Sandbox) -> Result<&'a T, SandboxErrorKind>
     where
-        T: SandboxSafe,
+        T: SandboxSafe + Pointee,
     {
-        unsafe { self.0.as_mut().unwrap() }
+        T::check(self.0, WRITABLE)?;
+        Ok(unsafe { &*self.0 })
+    }
+
+    /// Returns a mutable reference to the value, which lives as long as the exclusive borrow of
+    /// the sandbox. Panics if the value does not lie in the sandbox's data.
+    pub fn as_mut<'a>(&self, sandbox: &'a mut Sandbox) -> &'a mut T
+    where
+        T: SandboxSafe + Pointee,
+    {
+        self.try_as_mut(sandbox)
+            .unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxPtrMut::as_mut`], but returns the failed check instead of panicking.
+    pub fn try_as_mut<'a>(&self, _sandbox: &'a mut Sandbox) -> Result<&'a mut T, SandboxErrorKind>
+    where
+        T: SandboxSafe + Pointee,
+    {
+        T::check(self.0, WRITABLE)?;
+        Ok(unsafe { &mut *self.0 })
     }
 
     pub fn as_slice(&self, len: usize) -> SandboxPtrMut<[T]>
@@ -129,10 +395,572 @@ impl<T: ?Sized> SandboxPtrMut<T> {
         T: Sized,
         T: SandboxSafe,
     {
-        unsafe {
-            assert!(len == 0 || addr_of!(_SANDBOX_START_) <= self.0 as *const libc::c_void);
-            assert!(len == 0 || addr_of!(_SANDBOX_END_) >= self.0.add(len) as *const libc::c_void);
+        self.try_as_slice(len)
+            .unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxPtrMut::as_slice`], but returns the failed check instead of panicking.
+    pub fn try_as_slice(&self, len: usize) -> Result<SandboxPtrMut<[T]>, SandboxErrorKind>
+    where
+        T: Sized,
+        T: SandboxSafe,
+    {
+        let slice = std::ptr::slice_from_raw_parts_mut(self.0, len);
+        <[T]>::check(slice, WRITABLE)?;
+        Ok(SandboxPtrMut(slice))
+    }
+}
+
+impl<T> SandboxPtrMut<[T]> {
+    /// The number of elements in the slice.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A pointer to the first element of the slice.
+    pub fn as_mut_ptr(&self) -> *mut T {
+        self.0 as *mut T
+    }
+}
+
+impl<S> SandboxPtrMut<S> {
+    /// Projects the pointer to the field `F` of the struct it points to.
+    pub fn field<F: Field<S>>(&self) -> SandboxPtrMut<F::Type> {
+        SandboxPtrMut(self.0.wrapping_byte_add(F::OFFSET) as *mut F::Type)
+    }
+}
+
+impl<T> SandboxPtrMut<*const T> {
+    /// Reads the pointer this points to, or `None` if it is null. Panics if this or the pointer
+    /// read does not point into the sandbox.
+    ///
+    /// Borrowing the sandbox keeps sandboxed code from running, and so from changing the value,
+    /// while it is read.
+    pub fn read(&self, sandbox: &Sandbox) -> Option<SandboxPtr<T>> {
+        self.try_read(sandbox)
+            .unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxPtrMut::read`], but returns the failed check instead of panicking.
+    pub fn try_read(&self, _sandbox: &Sandbox) -> Result<Option<SandboxPtr<T>>, SandboxErrorKind> {
+        check_pointee(self.0.cast_const(), 1, WRITABLE)?;
+        SandboxPtr::try_new_nullable(unsafe { self.0.read() })
+    }
+}
+
+impl<T> SandboxPtrMut<*mut T> {
+    /// Reads the pointer this points to, or `None` if it is null. Panics if this or the pointer
+    /// read does not point into the sandbox.
+    ///
+    /// Borrowing the sandbox keeps sandboxed code from running, and so from changing the value,
+    /// while it is read.
+    pub fn read(&self, sandbox: &Sandbox) -> Option<SandboxPtrMut<T>> {
+        self.try_read(sandbox)
+            .unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxPtrMut::read`], but returns the failed check instead of panicking.
+    pub fn try_read(
+        &self,
+        _sandbox: &Sandbox,
+    ) -> Result<Option<SandboxPtrMut<T>>, SandboxErrorKind> {
+        check_pointee(self.0.cast_const(), 1, WRITABLE)?;
+        SandboxPtrMut::try_new_nullable(unsafe { self.0.read() })
+    }
+}
+
+/// A handle to an object inside a sandbox whose type is opaque to Rust, such as an incomplete C
+/// struct. Unlike a [`SandboxPtr`], a handle cannot be dereferenced; it can only be passed back
+/// into the sandbox that created it.
+pub struct SandboxHandle<T> {
+    ptr: NonNull<T>,
+    sandbox: u64,
+}
+
+impl<T> Clone for SandboxHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for SandboxHandle<T> {}
+
+impl<T> SandboxHandle<T> {
+    /// Wraps a pointer obtained from `sandbox`. Panics if the pointer is null.
+    pub fn new(ptr: *const T, sandbox: &Sandbox) -> Self {
+        let ptr = NonNull::new(ptr.cast_mut()).expect("sandbox returned a null handle");
+        SandboxHandle {
+            ptr,
+            sandbox: sandbox.id(),
+        }
+    }
+
+    /// Like [`SandboxHandle::new`], but returns an error instead of panicking on a null pointer.
+    pub fn try_new(ptr: *const T, sandbox: &Sandbox) -> Result<Self, SandboxErrorKind> {
+        Self::new_nullable(ptr, sandbox).ok_or(SandboxErrorKind::Null)
+    }
+
+    /// Like [`SandboxHandle::new`], but returns `None` for a null pointer instead of panicking.
+    pub fn new_nullable(ptr: *const T, sandbox: &Sandbox) -> Option<Self> {
+        (!ptr.is_null()).then(|| Self::new(ptr, sandbox))
+    }
+
+    /// Returns the pointer to pass into `sandbox`. Panics if the handle was created by a different
+    /// sandbox, which would not know the object.
+    pub fn get(&self, sandbox: &Sandbox) -> *mut T {
+        self.try_get(sandbox)
+            .unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxHandle::get`], but returns the failed check instead of panicking.
+    pub fn try_get(&self, sandbox: &Sandbox) -> Result<*mut T, SandboxErrorKind> {
+        if self.sandbox == sandbox.id() {
+            Ok(self.ptr.as_ptr())
+        } else {
+            Err(SandboxErrorKind::WrongSandbox)
+        }
+    }
+}
+
+/// A pointer to a function inside a sandbox, such as a callback stored by a sandboxed library.
+///
+/// The pointer is checked to lie within sandboxed code when it is created and again whenever it is
+/// used, since C code can hand out any value as a `SandboxFnPtr`. `F` is the raw function pointer
+/// type, e.g. `unsafe extern "C" fn(i32) -> i32`, which this type is layout-compatible with.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SandboxFnPtr<F>(F);
+
+impl<F: Copy> SandboxFnPtr<F> {
+    /// Wraps a function pointer. Panics if it does not point into sandboxed code.
+    pub fn new(f: F) -> Self {
+        let f = SandboxFnPtr(f);
+        f.validate();
+        f
+    }
+
+    fn validate(&self) {
+        if let Err(kind) = self.check() {
+            panic!("{kind}");
+        }
+    }
+
+    /// Like [`SandboxFnPtr::validate`], but returns the failed check instead of panicking.
+    fn check(&self) -> Result<(), SandboxErrorKind> {
+        assert_eq!(
+            std::mem::size_of::<F>(),
+            std::mem::size_of::<usize>(),
+            "SandboxFnPtr must wrap a function pointer"
+        );
+        let addr: usize = unsafe { std::mem::transmute_copy(&self.0) };
+        // An address at the very end of the address space cannot be in the sandbox's code.
+        if addr
+            .checked_add(1)
+            .is_some_and(|end| region::contains(addr, end, RegionKind::Text))
+        {
+            Ok(())
+        } else {
+            Err(SandboxErrorKind::CodeOutOfSandbox)
+        }
+    }
+
+    /// Returns the function pointer, e.g. to pass it back into the sandbox. Panics if it does not
+    /// point into sandboxed code.
+    pub fn get(&self) -> F {
+        self.validate();
+        self.0
+    }
+
+    /// Calls `f` with the function pointer inside `sandbox`. Panics if the pointer does not point
+    /// into sandboxed code.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Sandbox::call`], `f` must not reference data that lives outside the sandbox.
+    pub unsafe fn call<T, C: FnOnce(F) -> T + 'static>(&self, sandbox: &mut Sandbox, f: C) -> T
+    where
+        F: 'static,
+    {
+        let ptr = self.get();
+        sandbox.call(move || f(ptr))
+    }
+}
+
+/// Reads the vtable of the polymorphic C++ object at `this`, checking that it lies in the
+/// sandbox's read-only data and that each of its entries points into sandboxed code. Sandboxed
+/// code can overwrite the vtable pointer of the object, but not the vtables themselves.
+///
+/// # Safety
+///
+/// The first field of the object must be its vtable pointer, and `V` must be a `#[repr(C)]` struct
+/// of function pointers.
+unsafe fn read_vtable<V>(this: *const usize) -> Result<V, SandboxErrorKind> {
+    let vtable = this.read();
+    let size = std::mem::size_of::<V>();
+    // The whole vtable is checked before any entry is read. A vtable that wraps around the end of
+    // the address space lies outside the sandbox.
+    if !vtable.is_multiple_of(std::mem::align_of::<usize>())
+        || !vtable
+            .checked_add(size)
+            .is_some_and(|end| region::contains(vtable, end, RegionKind::ReadOnly))
+    {
+        return Err(SandboxErrorKind::VtableOutOfSandbox);
+    }
+    let entries =
+        std::slice::from_raw_parts(vtable as *const usize, size / std::mem::size_of::<usize>());
+    if !entries.iter().all(|&entry| {
+        entry
+            .checked_add(1)
+            .is_some_and(|end| region::contains(entry, end, RegionKind::Text))
+    }) {
+        return Err(SandboxErrorKind::CodeOutOfSandbox);
+    }
+    Ok((vtable as *const V).read())
+}
+
+impl<T> SandboxPtr<T> {
+    /// Reads the vtable `V` of the C++ object this points to. Panics if the vtable or any of its
+    /// entries lies outside the sandbox.
+    ///
+    /// # Safety
+    ///
+    /// `T` must be a polymorphic class whose vtable has the layout of `V`, a `#[repr(C)]` struct of
+    /// function pointers.
+    pub unsafe fn vtable<V>(&self) -> V {
+        self.try_vtable().unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxPtr::vtable`], but returns the failed check instead of panicking.
+    ///
+    /// # Safety
+    ///
+    /// As for [`SandboxPtr::vtable`].
+    pub unsafe fn try_vtable<V>(&self) -> Result<V, SandboxErrorKind> {
+        read_vtable(self.0.cast())
+    }
+}
+
+impl<T> SandboxPtrMut<T> {
+    /// Reads the vtable `V` of the C++ object this points to. Panics if the vtable or any of its
+    /// entries lies outside the sandbox.
+    ///
+    /// # Safety
+    ///
+    /// As for [`SandboxPtr::vtable`].
+    pub unsafe fn vtable<V>(&self) -> V {
+        self.try_vtable().unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxPtrMut::vtable`], but returns the failed check instead of panicking.
+    ///
+    /// # Safety
+    ///
+    /// As for [`SandboxPtr::vtable`].
+    pub unsafe fn try_vtable<V>(&self) -> Result<V, SandboxErrorKind> {
+        read_vtable(self.0.cast())
+    }
+}
+
+impl<F: Copy> SandboxPtr<Option<SandboxFnPtr<F>>> {
+    /// Reads the function pointer this points to, or `None` if it is null. Panics if this does not
+    /// point into the sandbox, or the function pointer does not point into sandboxed code.
+    ///
+    /// As for [`SandboxPtr::read`], borrowing the sandbox keeps the value from changing.
+    pub fn read(&self, sandbox: &Sandbox) -> Option<SandboxFnPtr<F>> {
+        self.try_read(sandbox)
+            .unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxPtr::read`], but returns the failed check instead of panicking.
+    pub fn try_read(
+        &self,
+        _sandbox: &Sandbox,
+    ) -> Result<Option<SandboxFnPtr<F>>, SandboxErrorKind> {
+        check_sandbox_ptr(self.0)?;
+        let f = unsafe { self.0.read() };
+        if let Some(f) = f {
+            f.check()?;
+        }
+        Ok(f)
+    }
+}
+
+impl<F: Copy> SandboxPtrMut<Option<SandboxFnPtr<F>>> {
+    /// Reads the function pointer this points to, or `None` if it is null. Panics if this does not
+    /// point into the sandbox, or the function pointer does not point into sandboxed code.
+    ///
+    /// As for [`SandboxPtr::read`], borrowing the sandbox keeps the value from changing.
+    pub fn read(&self, sandbox: &Sandbox) -> Option<SandboxFnPtr<F>> {
+        self.try_read(sandbox)
+            .unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxPtrMut::read`], but returns the failed check instead of panicking.
+    pub fn try_read(
+        &self,
+        _sandbox: &Sandbox,
+    ) -> Result<Option<SandboxFnPtr<F>>, SandboxErrorKind> {
+        check_pointee(self.0.cast_const(), 1, WRITABLE)?;
+        let f = unsafe { self.0.read() };
+        if let Some(f) = f {
+            f.check()?;
+        }
+        Ok(f)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use region::Region;
+
+    /// Heap memory registered as a sandbox region for the duration of a test.
+    pub(crate) struct TestRegion {
+        memory: Box<[u64]>,
+        region: Region,
+    }
+
+    impl TestRegion {
+        pub(crate) fn new(words: usize, kind: RegionKind) -> TestRegion {
+            let memory = vec![0; words].into_boxed_slice();
+            let start = memory.as_ptr() as usize;
+            let region = Region {
+                start,
+                end: start + words * 8,
+                kind,
+            };
+            region::register(region);
+            TestRegion { memory, region }
+        }
+
+        pub(crate) fn ptr<T>(&mut self, offset: usize) -> *mut T {
+            self.memory.as_mut_ptr().wrapping_byte_add(offset).cast()
         }
-        SandboxPtrMut(std::ptr::slice_from_raw_parts_mut(self.0, len))
+
+        pub(crate) fn write<T>(&mut self, offset: usize, value: T) {
+            assert!(offset + std::mem::size_of::<T>() <= self.memory.len() * 8);
+            unsafe { self.ptr::<T>(offset).write_unaligned(value) }
+        }
+    }
+
+    impl Drop for TestRegion {
+        fn drop(&mut self) {
+            region::unregister(self.region);
+        }
+    }
+
+    #[test]
+    fn pointers_must_lie_in_sandbox() {
+        let mut data = TestRegion::new(2, RegionKind::Data);
+        let mut rodata = TestRegion::new(2, RegionKind::ReadOnly);
+        let outside = Box::new(0u64);
+
+        assert!(SandboxPtr::try_new(data.ptr::<u64>(8)).is_ok());
+        assert!(SandboxPtrMut::try_new(data.ptr::<u64>(8)).is_ok());
+        assert!(SandboxPtr::try_new(rodata.ptr::<u64>(0)).is_ok());
+        assert_eq!(
+            SandboxPtrMut::try_new(rodata.ptr::<u64>(0)).err(),
+            Some(SandboxErrorKind::OutOfSandbox)
+        );
+        assert_eq!(
+            check_sandbox_ptr(&*outside as *const u64),
+            Err(SandboxErrorKind::OutOfSandbox)
+        );
+        // The value must lie entirely inside the region.
+        assert_eq!(
+            check_sandbox_ptr(data.ptr::<[u64; 2]>(8)),
+            Err(SandboxErrorKind::OutOfSandbox)
+        );
+        assert_eq!(
+            check_sandbox_ptr(usize::MAX as *const u8),
+            Err(SandboxErrorKind::OutOfSandbox)
+        );
+        // Zero-sized values are not checked against the regions, but must be aligned.
+        assert!(check_sandbox_ptr(&*outside as *const u64 as *const ()).is_ok());
+        assert_eq!(
+            check_sandbox_ptr(std::ptr::null::<()>()),
+            Err(SandboxErrorKind::Null)
+        );
+    }
+
+    #[test]
+    fn slices_must_lie_in_sandbox() {
+        let mut data = TestRegion::new(4, RegionKind::Data);
+        let ptr = SandboxPtrMut::new(data.ptr::<u32>(0));
+        assert_eq!(ptr.as_slice(8).len(), 8);
+        assert!(ptr.as_slice(0).is_empty());
+        assert_eq!(
+            ptr.try_as_slice(9).err(),
+            Some(SandboxErrorKind::OutOfSandbox)
+        );
+        assert_eq!(
+            ptr.try_as_slice(usize::MAX / 2).err(),
+            Some(SandboxErrorKind::OutOfSandbox)
+        );
+
+        let mut rodata = TestRegion::new(1, RegionKind::ReadOnly);
+        assert_eq!(SandboxPtr::new(rodata.ptr::<u8>(0)).as_slice(8).len(), 8);
+    }
+
+    #[test]
+    fn references_are_checked() {
+        let mut sandbox = Sandbox::new();
+        let mut data = TestRegion::new(2, RegionKind::Data);
+        data.write(0, 7u64);
+        let ptr = SandboxPtrMut::new(data.ptr::<u64>(0));
+        assert_eq!(*ptr.as_ref(&sandbox), 7);
+        *ptr.as_mut(&mut sandbox) = 8;
+        assert_eq!(*SandboxPtr::new(ptr.get()).as_ref(&sandbox), 8);
+        assert_eq!(ptr.as_slice(2).as_ref(&sandbox), [8, 0]);
+
+        // A projection past the end of the region is caught when it is dereferenced.
+        struct Past;
+        unsafe impl Field<u64> for Past {
+            type Type = u64;
+            const OFFSET: usize = 16;
+        }
+        let past = ptr.field::<Past>();
+        assert_eq!(
+            past.try_as_ref(&sandbox).err(),
+            Some(SandboxErrorKind::OutOfSandbox)
+        );
+        assert_eq!(
+            past.try_as_mut(&mut sandbox).err(),
+            Some(SandboxErrorKind::OutOfSandbox)
+        );
+        let past = SandboxPtr::new(ptr.get()).field::<Past>();
+        assert_eq!(
+            past.try_as_ref(&sandbox).err(),
+            Some(SandboxErrorKind::OutOfSandbox)
+        );
+    }
+
+    #[test]
+    fn vtables_are_checked() {
+        #[repr(C)]
+        struct Vtable {
+            first: usize,
+            second: usize,
+        }
+        let text = TestRegion::new(2, RegionKind::Text);
+        let mut rodata = TestRegion::new(4, RegionKind::ReadOnly);
+        let mut object = TestRegion::new(1, RegionKind::Data);
+        let code = text.region.start;
+        rodata.write(0, [code, code + 8, code, usize::MAX]);
+        let this = SandboxPtr::new(object.ptr::<u64>(0));
+        let vtable = |object: &mut TestRegion, address: usize| {
+            object.write(0, address);
+            unsafe { this.try_vtable::<Vtable>() }
+        };
+
+        let table = rodata.ptr::<u8>(0) as usize;
+        let v = vtable(&mut object, table).unwrap();
+        assert_eq!((v.first, v.second), (code, code + 8));
+        // The second entry lies at the very end of the address space.
+        assert_eq!(
+            vtable(&mut object, table + 16).err(),
+            Some(SandboxErrorKind::CodeOutOfSandbox)
+        );
+        // The vtable extends past the end of the region, is misaligned, wraps around the end of
+        // the address space, or lies in writable data.
+        let data = object.ptr::<u8>(0) as usize;
+        for address in [table + 24, table + 4, usize::MAX - 7, data] {
+            assert_eq!(
+                vtable(&mut object, address).err(),
+                Some(SandboxErrorKind::VtableOutOfSandbox)
+            );
+        }
+    }
+
+    #[test]
+    fn read_checks_loaded_pointer() {
+        let sandbox = Sandbox::new();
+        let mut data = TestRegion::new(4, RegionKind::Data);
+        let target = data.ptr::<u32>(24);
+        data.write(0, target);
+        data.write(8, std::ptr::null_mut::<u32>());
+        data.write(16, target.wrapping_byte_add(1));
+        let outside = Box::new(0u32);
+
+        let field = |data: &mut TestRegion, offset| SandboxPtr::new(data.ptr::<*const u32>(offset));
+        let ptr = field(&mut data, 0).read(&sandbox).unwrap();
+        assert_eq!(ptr.get(), target);
+        assert!(field(&mut data, 8).read(&sandbox).is_none());
+        assert_eq!(
+            field(&mut data, 16).try_read(&sandbox).err(),
+            Some(SandboxErrorKind::Misaligned)
+        );
+
+        let field = SandboxPtrMut::new(data.ptr::<*mut u32>(0));
+        assert_eq!(field.read(&sandbox).unwrap().get(), target);
+        data.write(0, &*outside as *const u32);
+        assert_eq!(
+            field.try_read(&sandbox).err(),
+            Some(SandboxErrorKind::OutOfSandbox)
+        );
+    }
+
+    #[test]
+    fn handles_belong_to_their_sandbox() {
+        struct Opaque;
+        let sandbox = Sandbox::new();
+        let other = Sandbox::new();
+        let ptr = std::ptr::NonNull::<Opaque>::dangling().as_ptr();
+
+        let handle = SandboxHandle::new(ptr, &sandbox);
+        assert_eq!(handle.get(&sandbox), ptr);
+        assert_eq!(
+            handle.try_get(&other).err(),
+            Some(SandboxErrorKind::WrongSandbox)
+        );
+        assert!(SandboxHandle::new_nullable(std::ptr::null::<Opaque>(), &sandbox).is_none());
+        assert_eq!(
+            SandboxHandle::try_new(std::ptr::null::<Opaque>(), &sandbox).err(),
+            Some(SandboxErrorKind::Null)
+        );
+    }
+
+    #[test]
+    fn read_checks_field_address() {
+        let sandbox = Sandbox::new();
+        let mut data = TestRegion::new(2, RegionKind::Data);
+        let field = unsafe { SandboxPtr::new_unchecked(data.ptr::<*const u32>(1)) };
+        assert_eq!(
+            field.try_read(&sandbox).err(),
+            Some(SandboxErrorKind::Misaligned)
+        );
+        let field = unsafe { SandboxPtr::new_unchecked(std::ptr::null::<*const u32>()) };
+        assert_eq!(field.try_read(&sandbox).err(), Some(SandboxErrorKind::Null));
+    }
+
+    #[test]
+    fn read_checks_function_pointer() {
+        let sandbox = Sandbox::new();
+        let mut data = TestRegion::new(2, RegionKind::Data);
+        let text = TestRegion::new(1, RegionKind::Text);
+        data.write(0, text.region.start);
+        data.write(8, read_checks_function_pointer as fn() as usize);
+
+        type F = unsafe extern "C" fn();
+        let field = |data: &mut TestRegion, offset| {
+            SandboxPtr::new(data.ptr::<Option<SandboxFnPtr<F>>>(offset))
+        };
+        let f = field(&mut data, 0).read(&sandbox).unwrap();
+        assert_eq!(f.get() as usize, text.region.start);
+        assert_eq!(
+            field(&mut data, 8).try_read(&sandbox).err(),
+            Some(SandboxErrorKind::CodeOutOfSandbox)
+        );
+        data.write(0, 0usize);
+        assert!(field(&mut data, 0).read(&sandbox).is_none());
+        data.write(0, usize::MAX);
+        assert_eq!(
+            field(&mut data, 0).try_read(&sandbox).err(),
+            Some(SandboxErrorKind::CodeOutOfSandbox)
+        );
     }
 }
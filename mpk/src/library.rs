@@ -0,0 +1,792 @@
+//! Loading of shared libraries into a sandbox.
+//!
+//! The loader maps a library's segments itself instead of going through the system dynamic linker,
+//! so that the library's imports only ever resolve to the functions explicitly handed to it, and so
+//! that its writable memory (including the GOT) can be tagged with the sandbox's protection key.
+use std::{collections::HashMap, ffi::c_void, io, path::Path, ptr::null_mut};
+
+use crate::region::{self, Region, RegionKind};
+use crate::Sandbox;
+
+const PAGE_SIZE: usize = 4096;
+
+const ET_DYN: u16 = 3;
+const EM_X86_64: u16 = 62;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_TLS: u32 = 7;
+const PT_GNU_RELRO: u32 = 0x6474e552;
+
+const PF_X: u32 = 0x1;
+const PF_W: u32 = 0x2;
+const PF_R: u32 = 0x4;
+
+const DT_NULL: i64 = 0;
+const DT_PLTRELSZ: i64 = 2;
+const DT_HASH: i64 = 4;
+const DT_STRTAB: i64 = 5;
+const DT_SYMTAB: i64 = 6;
+const DT_RELA: i64 = 7;
+const DT_RELASZ: i64 = 8;
+const DT_INIT: i64 = 12;
+const DT_REL: i64 = 17;
+const DT_PLTREL: i64 = 20;
+const DT_JMPREL: i64 = 23;
+const DT_INIT_ARRAY: i64 = 25;
+const DT_INIT_ARRAYSZ: i64 = 27;
+const DT_GNU_HASH: i64 = 0x6ffffef5;
+
+const SHN_UNDEF: u16 = 0;
+const STB_WEAK: u8 = 2;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const STT_GNU_IFUNC: u8 = 10;
+
+const R_X86_64_NONE: u32 = 0;
+const R_X86_64_64: u32 = 1;
+const R_X86_64_GLOB_DAT: u32 = 6;
+const R_X86_64_JUMP_SLOT: u32 = 7;
+const R_X86_64_RELATIVE: u32 = 8;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn unsupported(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, msg.into())
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> io::Result<[u8; N]> {
+    offset
+        .checked_add(N)
+        .and_then(|end| data.get(offset..end))
+        .map(|b| b.try_into().unwrap())
+        .ok_or_else(|| invalid("unexpected end of ELF data"))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> io::Result<u16> {
+    read(data, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> io::Result<u32> {
+    read(data, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> io::Result<u64> {
+    read(data, offset).map(u64::from_le_bytes)
+}
+
+/// Returns the offset of entry `index` of a table of `size`-byte entries at `base`, checking for
+/// overflow, since all three may come from the file.
+fn entry(base: usize, index: usize, size: usize) -> io::Result<usize> {
+    index
+        .checked_mul(size)
+        .and_then(|offset| base.checked_add(offset))
+        .ok_or_else(|| invalid("offset out of range"))
+}
+
+/// The symbols a sandboxed library is allowed to import.
+#[derive(Clone, Debug, Default)]
+pub struct Imports {
+    symbols: HashMap<String, usize>,
+}
+
+impl Imports {
+    pub fn new() -> Imports {
+        Imports::default()
+    }
+
+    /// Makes `address` available to loaded libraries under `name`.
+    ///
+    /// # Safety
+    ///
+    /// Sandboxed code may call or access the symbol at any time, with any arguments, and with the
+    /// privileges of the sandbox. It must not be able to use it to access memory outside the
+    /// sandbox.
+    pub unsafe fn insert(&mut self, name: impl Into<String>, address: *const c_void) {
+        self.symbols.insert(name.into(), address as usize);
+    }
+}
+
+/// A shared library loaded into a sandbox.
+///
+/// Dropping the library unmaps it without running its finalizers.
+pub struct Library {
+    mapping: *mut c_void,
+    size: usize,
+    exports: HashMap<String, usize>,
+    regions: Vec<Region>,
+}
+
+unsafe impl Send for Library {}
+
+impl Library {
+    /// Returns the address of a function or variable exported by the library.
+    pub fn get(&self, name: &str) -> Option<*const c_void> {
+        self.exports.get(name).map(|&addr| addr as *const c_void)
+    }
+
+    /// Returns the regions occupied by the library.
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        for region in &self.regions {
+            region::unregister(*region);
+        }
+        unsafe {
+            libc::munmap(self.mapping, self.size);
+        }
+    }
+}
+
+struct Segment {
+    ty: u32,
+    flags: u32,
+    offset: usize,
+    vaddr: usize,
+    filesz: usize,
+    memsz: usize,
+}
+
+struct Symbol {
+    name: String,
+    info: u8,
+    shndx: u16,
+    value: usize,
+}
+
+impl Symbol {
+    fn bind(&self) -> u8 {
+        self.info >> 4
+    }
+
+    fn ty(&self) -> u8 {
+        self.info & 0xf
+    }
+}
+
+/// A library image that has been mapped read-write but not yet exposed to the sandbox.
+struct Image {
+    mapping: *mut c_void,
+    size: usize,
+    /// The lowest virtual address of the library, which is placed at the start of the mapping.
+    bias: usize,
+}
+
+impl Image {
+    fn base(&self) -> usize {
+        (self.mapping as usize).wrapping_sub(self.bias)
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.mapping as *const u8, self.size) }
+    }
+
+    /// Returns the offset into the mapping of the virtual address `vaddr`.
+    fn offset(&self, vaddr: usize) -> io::Result<usize> {
+        self.range(vaddr, 1)
+    }
+
+    /// Returns the offset into the mapping of the `len` bytes at the virtual address `vaddr`,
+    /// checking that they lie entirely within the mapping.
+    fn range(&self, vaddr: usize, len: usize) -> io::Result<usize> {
+        vaddr
+            .checked_sub(self.bias)
+            .filter(|&offset| offset.checked_add(len).is_some_and(|end| end <= self.size))
+            .ok_or_else(|| invalid("address lies outside the library"))
+    }
+
+    fn write_u64(&mut self, vaddr: usize, value: u64) -> io::Result<()> {
+        let offset = self
+            .range(vaddr, 8)
+            .map_err(|_| invalid("relocation lies outside the library"))?;
+        unsafe {
+            self.mapping
+                .byte_add(offset)
+                .cast::<u64>()
+                .write_unaligned(value)
+        };
+        Ok(())
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        if !self.mapping.is_null() {
+            unsafe {
+                libc::munmap(self.mapping, self.size);
+            }
+        }
+    }
+}
+
+fn parse_segments(file: &[u8]) -> io::Result<Vec<Segment>> {
+    if read::<4>(file, 0)? != *b"\x7fELF" {
+        return Err(invalid("not an ELF file"));
+    }
+    if file[4] != 2 || file[5] != 1 {
+        return Err(unsupported(
+            "only 64-bit little-endian libraries are supported",
+        ));
+    }
+    if read_u16(file, 0x10)? != ET_DYN {
+        return Err(invalid("not a shared library"));
+    }
+    if read_u16(file, 0x12)? != EM_X86_64 {
+        return Err(unsupported("only x86-64 libraries are supported"));
+    }
+
+    let phoff = read_u64(file, 0x20)? as usize;
+    let phentsize = read_u16(file, 0x36)? as usize;
+    let phnum = read_u16(file, 0x38)? as usize;
+    (0..phnum)
+        .map(|i| {
+            let ph = entry(phoff, i, phentsize)?;
+            Ok(Segment {
+                ty: read_u32(file, ph)?,
+                flags: read_u32(file, ph + 4)?,
+                offset: read_u64(file, ph + 8)? as usize,
+                vaddr: read_u64(file, ph + 16)? as usize,
+                filesz: read_u64(file, ph + 32)? as usize,
+                memsz: read_u64(file, ph + 40)? as usize,
+            })
+        })
+        .collect()
+}
+
+/// Maps the loadable segments of a library read-write.
+fn map_segments(file: &[u8], segments: &[Segment]) -> io::Result<Image> {
+    let loads = || segments.iter().filter(|s| s.ty == PT_LOAD);
+    let lo = loads()
+        .map(|s| s.vaddr)
+        .min()
+        .ok_or_else(|| invalid("library has no loadable segments"))?
+        & !(PAGE_SIZE - 1);
+    let hi = loads()
+        .map(|s| s.vaddr.checked_add(s.memsz))
+        .collect::<Option<Vec<_>>>()
+        .and_then(|ends| ends.into_iter().max())
+        .and_then(|hi| hi.checked_next_multiple_of(PAGE_SIZE))
+        .ok_or_else(|| invalid("segment lies outside the address space"))?;
+
+    let mapping = unsafe {
+        libc::mmap(
+            null_mut(),
+            hi - lo,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_ANONYMOUS | libc::MAP_PRIVATE,
+            -1,
+            0,
+        )
+    };
+    if mapping == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    let image = Image {
+        mapping,
+        size: hi - lo,
+        bias: lo,
+    };
+
+    for segment in loads() {
+        if segment.filesz > segment.memsz {
+            return Err(invalid("segment is larger in the file than in memory"));
+        }
+        let contents = segment
+            .offset
+            .checked_add(segment.filesz)
+            .and_then(|end| file.get(segment.offset..end))
+            .ok_or_else(|| invalid("segment extends past the end of the file"))?;
+        let offset = image.range(segment.vaddr, segment.memsz)?;
+        unsafe {
+            image
+                .mapping
+                .byte_add(offset)
+                .cast::<u8>()
+                .copy_from_nonoverlapping(contents.as_ptr(), contents.len());
+        }
+    }
+
+    Ok(image)
+}
+
+/// The entries of the dynamic section the loader understands.
+#[derive(Default)]
+struct Dynamic {
+    strtab: Option<usize>,
+    symtab: Option<usize>,
+    hash: Option<usize>,
+    gnu_hash: Option<usize>,
+    rela: Option<usize>,
+    relasz: usize,
+    jmprel: Option<usize>,
+    pltrelsz: usize,
+    init: Option<usize>,
+    init_array: Option<usize>,
+    init_arraysz: usize,
+}
+
+fn parse_dynamic(image: &Image, segments: &[Segment]) -> io::Result<Dynamic> {
+    let segment = segments
+        .iter()
+        .find(|s| s.ty == PT_DYNAMIC)
+        .ok_or_else(|| invalid("library has no dynamic section"))?;
+    let bytes = image.bytes();
+    let start = image.range(segment.vaddr, segment.memsz)?;
+
+    let mut dynamic = Dynamic::default();
+    for entry in (start..start + segment.memsz).step_by(16) {
+        let tag = read_u64(bytes, entry)? as i64;
+        let val = read_u64(bytes, entry + 8)? as usize;
+        match tag {
+            DT_NULL => break,
+            DT_STRTAB => dynamic.strtab = Some(val),
+            DT_SYMTAB => dynamic.symtab = Some(val),
+            DT_HASH => dynamic.hash = Some(val),
+            DT_GNU_HASH => dynamic.gnu_hash = Some(val),
+            DT_RELA => dynamic.rela = Some(val),
+            DT_RELASZ => dynamic.relasz = val,
+            DT_JMPREL => dynamic.jmprel = Some(val),
+            DT_PLTRELSZ => dynamic.pltrelsz = val,
+            DT_PLTREL if val as i64 != DT_RELA => {
+                return Err(unsupported("only RELA relocations are supported"))
+            }
+            DT_REL => return Err(unsupported("only RELA relocations are supported")),
+            DT_INIT => dynamic.init = Some(val),
+            DT_INIT_ARRAY => dynamic.init_array = Some(val),
+            DT_INIT_ARRAYSZ => dynamic.init_arraysz = val,
+            _ => {}
+        }
+    }
+    Ok(dynamic)
+}
+
+/// Returns the number of entries in the dynamic symbol table.
+fn symbol_count(image: &Image, dynamic: &Dynamic) -> io::Result<usize> {
+    let bytes = image.bytes();
+    if let Some(hash) = dynamic.hash {
+        // The number of chain entries equals the number of symbols.
+        return Ok(read_u32(bytes, image.range(hash, 8)? + 4)? as usize);
+    }
+
+    let hash = dynamic
+        .gnu_hash
+        .ok_or_else(|| invalid("library has no symbol hash table"))?;
+    let hash = image.range(hash, 16)?;
+    let nbuckets = read_u32(bytes, hash)? as usize;
+    let symoffset = read_u32(bytes, hash + 4)? as usize;
+    let bloom_size = read_u32(bytes, hash + 8)? as usize;
+    let buckets = entry(hash + 16, bloom_size, 8)?;
+    let chains = entry(buckets, nbuckets, 4)?;
+
+    // The highest symbol index is the end of the chain starting at the highest bucket. Symbols
+    // below `symoffset` are not hashed.
+    let mut last = 0;
+    for i in 0..nbuckets {
+        last = last.max(read_u32(bytes, entry(buckets, i, 4)?)? as usize);
+    }
+    if last < symoffset {
+        return Ok(symoffset);
+    }
+    while read_u32(bytes, entry(chains, last - symoffset, 4)?)? & 1 == 0 {
+        last += 1;
+    }
+    Ok(last + 1)
+}
+
+fn parse_symbols(image: &Image, dynamic: &Dynamic) -> io::Result<Vec<Symbol>> {
+    let bytes = image.bytes();
+    let strtab = image.offset(
+        dynamic
+            .strtab
+            .ok_or_else(|| invalid("library has no string table"))?,
+    )?;
+    let symtab = image.offset(
+        dynamic
+            .symtab
+            .ok_or_else(|| invalid("library has no symbol table"))?,
+    )?;
+
+    (0..symbol_count(image, dynamic)?)
+        .map(|i| {
+            let sym = entry(symtab, i, 24)?;
+            let name = strtab + read_u32(bytes, sym)? as usize;
+            let name = bytes
+                .get(name..)
+                .and_then(|s| std::ffi::CStr::from_bytes_until_nul(s).ok())
+                .ok_or_else(|| invalid("invalid symbol name"))?;
+            Ok(Symbol {
+                name: name.to_string_lossy().into_owned(),
+                info: read::<1>(bytes, sym + 4)?[0],
+                shndx: read_u16(bytes, sym + 6)?,
+                value: read_u64(bytes, sym + 8)? as usize,
+            })
+        })
+        .collect()
+}
+
+fn resolve(image: &Image, symbol: &Symbol, imports: &Imports) -> io::Result<usize> {
+    if symbol.ty() == STT_GNU_IFUNC {
+        return Err(unsupported(format!(
+            "indirect function `{}` is not supported",
+            symbol.name
+        )));
+    }
+    if symbol.shndx != SHN_UNDEF {
+        return Ok(image.base().wrapping_add(symbol.value));
+    }
+    match imports.symbols.get(&symbol.name) {
+        Some(&address) => Ok(address),
+        None if symbol.bind() == STB_WEAK => Ok(0),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("undefined symbol `{}`", symbol.name),
+        )),
+    }
+}
+
+fn relocate(
+    image: &mut Image,
+    table: Option<usize>,
+    size: usize,
+    symbols: &[Symbol],
+    imports: &Imports,
+) -> io::Result<()> {
+    let Some(table) = table else {
+        return Ok(());
+    };
+    let start = image.range(table, size)?;
+    for rela in (start..start + size).step_by(24) {
+        let bytes = image.bytes();
+        let offset = read_u64(bytes, rela)? as usize;
+        let info = read_u64(bytes, rela + 8)?;
+        let addend = read_u64(bytes, rela + 16)?;
+        let (sym, ty) = ((info >> 32) as usize, info as u32);
+
+        let symbol = || {
+            if sym == 0 {
+                return Ok(0);
+            }
+            symbols
+                .get(sym)
+                .ok_or_else(|| invalid("relocation refers to a nonexistent symbol"))
+                .and_then(|s| resolve(image, s, imports))
+        };
+        let value = match ty {
+            R_X86_64_NONE => continue,
+            R_X86_64_64 => (symbol()? as u64).wrapping_add(addend),
+            R_X86_64_GLOB_DAT | R_X86_64_JUMP_SLOT => symbol()? as u64,
+            R_X86_64_RELATIVE => (image.base() as u64).wrapping_add(addend),
+            _ => return Err(unsupported(format!("unsupported relocation type {ty}"))),
+        };
+        image.write_u64(offset, value)?;
+    }
+    Ok(())
+}
+
+fn protection(flags: u32) -> i32 {
+    let mut prot = libc::PROT_NONE;
+    if flags & PF_R != 0 {
+        prot |= libc::PROT_READ;
+    }
+    if flags & PF_W != 0 {
+        prot |= libc::PROT_WRITE;
+    }
+    if flags & PF_X != 0 {
+        prot |= libc::PROT_EXEC;
+    }
+    prot
+}
+
+/// Applies `prot` to the pages covering `[start, end)`, tagging them with `pkey` if present.
+#[allow(unused_variables)]
+fn protect(start: usize, end: usize, prot: i32, pkey: Option<i32>) -> io::Result<()> {
+    let start = start & !(PAGE_SIZE - 1);
+    let len = end.next_multiple_of(PAGE_SIZE) - start;
+
+    #[cfg(feature = "mpk")]
+    if let Some(pkey) = pkey {
+        let err = unsafe { crate::sandbox::pkey_mprotect(start as *mut c_void, len, prot, pkey) };
+        if err < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        return Ok(());
+    }
+
+    if unsafe { libc::mprotect(start as *mut c_void, len, prot) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+impl Sandbox {
+    /// Loads a shared library into the sandbox.
+    ///
+    /// The library's imports are resolved only against `imports`; libraries it depends on are not
+    /// loaded. Its writable segments, including the GOT, are tagged with the sandbox's protection
+    /// key and registered as sandbox data, and its initializers are run inside the sandbox.
+    /// Libraries using thread-local storage or indirect functions are not supported.
+    ///
+    /// # Safety
+    ///
+    /// The library must be a sandboxed library: its code runs with the sandbox's privileges, but
+    /// nothing prevents it from making system calls.
+    pub unsafe fn load_library(
+        &mut self,
+        path: impl AsRef<Path>,
+        imports: &Imports,
+    ) -> io::Result<Library> {
+        let file = std::fs::read(path)?;
+        let segments = parse_segments(&file)?;
+        if segments.iter().any(|s| s.ty == PT_TLS) {
+            return Err(unsupported("thread-local storage is not supported"));
+        }
+
+        let mut image = map_segments(&file, &segments)?;
+        let dynamic = parse_dynamic(&image, &segments)?;
+        let symbols = parse_symbols(&image, &dynamic)?;
+        relocate(&mut image, dynamic.rela, dynamic.relasz, &symbols, imports)?;
+        relocate(
+            &mut image,
+            dynamic.jmprel,
+            dynamic.pltrelsz,
+            &symbols,
+            imports,
+        )?;
+
+        let exports = symbols
+            .iter()
+            .filter(|s| s.shndx != SHN_UNDEF && matches!(s.ty(), STT_FUNC | STT_OBJECT))
+            .map(|s| (s.name.clone(), image.base().wrapping_add(s.value)))
+            .collect();
+
+        let mut initializers = Vec::new();
+        if let Some(init) = dynamic.init {
+            initializers.push(image.base().wrapping_add(init));
+        }
+        if let Some(init_array) = dynamic.init_array {
+            let start = image.range(init_array, dynamic.init_arraysz)?;
+            for entry in (start..start + dynamic.init_arraysz).step_by(8) {
+                initializers.push(read_u64(image.bytes(), entry)? as usize);
+            }
+        }
+
+        // All relocations are resolved eagerly, so the entire image can now be made inaccessible
+        // except for the loadable segments, and the RELRO region (holding the GOT) read-only.
+        let pkey = self.pkey();
+        protect(
+            image.mapping as usize,
+            image.mapping as usize + image.size,
+            libc::PROT_NONE,
+            None,
+        )?;
+        // The addresses of all segments were checked to lie within the mapping above.
+        let bounds = |segment: &Segment| {
+            let start = image.mapping as usize + image.range(segment.vaddr, segment.memsz)?;
+            io::Result::Ok((start, start + segment.memsz))
+        };
+        let mut relro = Vec::new();
+        for segment in segments.iter().filter(|s| s.ty == PT_GNU_RELRO) {
+            let (start, end) = bounds(segment)?;
+            // The RELRO region is protected in whole pages, and its end is rounded down so that it
+            // does not cover the rest of the writable segment.
+            let (start, end) = (start & !(PAGE_SIZE - 1), end & !(PAGE_SIZE - 1));
+            if start < end {
+                relro.push((start, end));
+            }
+        }
+        let mut regions = Vec::new();
+        for segment in segments.iter().filter(|s| s.ty == PT_LOAD) {
+            let (start, end) = bounds(segment)?;
+            let writable = segment.flags & PF_W != 0;
+            protect(
+                start,
+                end,
+                protection(segment.flags),
+                if writable { pkey } else { None },
+            )?;
+
+            if !writable {
+                let kind = if segment.flags & PF_X != 0 {
+                    RegionKind::Text
+                } else {
+                    RegionKind::ReadOnly
+                };
+                regions.push(Region { start, end, kind });
+                continue;
+            }
+            // The parts of a writable segment that are read-only after relocation are registered
+            // as such, so that they are not mistaken for writable sandbox data.
+            let mut data_start = start;
+            for &(relro_start, relro_end) in &relro {
+                let (relro_start, relro_end) = (relro_start.max(start), relro_end.min(end));
+                if relro_start >= relro_end {
+                    continue;
+                }
+                if data_start < relro_start {
+                    regions.push(Region {
+                        start: data_start,
+                        end: relro_start,
+                        kind: RegionKind::Data,
+                    });
+                }
+                regions.push(Region {
+                    start: relro_start,
+                    end: relro_end,
+                    kind: RegionKind::ReadOnly,
+                });
+                data_start = relro_end;
+            }
+            if data_start < end {
+                regions.push(Region {
+                    start: data_start,
+                    end,
+                    kind: RegionKind::Data,
+                });
+            }
+        }
+        for &(start, end) in &relro {
+            protect(start, end, libc::PROT_READ, pkey)?;
+        }
+
+        let library = Library {
+            mapping: std::mem::replace(&mut image.mapping, null_mut()),
+            size: image.size,
+            exports,
+            regions,
+        };
+        for region in &library.regions {
+            region::register(*region);
+        }
+
+        for init in initializers {
+            let init: extern "C" fn() = std::mem::transmute(init);
+            self.call(move || init());
+        }
+
+        Ok(library)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, process::Command};
+
+    use super::*;
+
+    /// Compiles `source` into a shared library that does not depend on the C library.
+    fn compile(name: &str, source: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mpk-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let c = dir.join("lib.c");
+        let lib = dir.join("lib.so");
+        fs::write(&c, source).unwrap();
+        let status = Command::new("cc")
+            .args([
+                "-shared",
+                "-fPIC",
+                "-nostdlib",
+                "-O2",
+                "-Wl,-z,relro,-z,now",
+                "-o",
+            ])
+            .arg(&lib)
+            .arg(&c)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        lib
+    }
+
+    const SOURCE: &str = "
+        int value = 41;
+        int *const pointer = &value;
+        int get(void) { return *pointer + 1; }
+    ";
+
+    #[test]
+    fn loads_and_registers_library() {
+        let path = compile("load", SOURCE);
+        let mut sandbox = Sandbox::new();
+        let library = unsafe { sandbox.load_library(&path, &Imports::new()) }.unwrap();
+
+        let get: extern "C" fn() -> i32 =
+            unsafe { std::mem::transmute(library.get("get").unwrap()) };
+        assert_eq!(unsafe { sandbox.call(move || get()) }, 42);
+
+        let value = library.get("value").unwrap() as usize;
+        let pointer = library.get("pointer").unwrap() as usize;
+        assert!(region::contains(value, value + 4, RegionKind::Data));
+        // The pointer is relocated and then lies in the RELRO region, which the sandbox cannot
+        // write.
+        assert!(region::contains(pointer, pointer + 8, RegionKind::ReadOnly));
+        assert!(!region::contains(pointer, pointer + 8, RegionKind::Data));
+        assert!(library.regions().iter().all(|r| r.start < r.end));
+        for (i, a) in library.regions().iter().enumerate() {
+            for b in &library.regions()[i + 1..] {
+                assert!(a.end <= b.start || b.end <= a.start, "{a:?} overlaps {b:?}");
+            }
+        }
+
+        let regions = library.regions().to_vec();
+        drop(library);
+        assert!(!regions
+            .iter()
+            .any(|r| region::contains(r.start, r.end, r.kind)));
+    }
+
+    #[test]
+    fn rejects_truncated_library() {
+        let file = fs::read(compile("truncated", SOURCE)).unwrap();
+        for len in [0, 16, 64, 100] {
+            let err = parse_segments(&file[..len]).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn rejects_segments_outside_address_space() {
+        let segment = Segment {
+            ty: PT_LOAD,
+            flags: PF_R,
+            offset: 0,
+            vaddr: usize::MAX - 16,
+            filesz: 0,
+            memsz: 32,
+        };
+        let err = map_segments(&[], &[segment]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let segment = Segment {
+            ty: PT_LOAD,
+            flags: PF_R,
+            offset: usize::MAX,
+            vaddr: 0,
+            filesz: 2,
+            memsz: 2,
+        };
+        let err = map_segments(&[0; 16], &[segment]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_relocations_outside_image() {
+        let file = fs::read(compile("relocations", SOURCE)).unwrap();
+        let segments = parse_segments(&file).unwrap();
+        let mut image = map_segments(&file, &segments).unwrap();
+        let end = image.bias + image.size;
+
+        image.write_u64(image.bias, 0).unwrap();
+        image.write_u64(end - 8, 0).unwrap();
+        for vaddr in [end - 4, end, image.bias.wrapping_sub(8), usize::MAX - 3] {
+            let err = image.write_u64(vaddr, 0).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+        assert!(image.range(image.bias, usize::MAX).is_err());
+    }
+}
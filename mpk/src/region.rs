@@ -0,0 +1,156 @@
+//! Registry of the memory regions that belong to sandboxes.
+use std::{
+    ptr::addr_of,
+    sync::{Once, RwLock},
+};
+
+// `sandbox-build` moves the writable data of every sandboxed object into the `sandbox_data`
+// section and its zero-initialized data into `sandbox_bss`, its read-only data into
+// `sandbox_rodata` and its code into `sandbox_text`, and the linker defines these symbols around
+// them.
+#[allow(non_upper_case_globals)]
+extern "C" {
+    static __start_sandbox_data: libc::c_void;
+    static __stop_sandbox_data: libc::c_void;
+    static __start_sandbox_bss: libc::c_void;
+    static __stop_sandbox_bss: libc::c_void;
+    static __start_sandbox_rodata: libc::c_void;
+    static __stop_sandbox_rodata: libc::c_void;
+    static __start_sandbox_text: libc::c_void;
+    static __stop_sandbox_text: libc::c_void;
+}
+
+// Empty, page-aligned members of each section, so that the sections (and the symbols bounding
+// them) exist even if no statically linked sandboxed library is present. Nothing refers to the
+// anchors, so they are marked as retained (`R`, `SHF_GNU_RETAIN`) to survive `--gc-sections`.
+// With `-z start-stop-gc`, the `__start_`/`__stop_` references above would not keep them alive
+// otherwise, and the symbols would be undefined. The `int3` is never executed; it only keeps the
+// code section from being empty.
+std::arch::global_asm!(
+    ".pushsection sandbox_data, \"awR\", @progbits",
+    ".balign 4096",
+    ".popsection",
+    ".pushsection sandbox_bss, \"awR\", @nobits",
+    ".balign 4096",
+    ".popsection",
+    ".pushsection sandbox_rodata, \"awR\", @progbits",
+    ".balign 4096",
+    ".popsection",
+    ".pushsection sandbox_text, \"axR\", @progbits",
+    "int3",
+    ".popsection"
+);
+
+/// Returns the bounds of the statically linked sandbox data.
+pub(crate) fn sandbox_region() -> (*const libc::c_void, *const libc::c_void) {
+    (
+        addr_of!(__start_sandbox_data),
+        addr_of!(__stop_sandbox_data),
+    )
+}
+
+/// Returns the bounds of the statically linked zero-initialized sandbox data.
+pub(crate) fn sandbox_bss() -> (*const libc::c_void, *const libc::c_void) {
+    (addr_of!(__start_sandbox_bss), addr_of!(__stop_sandbox_bss))
+}
+
+/// Returns the bounds of the read-only data of statically linked sandboxed libraries.
+fn sandbox_rodata() -> (*const libc::c_void, *const libc::c_void) {
+    (
+        addr_of!(__start_sandbox_rodata),
+        addr_of!(__stop_sandbox_rodata),
+    )
+}
+
+/// Makes the read-only data of statically linked sandboxed libraries read-only. Moving it out of
+/// `.rodata` and `.data.rel.ro` loses the protection the linker and the dynamic linker apply, so
+/// this must happen before sandboxed code first runs.
+pub(crate) fn protect_rodata() {
+    static PROTECT: Once = Once::new();
+    PROTECT.call_once(|| {
+        let (start, end) = sandbox_rodata();
+        let len = end as usize - start as usize;
+        // The section is page-aligned and padded, so it can be protected on its own.
+        if len != 0 && unsafe { libc::mprotect(start as *mut _, len, libc::PROT_READ) } < 0 {
+            panic!(
+                "could not protect sandbox read-only data: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    });
+}
+
+/// Returns the bounds of the code of statically linked sandboxed libraries.
+fn sandbox_text() -> (*const libc::c_void, *const libc::c_void) {
+    (
+        addr_of!(__start_sandbox_text),
+        addr_of!(__stop_sandbox_text),
+    )
+}
+
+/// What a sandbox region contains.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegionKind {
+    /// Data the sandbox can write.
+    Data,
+    /// Data the sandbox can only read.
+    ReadOnly,
+    /// Sandboxed code.
+    Text,
+}
+
+/// A range of addresses `[start, end)` belonging to a sandbox.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+    pub kind: RegionKind,
+}
+
+/// Regions of dynamically loaded libraries. The statically linked region is not listed here, since
+/// it is always present.
+static REGIONS: RwLock<Vec<Region>> = RwLock::new(Vec::new());
+
+pub(crate) fn register(region: Region) {
+    REGIONS.write().unwrap().push(region);
+}
+
+pub(crate) fn unregister(region: Region) {
+    let mut regions = REGIONS.write().unwrap();
+    if let Some(i) = regions.iter().position(|r| *r == region) {
+        regions.swap_remove(i);
+    }
+}
+
+/// Returns whether the range `[start, end)` lies entirely within one sandbox region of the given
+/// kind.
+pub fn contains(start: usize, end: usize, kind: RegionKind) -> bool {
+    let within = |r: &Region| r.kind == kind && r.start <= start && end <= r.end;
+
+    let static_region = |(start, end): (*const libc::c_void, *const libc::c_void), kind| Region {
+        start: start as usize,
+        end: end as usize,
+        kind,
+    };
+    within(&static_region(sandbox_region(), RegionKind::Data))
+        || within(&static_region(sandbox_bss(), RegionKind::Data))
+        || within(&static_region(sandbox_rodata(), RegionKind::ReadOnly))
+        || within(&static_region(sandbox_text(), RegionKind::Text))
+        || REGIONS.read().unwrap().iter().any(within)
+}
+
+/// Returns the end of the sandbox data region containing `addr`, bounding reads that search for
+/// the end of a value, such as a string's terminator.
+pub(crate) fn data_end(addr: usize) -> Option<usize> {
+    for (start, end) in [sandbox_region(), sandbox_bss()] {
+        if start as usize <= addr && addr < end as usize {
+            return Some(end as usize);
+        }
+    }
+    REGIONS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|r| r.kind == RegionKind::Data && r.start <= addr && addr < r.end)
+        .map(|r| r.end)
+}
@@ -1,94 +1,251 @@
 #[allow(unused)]
 use super::*;
 #[allow(unused)]
-use std::{arch::asm, mem::ManuallyDrop, ptr::null_mut};
+use std::{
+    arch::asm,
+    cell::Cell,
+    mem::ManuallyDrop,
+    ptr::null_mut,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
 
 #[allow(unused)]
 pub struct Sandbox {
+    id: u64,
     pkey: Option<std::io::Result<i32>>,
     stack: *mut libc::c_void,
+    pending: Arc<PendingFrees>,
 }
 
+/// Allocations that owned values such as [`SandboxCString`] gave up when they were dropped, as
+/// pointers along with the sandboxed function that frees them. Dropping a value cannot enter the
+/// sandbox, which is borrowed by whoever calls into it, so they are freed on the next call instead.
+pub(crate) type PendingFrees = Mutex<Vec<(usize, unsafe extern "C" fn(*mut libc::c_void))>>;
+
 unsafe impl Send for Sandbox {}
 
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(feature = "mpk")]
 const SANDBOX_STACK_SIZE: usize = 1 << 23; // 8 MiB
 
 #[cfg(feature = "mpk")]
 static mut RETURNSTACK: *mut libc::c_void = null_mut();
 
+/// The identity of the next sandbox created.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Whether the current thread is running a function passed to a sandbox.
+    static INSIDE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the current thread as running sandboxed code until it is dropped. Nothing keeps a function
+/// running in a sandbox from reaching another sandbox (or the same one, through a global) and
+/// calling into it, which would overwrite the saved host stack, so that is checked at runtime.
+struct Inside(());
+
+impl Inside {
+    fn enter() -> Inside {
+        if INSIDE.with(|inside| inside.replace(true)) {
+            panic!("cannot enter a sandbox while running in one");
+        }
+        Inside(())
+    }
+}
+
+impl Drop for Inside {
+    fn drop(&mut self) {
+        INSIDE.with(|inside| inside.set(false));
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        // A sandbox moved into a function running in another one is dropped there, and cannot be
+        // entered to free what is still pending, which is then leaked.
+        if INSIDE.with(Cell::get) {
+            return;
+        }
+        let _inside = Inside::enter();
+        unsafe { self.free_pending() };
+    }
+}
+
 #[allow(unused)]
 const PKEY_DISABLE_ACCESS: u32 = 0x1;
 #[allow(unused)]
 const PKEY_DISABLE_WRITE: u32 = 0x2;
 
+const ARCH_SHSTK_STATUS: i32 = 0x5005;
+const ARCH_SHSTK_SHSTK: u64 = 0x1;
+
 impl Sandbox {
-    pub const fn new() -> Sandbox {
+    pub fn new() -> Sandbox {
+        region::protect_rodata();
         Sandbox {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
             pkey: None,
             stack: null_mut(),
+            pending: Arc::default(),
         }
     }
 
-    /// Calls a function within the sandbox.
+    /// Calls a function within the sandbox. If the function panics, the panic is resumed after
+    /// leaving the sandbox.
+    ///
+    /// # Panics
+    ///
+    /// If called from within a function running in a sandbox.
     ///
     /// # Safety
     ///
     /// The provided function must not reference data that lives outside the sandbox.
-    #[cfg(feature = "mpk")]
     pub unsafe fn call<T, F: FnOnce() -> T + 'static>(&mut self, f: F) -> T {
-        if self.init().is_none() {
-            // MPK is not available, so just call the function directly
-            return f();
-        };
-
-        assert_eq!(RETURNSTACK, null_mut());
-
-        // Write the closure to the sandbox stack.
-        let sp = self.stack.byte_add(SANDBOX_STACK_SIZE);
-        let sp = sp.cast::<SandboxArgs<T, F>>().sub(1);
-        sp.write(SandboxArgs {
-            f: ManuallyDrop::new(f),
-        });
+        let _inside = Inside::enter();
+        self.free_pending();
+        run(self.stack(), f)
+    }
 
-        let oldpkru = rdpkru();
-        let newpkru = pkru_set(oldpkru, 11, PKEY_DISABLE_WRITE);
-
-        asm!(
-            "
-        mov [rip + {oldstack}], rsp  // Save old stack pointer
-        mov rsp, rdi                 // Switch to new stack
-        wrpkru                       // Switch to sandbox protection
-        call {_sandbox_call}         // Call sandboxed function
-        
-        mov rax, r12
-        xor rcx, rcx
-        xor rdx, rdx
-        wrpkru                       // Restore previous protection
-        mov rsp, [rip + {oldstack}]  // Switch to old stack
-        ",
-            oldstack = sym RETURNSTACK,
-            in ("rdi") sp,          // argument 0 to _sandbox_call
-            in ("rax") newpkru,
-            in ("rcx") 0,
-            inout ("rdx") 0 => _,
-            in ("r12") oldpkru,     // need a callee-preserved register
-            _sandbox_call = sym _sandbox_call::<T, F>,
-            clobber_abi("sysv64")
-        );
+    /// Calls a function within the sandbox, passing it the sandbox, e.g. to create and check
+    /// handles. This lets a batch of calls into sandboxed code share a single transition. Since
+    /// the sandbox is only borrowed, the function cannot enter it again.
+    ///
+    /// Nothing limits the function to calling the sandboxed library, so running it is unsafe, as
+    /// is the `batch` method of generated bindings that wraps it:
+    ///
+    /// ```compile_fail,E0133
+    /// let mut sandbox = mpk::Sandbox::new();
+    /// let host = Box::new(0);
+    /// sandbox.call_batch(move |_| *host + 1);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If called from within a function running in a sandbox.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Sandbox::call`].
+    pub unsafe fn call_batch<T, F: FnOnce(&Sandbox) -> T + 'static>(&mut self, f: F) -> T {
+        let _inside = Inside::enter();
+        self.free_pending();
+        let stack = self.stack();
+        let this: &Sandbox = self;
+        let sandbox: *const Sandbox = this;
+        run(stack, move || f(&*sandbox))
+    }
 
-        RETURNSTACK = null_mut();
-        ManuallyDrop::into_inner(sp.read().result)
+    /// Returns the queue of allocations to free on the next call into this sandbox.
+    pub(crate) fn pending_frees(&self) -> &Arc<PendingFrees> {
+        &self.pending
     }
 
-    /// Calls a function within the sandbox.
+    /// Frees the allocations that were dropped since the last call into the sandbox, each in its
+    /// own transition so that the closures only capture the pointer and function.
     ///
     /// # Safety
     ///
-    /// The provided function must not reference data that lives outside the sandbox.
+    /// The caller must hold an [`Inside`] guard.
+    unsafe fn free_pending(&mut self) {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        let stack = self.stack();
+        for (ptr, free) in pending {
+            run(stack, move || free(ptr as *mut libc::c_void));
+        }
+    }
+
+    /// Returns the stack to run sandboxed functions on, or null if MPK is not available and they
+    /// are called directly.
+    #[cfg(feature = "mpk")]
+    fn stack(&mut self) -> *mut libc::c_void {
+        match self.init() {
+            Some(_) => self.stack,
+            None => null_mut(),
+        }
+    }
+
     #[cfg(not(feature = "mpk"))]
-    pub unsafe fn call<T, F: FnOnce() -> T + 'static>(&mut self, f: F) -> T {
-        f()
+    fn stack(&mut self) -> *mut libc::c_void {
+        null_mut()
+    }
+
+    /// Returns an identifier that is unique to this sandbox within the process.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns whether the calling thread runs with a CET shadow stack, which keeps sandboxed code
+    /// from redirecting returns into the host.
+    ///
+    /// Shadow stacks need hardware and kernel (Linux 6.6+) support, and the loader only enables
+    /// them if every object in the process is marked as compatible. Linux does not support
+    /// indirect branch tracking in user mode, so IBT markings are never enforced at runtime.
+    pub fn cet_enforced(&self) -> bool {
+        let mut features = 0u64;
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_arch_prctl,
+                ARCH_SHSTK_STATUS,
+                &mut features as *mut u64,
+            )
+        };
+        ret == 0 && features & ARCH_SHSTK_SHSTK != 0
+    }
+
+    /// Maps `len` bytes of zeroed memory that sandboxed code can write, such as a heap for its
+    /// allocator. Pages are only backed once touched, so large reservations are cheap. The memory
+    /// is registered as a sandbox region and stays mapped for the lifetime of the process.
+    pub fn map_memory(&mut self, len: usize) -> std::io::Result<*mut libc::c_void> {
+        let prot = libc::PROT_READ | libc::PROT_WRITE;
+        let memory = unsafe {
+            libc::mmap(
+                null_mut(),
+                len,
+                prot,
+                libc::MAP_ANONYMOUS | libc::MAP_PRIVATE | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if memory == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        #[cfg(feature = "mpk")]
+        if let Some(pkey) = self.pkey() {
+            if unsafe { pkey_mprotect(memory, len, prot, pkey) } < 0 {
+                let err = std::io::Error::last_os_error();
+                unsafe { libc::munmap(memory, len) };
+                return Err(err);
+            }
+        }
+
+        region::register(region::Region {
+            start: memory as usize,
+            end: memory as usize + len,
+            kind: RegionKind::Data,
+        });
+        Ok(memory)
+    }
+
+    /// Returns the protection key of this sandbox, allocating it if necessary, or `None` if MPK
+    /// is not available.
+    #[cfg(feature = "mpk")]
+    pub(crate) fn pkey(&mut self) -> Option<i32> {
+        self.init()
+    }
+
+    #[cfg(not(feature = "mpk"))]
+    pub(crate) fn pkey(&mut self) -> Option<i32> {
+        None
     }
 
     #[cfg(feature = "mpk")]
@@ -108,17 +265,20 @@ impl Sandbox {
             }
 
             unsafe {
-                let sandbox_start = std::ptr::addr_of!(_SANDBOX_START_) as *mut libc::c_void;
-                let sandbox_end = std::ptr::addr_of!(_SANDBOX_END_) as *mut libc::c_void;
+                for (sandbox_start, sandbox_end) in
+                    [region::sandbox_region(), region::sandbox_bss()]
+                {
+                    let sandbox_start = sandbox_start as *mut libc::c_void;
 
-                let err = pkey_mprotect(
-                    sandbox_start,
-                    sandbox_end as usize - sandbox_start as usize,
-                    libc::PROT_READ | libc::PROT_WRITE,
-                    pkey,
-                );
-                if err < 0 {
-                    return Err(std::io::Error::last_os_error());
+                    let err = pkey_mprotect(
+                        sandbox_start,
+                        sandbox_end as usize - sandbox_start as usize,
+                        libc::PROT_READ | libc::PROT_WRITE,
+                        pkey,
+                    );
+                    if err < 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
                 }
 
                 let stack = libc::mmap(
@@ -160,19 +320,122 @@ impl Sandbox {
     }
 }
 
+/// Calls `f` on the sandbox stack `stack`, or directly if it is null.
+#[cfg(feature = "mpk")]
+unsafe fn run<T, F: FnOnce() -> T + 'static>(stack: *mut libc::c_void, f: F) -> T {
+    if stack.is_null() {
+        // MPK is not available, so just call the function directly
+        return f();
+    }
+    enter(stack, f)
+}
+
+#[cfg(not(feature = "mpk"))]
+unsafe fn run<T, F: FnOnce() -> T + 'static>(_stack: *mut libc::c_void, f: F) -> T {
+    f()
+}
+
+/// Switches to the sandbox stack `stack` and protection, which [`Sandbox::init`] has set up, and
+/// calls `f`.
+#[cfg(feature = "mpk")]
+unsafe fn enter<T, F: FnOnce() -> T + 'static>(stack: *mut libc::c_void, f: F) -> T {
+    assert_eq!(std::ptr::addr_of!(RETURNSTACK).read(), null_mut());
+
+    // Write the closure to the sandbox stack.
+    let sp = stack.byte_add(SANDBOX_STACK_SIZE);
+    let sp = sp.cast::<SandboxArgs<T, F>>().sub(1);
+    sp.write(SandboxArgs {
+        f: ManuallyDrop::new(f),
+    });
+
+    let oldpkru = rdpkru();
+    let newpkru = pkru_set(oldpkru, 11, PKEY_DISABLE_WRITE);
+
+    // Every `wrpkru` emitted by this crate records its address in the `mpk_pkru_sites`
+    // section, as a 32-bit offset relative to the entry itself. This allows `sandbox-verify`
+    // to tell the runtime's own protection switches apart from unintended gadgets.
+    asm!(
+        "
+    mov [rip + {oldstack}], rsp  // Save old stack pointer
+    mov rsp, rdi                 // Switch to new stack
+2:  wrpkru                       // Switch to sandbox protection
+    lea rsi, [rip + {_sandbox_call}]
+    call {trampoline}            // Call sandboxed function
+
+    mov rax, r12
+    xor rcx, rcx
+    xor rdx, rdx
+3:  wrpkru                       // Restore previous protection
+    mov rsp, [rip + {oldstack}]  // Switch to old stack
+
+    .pushsection mpk_pkru_sites, \"aR\"
+    .long 2b - .
+    .long 3b - .
+    .popsection
+    ",
+        oldstack = sym RETURNSTACK,
+        in ("rdi") sp,          // argument 0 to _sandbox_call
+        in ("rax") newpkru,
+        in ("rcx") 0,
+        inout ("rdx") 0 => _,
+        in ("r12") oldpkru,     // need a callee-preserved register
+        _sandbox_call = sym _sandbox_call::<T, F>,
+        trampoline = sym mpk_sandbox_trampoline,
+        clobber_abi("sysv64")
+    );
+
+    RETURNSTACK = null_mut();
+    match ManuallyDrop::into_inner(sp.read().result) {
+        Ok(result) => result,
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
+}
+
+// Calls the function in `rsi` with the argument in `rdi`, for `enter`. An unwinder walking out of
+// sandboxed code, e.g. to print the backtrace of a panic, would otherwise find the caller's frame
+// relative to the sandbox stack pointer and read past the top of the sandbox stack, so the
+// trampoline marks its return address as undefined, which ends the walk here.
+#[cfg(feature = "mpk")]
+std::arch::global_asm!(
+    ".pushsection .text.mpk_sandbox_trampoline, \"ax\", @progbits",
+    ".globl mpk_sandbox_trampoline",
+    ".hidden mpk_sandbox_trampoline",
+    ".type mpk_sandbox_trampoline, @function",
+    ".p2align 4",
+    "mpk_sandbox_trampoline:",
+    ".cfi_startproc",
+    ".cfi_undefined rip",
+    "sub rsp, 8",
+    ".cfi_adjust_cfa_offset 8",
+    "call rsi",
+    "add rsp, 8",
+    ".cfi_adjust_cfa_offset -8",
+    "ret",
+    ".cfi_endproc",
+    ".size mpk_sandbox_trampoline, . - mpk_sandbox_trampoline",
+    ".popsection"
+);
+
+#[cfg(feature = "mpk")]
+extern "sysv64" {
+    fn mpk_sandbox_trampoline();
+}
+
 #[cfg(feature = "mpk")]
 #[repr(align(16))]
 union SandboxArgs<T, F: FnOnce() -> T + 'static> {
     f: ManuallyDrop<F>,
-    result: ManuallyDrop<T>,
+    result: ManuallyDrop<std::thread::Result<T>>,
 }
 
+/// Runs the closure on the sandbox stack. A panic must not unwind through the stack switch in
+/// [`Sandbox::call`], so it is caught here and resumed once the host stack and protection are back.
 #[cfg(feature = "mpk")]
 unsafe extern "sysv64" fn _sandbox_call<T, F: FnOnce() -> T + 'static>(
     args: *mut SandboxArgs<T, F>,
 ) {
     let f = ManuallyDrop::into_inner(args.read().f);
-    let result = f();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
     args.write(SandboxArgs {
         result: ManuallyDrop::new(result),
     });
@@ -192,7 +455,18 @@ fn rdpkru() -> u32 {
 #[allow(unused)]
 #[cfg(feature = "mpk")]
 unsafe fn wrpkru(value: u32) {
-    asm!("wrpkru", in("eax") value, options(nostack));
+    asm!(
+        "
+    2:  wrpkru
+        .pushsection mpk_pkru_sites, \"aR\"
+        .long 2b - .
+        .popsection
+        ",
+        in("eax") value,
+        in("ecx") 0,
+        in("edx") 0,
+        options(nostack)
+    );
 }
 
 #[cfg(feature = "mpk")]
@@ -210,6 +484,59 @@ fn pkey_alloc(flags: u32, access_rights: u32) -> i32 {
 }
 
 #[cfg(feature = "mpk")]
-unsafe fn pkey_mprotect(addr: *mut libc::c_void, len: usize, prot: i32, pkey: i32) -> i32 {
+pub(crate) unsafe fn pkey_mprotect(
+    addr: *mut libc::c_void,
+    len: usize,
+    prot: i32,
+    pkey: i32,
+) -> i32 {
     libc::syscall(libc::SYS_pkey_mprotect, addr, len, prot, pkey) as i32
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panics_resume_outside_the_sandbox() {
+        let mut sandbox = Sandbox::new();
+        let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+            sandbox.call(|| -> u32 { std::panic::panic_any(7u32) })
+        }));
+        assert_eq!(*panic.unwrap_err().downcast::<u32>().unwrap(), 7);
+
+        let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+            sandbox.call_batch(|_| panic!("inside the batch"))
+        }));
+        assert_eq!(
+            *panic.unwrap_err().downcast::<&str>().unwrap(),
+            "inside the batch"
+        );
+
+        // The sandbox is left in a usable state.
+        assert_eq!(unsafe { sandbox.call(|| 1) }, 1);
+    }
+
+    #[test]
+    fn nested_calls_panic() {
+        let mut outer = Sandbox::new();
+        let mut inner = Sandbox::new();
+        let nested = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+            outer.call(move || inner.call(|| ()))
+        }));
+        assert!(nested.is_err());
+
+        let mut inner = Sandbox::new();
+        let batched = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+            outer.call_batch(move |_| inner.call_batch(|_| ()))
+        }));
+        assert!(batched.is_err());
+
+        // Leaving the sandbox, even by panicking, allows entering it again.
+        assert_eq!(unsafe { outer.call(|| 1) }, 1);
+        assert_eq!(
+            unsafe { outer.call_batch(|sandbox| sandbox.id()) },
+            outer.id()
+        );
+    }
+}
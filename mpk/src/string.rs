@@ -0,0 +1,239 @@
+//! Strings allocated inside the sandbox and owned by the host.
+use std::{
+    ffi::{c_char, CStr, CString, IntoStringError},
+    fmt,
+    ptr::NonNull,
+    str::Utf8Error,
+    sync::{Arc, Weak},
+};
+
+use crate::{region, sandbox::PendingFrees, Sandbox, SandboxErrorKind};
+
+/// A NUL-terminated string that a sandboxed function allocated and handed over to the caller, such
+/// as the output of a renderer returning a `malloc`'d `char *`.
+///
+/// The text lives in sandbox memory, which sandboxed code can change whenever it runs, so it is
+/// only borrowed along with the sandbox, and its terminator is searched for within the sandbox's
+/// data each time it is accessed. Dropping the string frees it with the sandbox's `free`. Entering
+/// the sandbox needs it borrowed mutably, so the string is queued on the sandbox and freed when
+/// the sandbox is next called or dropped. A string outliving its sandbox is leaked.
+#[must_use]
+pub struct SandboxCString {
+    ptr: NonNull<c_char>,
+    free: unsafe extern "C" fn(*mut libc::c_void),
+    sandbox: u64,
+    pending: Weak<PendingFrees>,
+}
+
+impl SandboxCString {
+    /// Takes ownership of the string `ptr` returned by `sandbox`, which `free`, a function of the
+    /// sandboxed library, deallocates. Panics if the pointer is null or the string is not
+    /// terminated inside the sandbox.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by the allocator `free` belongs to, and must not be owned by
+    /// anything else.
+    pub unsafe fn new(
+        ptr: *mut c_char,
+        free: unsafe extern "C" fn(*mut libc::c_void),
+        sandbox: &Sandbox,
+    ) -> Self {
+        Self::try_new(ptr, free, sandbox).unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxCString::new`], but returns `None` for a null pointer instead of panicking.
+    ///
+    /// # Safety
+    ///
+    /// As for [`SandboxCString::new`].
+    pub unsafe fn new_nullable(
+        ptr: *mut c_char,
+        free: unsafe extern "C" fn(*mut libc::c_void),
+        sandbox: &Sandbox,
+    ) -> Option<Self> {
+        (!ptr.is_null()).then(|| Self::new(ptr, free, sandbox))
+    }
+
+    /// Like [`SandboxCString::new`], but returns the failed check instead of panicking. The string
+    /// is not freed if a check fails.
+    ///
+    /// # Safety
+    ///
+    /// As for [`SandboxCString::new`].
+    pub unsafe fn try_new(
+        ptr: *mut c_char,
+        free: unsafe extern "C" fn(*mut libc::c_void),
+        sandbox: &Sandbox,
+    ) -> Result<Self, SandboxErrorKind> {
+        let ptr = NonNull::new(ptr).ok_or(SandboxErrorKind::Null)?;
+        terminated_len(ptr)?;
+        Ok(SandboxCString {
+            ptr,
+            free,
+            sandbox: sandbox.id(),
+            pending: Arc::downgrade(sandbox.pending_frees()),
+        })
+    }
+
+    /// Like [`SandboxCString::new_nullable`], but returns the failed check instead of panicking.
+    ///
+    /// # Safety
+    ///
+    /// As for [`SandboxCString::new`].
+    pub unsafe fn try_new_nullable(
+        ptr: *mut c_char,
+        free: unsafe extern "C" fn(*mut libc::c_void),
+        sandbox: &Sandbox,
+    ) -> Result<Option<Self>, SandboxErrorKind> {
+        if ptr.is_null() {
+            Ok(None)
+        } else {
+            Self::try_new(ptr, free, sandbox).map(Some)
+        }
+    }
+
+    /// Borrows the text for as long as `sandbox` is borrowed, so that sandboxed code cannot change
+    /// it meanwhile. Panics if `sandbox` did not return the string, or the string is no longer
+    /// terminated inside it.
+    pub fn as_c_str<'a>(&'a self, sandbox: &'a Sandbox) -> &'a CStr {
+        self.try_as_c_str(sandbox)
+            .unwrap_or_else(|kind| panic!("{kind}"))
+    }
+
+    /// Like [`SandboxCString::as_c_str`], but returns the failed check instead of panicking.
+    pub fn try_as_c_str<'a>(&'a self, sandbox: &'a Sandbox) -> Result<&'a CStr, SandboxErrorKind> {
+        self.check_sandbox(sandbox)?;
+        Ok(unsafe { self.c_str()? })
+    }
+
+    /// Borrows the text as UTF-8, like [`SandboxCString::as_c_str`].
+    pub fn to_str<'a>(&'a self, sandbox: &'a Sandbox) -> Result<&'a str, Utf8Error> {
+        self.as_c_str(sandbox).to_str()
+    }
+
+    /// Copies the text out of `sandbox` and frees the string. Panics if `sandbox` did not return
+    /// the string, or the string is no longer terminated inside it.
+    pub fn into_c_string(self, sandbox: &Sandbox) -> CString {
+        self.as_c_str(sandbox).to_owned()
+    }
+
+    /// Copies the text out of `sandbox` as UTF-8 and frees the string, like
+    /// [`SandboxCString::into_c_string`].
+    pub fn into_string(self, sandbox: &Sandbox) -> Result<String, IntoStringError> {
+        self.into_c_string(sandbox).into_string()
+    }
+
+    fn check_sandbox(&self, sandbox: &Sandbox) -> Result<(), SandboxErrorKind> {
+        if sandbox.id() != self.sandbox {
+            return Err(SandboxErrorKind::WrongSandbox);
+        }
+        Ok(())
+    }
+
+    /// # Safety
+    ///
+    /// No sandboxed code may run while the result is borrowed.
+    unsafe fn c_str(&self) -> Result<&CStr, SandboxErrorKind> {
+        let len = terminated_len(self.ptr)?;
+        let bytes = std::slice::from_raw_parts(self.ptr.as_ptr().cast::<u8>(), len + 1);
+        Ok(CStr::from_bytes_with_nul_unchecked(bytes))
+    }
+}
+
+impl Drop for SandboxCString {
+    fn drop(&mut self) {
+        if let Some(pending) = self.pending.upgrade() {
+            let ptr = self.ptr.as_ptr() as usize;
+            pending.lock().unwrap().push((ptr, self.free));
+        }
+    }
+}
+
+impl fmt::Debug for SandboxCString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SandboxCString")
+            .field("ptr", &self.ptr)
+            .field("sandbox", &self.sandbox)
+            .finish()
+    }
+}
+
+/// Returns the length of the string at `ptr`, searching for its terminator only within the sandbox
+/// data region the string starts in.
+///
+/// # Safety
+///
+/// No sandboxed code may run concurrently.
+unsafe fn terminated_len(ptr: NonNull<c_char>) -> Result<usize, SandboxErrorKind> {
+    let start = ptr.as_ptr() as usize;
+    let end = region::data_end(start).ok_or(SandboxErrorKind::OutOfSandbox)?;
+    let bytes = std::slice::from_raw_parts(start as *const u8, end - start);
+    bytes
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(SandboxErrorKind::Unterminated)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{tests::TestRegion, RegionKind};
+
+    static FREED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn free(ptr: *mut libc::c_void) {
+        FREED.store(ptr as usize, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn strings_are_freed_in_their_sandbox() {
+        let mut data = TestRegion::new(2, RegionKind::Data);
+        data.write(0, *b"hello\0");
+        let ptr = data.ptr::<c_char>(0);
+        let mut sandbox = Sandbox::new();
+        let mut other = Sandbox::new();
+
+        let string = unsafe { SandboxCString::new(ptr, free, &sandbox) };
+        assert_eq!(string.to_str(&sandbox), Ok("hello"));
+        assert_eq!(
+            string.try_as_c_str(&other),
+            Err(SandboxErrorKind::WrongSandbox)
+        );
+        drop(string);
+        assert_eq!(FREED.load(Ordering::SeqCst), 0);
+
+        // Dropped strings are freed on the next call into their sandbox.
+        unsafe { other.call(|| ()) };
+        assert_eq!(FREED.load(Ordering::SeqCst), 0);
+        unsafe { sandbox.call(|| ()) };
+        assert_eq!(FREED.load(Ordering::SeqCst), ptr as usize);
+
+        FREED.store(0, Ordering::SeqCst);
+        let string = unsafe { SandboxCString::new(ptr, free, &sandbox) };
+        assert_eq!(string.into_string(&sandbox).unwrap(), "hello");
+        unsafe { sandbox.call_batch(|_| ()) };
+        assert_eq!(FREED.load(Ordering::SeqCst), ptr as usize);
+
+        // Or when their sandbox is dropped.
+        FREED.store(0, Ordering::SeqCst);
+        let string = unsafe { SandboxCString::new(ptr, free, &sandbox) };
+        drop(string);
+        drop(sandbox);
+        assert_eq!(FREED.load(Ordering::SeqCst), ptr as usize);
+    }
+
+    #[test]
+    fn strings_must_be_terminated_in_sandbox() {
+        let mut data = TestRegion::new(1, RegionKind::Data);
+        data.write(0, [b'x'; 8]);
+        let sandbox = Sandbox::new();
+        let unterminated = unsafe { SandboxCString::try_new(data.ptr(0), free, &sandbox) };
+        assert_eq!(unterminated.err(), Some(SandboxErrorKind::Unterminated));
+        let outside = c"outside".as_ptr().cast_mut();
+        let outside = unsafe { SandboxCString::try_new(outside, free, &sandbox) };
+        assert_eq!(outside.err(), Some(SandboxErrorKind::OutOfSandbox));
+    }
+}
@@ -0,0 +1,466 @@
+//! Minimal reader for 64-bit little-endian ELF files.
+//!
+//! This only understands as much of the format as the sandbox build tooling needs: the section
+//! and program header tables, section contents and names, symbol tables, and GNU property notes.
+use std::io;
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_GNU_PROPERTY: u32 = 0x6474e553;
+
+pub const PF_X: u32 = 0x1;
+pub const PF_W: u32 = 0x2;
+pub const PF_R: u32 = 0x4;
+
+pub const SHT_PROGBITS: u32 = 1;
+pub const SHT_SYMTAB: u32 = 2;
+pub const SHT_RELA: u32 = 4;
+pub const SHT_NOTE: u32 = 7;
+pub const SHT_NOBITS: u32 = 8;
+pub const SHT_DYNSYM: u32 = 11;
+
+pub const SHF_WRITE: u64 = 0x1;
+pub const SHF_ALLOC: u64 = 0x2;
+pub const SHF_EXECINSTR: u64 = 0x4;
+pub const SHF_MERGE: u64 = 0x10;
+pub const SHF_STRINGS: u64 = 0x20;
+
+pub const SHN_UNDEF: u16 = 0;
+
+pub const STB_LOCAL: u8 = 0;
+pub const STB_GLOBAL: u8 = 1;
+pub const STB_WEAK: u8 = 2;
+
+pub const STT_FUNC: u8 = 2;
+
+const NT_GNU_PROPERTY_TYPE_0: u32 = 5;
+const GNU_PROPERTY_X86_FEATURE_1_AND: u32 = 0xc0000002;
+
+/// The object was compiled for indirect branch tracking (`endbr64` at indirect branch targets).
+pub const GNU_PROPERTY_X86_FEATURE_1_IBT: u32 = 0x1;
+/// The object is compatible with shadow stacks.
+pub const GNU_PROPERTY_X86_FEATURE_1_SHSTK: u32 = 0x2;
+
+const EHDR_SIZE: usize = 0x40;
+const SHDR_SIZE: usize = 0x40;
+const PHDR_SIZE: usize = 0x38;
+pub(crate) const SYM_SIZE: usize = 0x18;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Returns the `size` bytes at `offset` in `data`. Both usually come from the file itself, so
+/// their sum is checked rather than trusted.
+fn range(data: &[u8], offset: u64, size: u64) -> Option<&[u8]> {
+    let end = offset.checked_add(size)?;
+    data.get(usize::try_from(offset).ok()?..usize::try_from(end).ok()?)
+}
+
+/// Returns `base + index * size`, or an error if it overflows.
+fn entry(base: usize, index: usize, size: usize) -> io::Result<usize> {
+    index
+        .checked_mul(size)
+        .and_then(|offset| base.checked_add(offset))
+        .ok_or_else(|| invalid("offset out of range"))
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> io::Result<[u8; N]> {
+    range(data, offset as u64, N as u64)
+        .map(|b| b.try_into().unwrap())
+        .ok_or_else(|| invalid("unexpected end of ELF file"))
+}
+
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> io::Result<u16> {
+    read(data, offset).map(u16::from_le_bytes)
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> io::Result<u32> {
+    read(data, offset).map(u32::from_le_bytes)
+}
+
+pub(crate) fn read_u64(data: &[u8], offset: usize) -> io::Result<u64> {
+    read(data, offset).map(u64::from_le_bytes)
+}
+
+/// An entry of the section header table.
+#[derive(Clone, Debug)]
+pub struct SectionHeader {
+    /// The index of this section in the section header table.
+    pub index: usize,
+    /// The offset of this section's header within the file.
+    pub header_offset: usize,
+    pub name: u32,
+    pub ty: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub link: u32,
+    pub info: u32,
+    pub addralign: u64,
+    pub entsize: u64,
+}
+
+impl SectionHeader {
+    fn parse(data: &[u8], index: usize, header_offset: usize) -> io::Result<SectionHeader> {
+        Ok(SectionHeader {
+            index,
+            header_offset,
+            name: read_u32(data, header_offset)?,
+            ty: read_u32(data, header_offset + 4)?,
+            flags: read_u64(data, header_offset + 8)?,
+            addr: read_u64(data, header_offset + 16)?,
+            offset: read_u64(data, header_offset + 24)?,
+            size: read_u64(data, header_offset + 32)?,
+            link: read_u32(data, header_offset + 40)?,
+            info: read_u32(data, header_offset + 44)?,
+            addralign: read_u64(data, header_offset + 48)?,
+            entsize: read_u64(data, header_offset + 56)?,
+        })
+    }
+
+    /// Writes this header back to its original location in `data`.
+    pub(crate) fn write(&self, data: &mut [u8]) {
+        let h = &mut data[self.header_offset..self.header_offset + SHDR_SIZE];
+        h[0..4].copy_from_slice(&self.name.to_le_bytes());
+        h[4..8].copy_from_slice(&self.ty.to_le_bytes());
+        h[8..16].copy_from_slice(&self.flags.to_le_bytes());
+        h[16..24].copy_from_slice(&self.addr.to_le_bytes());
+        h[24..32].copy_from_slice(&self.offset.to_le_bytes());
+        h[32..40].copy_from_slice(&self.size.to_le_bytes());
+        h[40..44].copy_from_slice(&self.link.to_le_bytes());
+        h[44..48].copy_from_slice(&self.info.to_le_bytes());
+        h[48..56].copy_from_slice(&self.addralign.to_le_bytes());
+        h[56..64].copy_from_slice(&self.entsize.to_le_bytes());
+    }
+}
+
+/// An entry of the program header table.
+#[derive(Clone, Debug)]
+pub struct ProgramHeader {
+    pub ty: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+}
+
+impl ProgramHeader {
+    fn parse(data: &[u8], offset: usize) -> io::Result<ProgramHeader> {
+        Ok(ProgramHeader {
+            ty: read_u32(data, offset)?,
+            flags: read_u32(data, offset + 4)?,
+            offset: read_u64(data, offset + 8)?,
+            vaddr: read_u64(data, offset + 16)?,
+            filesz: read_u64(data, offset + 32)?,
+            memsz: read_u64(data, offset + 40)?,
+        })
+    }
+}
+
+/// An entry of a symbol table.
+#[derive(Clone, Debug)]
+pub struct Symbol<'a> {
+    pub name: &'a str,
+    pub info: u8,
+    pub shndx: u16,
+    pub value: u64,
+    pub size: u64,
+}
+
+impl Symbol<'_> {
+    pub fn bind(&self) -> u8 {
+        self.info >> 4
+    }
+
+    pub fn ty(&self) -> u8 {
+        self.info & 0xf
+    }
+}
+
+/// A parsed ELF file.
+pub struct Elf<'a> {
+    data: &'a [u8],
+    ty: u16,
+    segments: Vec<ProgramHeader>,
+    sections: Vec<SectionHeader>,
+    shstrndx: usize,
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(data: &'a [u8]) -> io::Result<Elf<'a>> {
+        if data.len() < EHDR_SIZE || &data[0..4] != b"\x7fELF" {
+            return Err(invalid("not an ELF file"));
+        }
+        if data[4] != 2 || data[5] != 1 {
+            return Err(invalid("only 64-bit little-endian ELF files are supported"));
+        }
+
+        let ty = read_u16(data, 0x10)?;
+        let phoff = read_u64(data, 0x20)? as usize;
+        let phentsize = read_u16(data, 0x36)? as usize;
+        let phnum = read_u16(data, 0x38)? as usize;
+        let shoff = read_u64(data, 0x28)? as usize;
+        let shentsize = read_u16(data, 0x3a)? as usize;
+        let shnum = read_u16(data, 0x3c)? as usize;
+        let shstrndx = read_u16(data, 0x3e)? as usize;
+        if shnum != 0 && shentsize != SHDR_SIZE {
+            return Err(invalid("unexpected section header size"));
+        }
+        if phnum != 0 && phentsize != PHDR_SIZE {
+            return Err(invalid("unexpected program header size"));
+        }
+
+        let segments = (0..phnum)
+            .map(|i| ProgramHeader::parse(data, entry(phoff, i, PHDR_SIZE)?))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let sections = (0..shnum)
+            .map(|i| SectionHeader::parse(data, i, entry(shoff, i, SHDR_SIZE)?))
+            .collect::<io::Result<Vec<_>>>()?;
+        if shnum != 0 && shstrndx >= shnum {
+            return Err(invalid("section name table index out of range"));
+        }
+
+        Ok(Elf {
+            data,
+            ty,
+            segments,
+            sections,
+            shstrndx,
+        })
+    }
+
+    /// The raw contents of the file.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// The object file type (`e_type`).
+    pub fn ty(&self) -> u16 {
+        self.ty
+    }
+
+    /// The program headers; empty for relocatable objects.
+    pub fn segments(&self) -> &[ProgramHeader] {
+        &self.segments
+    }
+
+    /// Returns the contents of a segment present in the file.
+    pub fn segment_data(&self, segment: &ProgramHeader) -> io::Result<&'a [u8]> {
+        range(self.data, segment.offset, segment.filesz)
+            .ok_or_else(|| invalid("segment extends past the end of the file"))
+    }
+
+    pub fn sections(&self) -> &[SectionHeader] {
+        &self.sections
+    }
+
+    /// The section header of the section name string table.
+    pub fn shstrtab(&self) -> &SectionHeader {
+        &self.sections[self.shstrndx]
+    }
+
+    /// Returns the contents of a section, or an empty slice for `SHT_NOBITS` sections.
+    pub fn section_data(&self, section: &SectionHeader) -> io::Result<&'a [u8]> {
+        if section.ty == SHT_NOBITS {
+            return Ok(&[]);
+        }
+        range(self.data, section.offset, section.size)
+            .ok_or_else(|| invalid("section extends past the end of the file"))
+    }
+
+    /// Reads a NUL-terminated string at `offset` in the string table `strtab`.
+    pub fn string(&self, strtab: &SectionHeader, offset: u32) -> io::Result<&'a str> {
+        let table = self.section_data(strtab)?;
+        let s = table
+            .get(offset as usize..)
+            .ok_or_else(|| invalid("string table offset out of range"))?;
+        let len = s
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| invalid("unterminated string"))?;
+        std::str::from_utf8(&s[..len]).map_err(|_| invalid("string is not UTF-8"))
+    }
+
+    pub fn section_name(&self, section: &SectionHeader) -> io::Result<&'a str> {
+        self.string(self.shstrtab(), section.name)
+    }
+
+    pub fn section_by_name(&self, name: &str) -> Option<&SectionHeader> {
+        self.sections
+            .iter()
+            .find(|s| self.section_name(s).is_ok_and(|n| n == name))
+    }
+
+    /// Returns the entries of the first symbol table of type `ty` (`SHT_SYMTAB` or `SHT_DYNSYM`),
+    /// or an empty list if there is none.
+    pub fn symbols(&self, ty: u32) -> io::Result<Vec<Symbol<'a>>> {
+        let Some(symtab) = self.sections.iter().find(|s| s.ty == ty) else {
+            return Ok(Vec::new());
+        };
+        let strtab = self
+            .sections
+            .get(symtab.link as usize)
+            .ok_or_else(|| invalid("symbol string table index out of range"))?;
+        let data = self.section_data(symtab)?;
+
+        data.chunks_exact(SYM_SIZE)
+            .map(|sym| {
+                Ok(Symbol {
+                    name: self.string(strtab, read_u32(sym, 0)?)?,
+                    info: sym[4],
+                    shndx: read_u16(sym, 6)?,
+                    value: read_u64(sym, 8)?,
+                    size: read_u64(sym, 16)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the `GNU_PROPERTY_X86_FEATURE_1_AND` bits recorded in the file's GNU property note,
+    /// or `None` if the file has no such property. The linker only sets a bit in the output if
+    /// every input object has it.
+    pub fn x86_features(&self) -> io::Result<Option<u32>> {
+        let (notes, align) = if let Some(section) = self.section_by_name(".note.gnu.property") {
+            let align = match section.addralign {
+                0..=4 => 4,
+                8 => 8,
+                _ => return Err(invalid("unexpected property note alignment")),
+            };
+            (self.section_data(section)?, align)
+        } else if let Some(segment) = self.segments.iter().find(|s| s.ty == PT_GNU_PROPERTY) {
+            (self.segment_data(segment)?, 8)
+        } else {
+            return Ok(None);
+        };
+
+        let mut offset = 0;
+        while offset + 12 <= notes.len() {
+            let namesz = read_u32(notes, offset)? as usize;
+            let descsz = read_u32(notes, offset + 4)? as usize;
+            let ty = read_u32(notes, offset + 8)?;
+            let name = offset + 12;
+            let desc = (name + namesz).next_multiple_of(align);
+            let next = (desc + descsz).next_multiple_of(align);
+
+            if ty == NT_GNU_PROPERTY_TYPE_0 && notes.get(name..name + namesz) == Some(b"GNU\0") {
+                let props = notes
+                    .get(desc..desc + descsz)
+                    .ok_or_else(|| invalid("property note extends past its section"))?;
+                let mut prop = 0;
+                while prop + 8 <= props.len() {
+                    let pr_type = read_u32(props, prop)?;
+                    let pr_datasz = read_u32(props, prop + 4)? as usize;
+                    if pr_type == GNU_PROPERTY_X86_FEATURE_1_AND {
+                        return read_u32(props, prop + 8).map(Some);
+                    }
+                    prop += 8 + pr_datasz.next_multiple_of(8);
+                }
+            }
+            offset = next;
+        }
+        Ok(None)
+    }
+
+    /// Returns the CET features (`IBT`, `SHSTK`) the file is not marked as supporting.
+    pub fn missing_cet_features(&self) -> io::Result<Vec<&'static str>> {
+        let features = self.x86_features()?.unwrap_or(0);
+        let mut missing = Vec::new();
+        if features & GNU_PROPERTY_X86_FEATURE_1_IBT == 0 {
+            missing.push("IBT");
+        }
+        if features & GNU_PROPERTY_X86_FEATURE_1_SHSTK == 0 {
+            missing.push("SHSTK");
+        }
+        Ok(missing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a minimal ELF header with the given program and section header tables.
+    fn header(phoff: u64, phnum: u16, shoff: u64, shnum: u16) -> Vec<u8> {
+        let mut data = vec![0; EHDR_SIZE];
+        data[0..4].copy_from_slice(b"\x7fELF");
+        data[4] = 2;
+        data[5] = 1;
+        data[0x20..0x28].copy_from_slice(&phoff.to_le_bytes());
+        data[0x28..0x30].copy_from_slice(&shoff.to_le_bytes());
+        data[0x36..0x38].copy_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+        data[0x38..0x3a].copy_from_slice(&phnum.to_le_bytes());
+        data[0x3a..0x3c].copy_from_slice(&(SHDR_SIZE as u16).to_le_bytes());
+        data[0x3c..0x3e].copy_from_slice(&shnum.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn rejects_header_tables_past_end_of_address_space() {
+        for data in [
+            header(u64::MAX - 8, 2, 0, 0),
+            header(0, 0, u64::MAX - 8, 2),
+            header(EHDR_SIZE as u64, 1, 0, 0),
+        ] {
+            let err = Elf::parse(&data).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn rejects_contents_past_end_of_address_space() {
+        let mut data = header(EHDR_SIZE as u64, 1, 0, 0);
+        data.resize(EHDR_SIZE + PHDR_SIZE, 0);
+        let phdr = &mut data[EHDR_SIZE..];
+        phdr[0..4].copy_from_slice(&PT_LOAD.to_le_bytes());
+        phdr[8..16].copy_from_slice(&(u64::MAX - 8).to_le_bytes());
+        phdr[32..40].copy_from_slice(&16u64.to_le_bytes());
+
+        let elf = Elf::parse(&data).unwrap();
+        let err = elf.segment_data(&elf.segments()[0]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(read_u64(&data, usize::MAX - 4).is_err());
+    }
+
+    /// Compiles a trivial function with the given `-fcf-protection` level and returns the object
+    /// and a shared library linked from it.
+    fn cet_outputs(protection: &str) -> (Vec<u8>, Vec<u8>) {
+        let dir = crate::tests::test_dir(&format!("cet-{protection}"));
+        let flag = format!("-fcf-protection={protection}");
+        let obj = crate::tests::compile(&dir, "f.c", "int f(int x) { return x + 1; }", &[&flag]);
+        let lib = dir.join("libf.so");
+        let status = std::process::Command::new("cc")
+            .args(["-shared", "-nostdlib", "-o"])
+            .arg(&lib)
+            .arg(&obj)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let outputs = (std::fs::read(obj).unwrap(), std::fs::read(lib).unwrap());
+        let _ = std::fs::remove_dir_all(&dir);
+        outputs
+    }
+
+    #[test]
+    fn reads_cet_properties() {
+        for (protection, missing) in [
+            ("full", &[][..]),
+            ("return", &["IBT"][..]),
+            ("branch", &["SHSTK"][..]),
+            ("none", &["IBT", "SHSTK"][..]),
+        ] {
+            let (obj, lib) = cet_outputs(protection);
+            // Without its section headers, a linked file's properties are found through its
+            // `PT_GNU_PROPERTY` segment.
+            let mut stripped = lib.clone();
+            stripped[0x28..0x30].fill(0);
+            stripped[0x3c..0x40].fill(0);
+            for data in [obj, lib, stripped] {
+                let elf = Elf::parse(&data).unwrap();
+                assert_eq!(elf.missing_cet_features().unwrap(), missing, "{protection}");
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Auditing the symbols a sandboxed library references from outside itself.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    path::PathBuf,
+};
+
+use crate::global_symbols;
+
+/// Returns whether an undefined symbol is provided by the linker rather than by other code: the
+/// GOT, and the bounds of the sections the library is moved into. The bounds of any other section
+/// would let the library find memory outside the sandbox, so they are reported like other imports.
+fn is_linker_defined(name: &str) -> bool {
+    name == "_GLOBAL_OFFSET_TABLE_"
+        || name.starts_with("__start_sandbox_")
+        || name.starts_with("__stop_sandbox_")
+}
+
+/// The symbols a library references but does not define, with the objects referencing each.
+pub(crate) struct Imports(BTreeMap<String, BTreeSet<String>>);
+
+impl Imports {
+    pub(crate) fn collect(objs: &[PathBuf]) -> Imports {
+        let defined: BTreeSet<String> = objs
+            .iter()
+            .flat_map(|obj| global_symbols(obj, true))
+            .collect();
+
+        let mut imports = BTreeMap::<String, BTreeSet<String>>::new();
+        for obj in objs {
+            let obj_name = obj.file_name().unwrap().to_string_lossy().into_owned();
+            for symbol in global_symbols(obj, false) {
+                if symbol.is_empty() || defined.contains(&symbol) || is_linker_defined(&symbol) {
+                    continue;
+                }
+                imports.entry(symbol).or_default().insert(obj_name.clone());
+            }
+        }
+        Imports(imports)
+    }
+
+    /// Returns the imported symbols that are not in `allowed`.
+    pub(crate) fn unexpected(&self, allowed: &BTreeSet<String>) -> Vec<&str> {
+        self.0
+            .keys()
+            .filter(|symbol| !allowed.contains(*symbol))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Lists every imported symbol, whether it is allowed, and the objects referencing it, followed
+    /// by any allowed symbols that are not actually imported.
+    pub(crate) fn report(&self, allowed: &BTreeSet<String>) -> String {
+        let mut report = String::new();
+        if self.0.is_empty() {
+            report += "no imported symbols\n";
+        }
+        for (symbol, objs) in &self.0 {
+            let status = if allowed.contains(symbol) {
+                "allowed"
+            } else {
+                "UNEXPECTED"
+            };
+            let objs: Vec<&str> = objs.iter().map(String::as_str).collect();
+            writeln!(report, "{symbol}\t{status}\t{}", objs.join(" ")).unwrap();
+        }
+        for symbol in allowed.iter().filter(|s| !self.0.contains_key(*s)) {
+            writeln!(report, "{symbol}\tunused").unwrap();
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{compile, test_dir};
+
+    #[test]
+    fn collects_symbols_defined_outside_the_library() {
+        let dir = test_dir("imports");
+        let a = compile(
+            &dir,
+            "a.c",
+            "extern int b(void); extern int puts(const char *);\n\
+             int a(void) { puts(\"a\"); return b(); }",
+            &["-fPIC"],
+        );
+        let b = compile(
+            &dir,
+            "b.c",
+            "extern int puts(const char *); extern char __start_sandbox_data[];\n\
+             int b(void) { return puts(__start_sandbox_data); }",
+            &["-fPIC"],
+        );
+        let imports = Imports::collect(&[a, b]);
+
+        // `b` is defined by another object of the library, and `__start_sandbox_data` by the
+        // linker.
+        assert_eq!(imports.unexpected(&BTreeSet::new()), ["puts"]);
+        let allowed = BTreeSet::from(["puts".to_owned(), "free".to_owned()]);
+        assert!(imports.unexpected(&allowed).is_empty());
+        assert_eq!(
+            imports.report(&allowed),
+            "puts\tallowed\ta.o b.o\nfree\tunused\n"
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reports_unexpected_imports() {
+        let dir = test_dir("imports-unexpected");
+        let obj = compile(
+            &dir,
+            "c.c",
+            "extern void *malloc(unsigned long); extern char __start_mpk_pkru_sites[];\n\
+             void *c(void) { return __start_mpk_pkru_sites + (long)malloc(1); }",
+            &["-fPIC"],
+        );
+        let imports = Imports::collect(&[obj]);
+
+        // Only the bounds of the sandbox's own sections are exempt.
+        assert_eq!(
+            imports.report(&BTreeSet::new()),
+            "__start_mpk_pkru_sites\tUNEXPECTED\tc.o\nmalloc\tUNEXPECTED\tc.o\n"
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,621 @@
+//! Build-script support for sandboxed C libraries.
+//!
+//! The crate wrapping a C library declares it from its `build.rs`:
+//!
+//! ```no_run
+//! sandbox_build::Build::new("sandboxed")
+//!     .file("src/sandbox.c")
+//!     .flag("-O3")
+//!     .header("src/sandbox.h")
+//!     .compile();
+//! ```
+//!
+//! This compiles the sources, moves their writable data into the [`SANDBOX_SECTION`] and
+//! [`SANDBOX_BSS_SECTION`] sections, their read-only data into [`SANDBOX_RODATA_SECTION`] and
+//! their code into [`SANDBOX_TEXT_SECTION`], archives them into `lib<name>.a`, and generates
+//! sandboxed bindings into `$OUT_DIR/<name>.rs`. No linker script is needed; the `mpk` runtime
+//! finds the sections through the `__start_`/`__stop_` symbols the linker defines for them.
+pub mod elf;
+mod imports;
+mod rewrite;
+mod variadic;
+
+pub use bindgen::SandboxConfig;
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    ffi::OsStr,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The section holding the writable data of every sandboxed library. This must match the section
+/// the `mpk` runtime protects.
+pub const SANDBOX_SECTION: &str = "sandbox_data";
+
+/// The section holding the zero-initialized writable data of every sandboxed library, which is
+/// kept apart from [`SANDBOX_SECTION`] so that it takes no space in the binary.
+pub const SANDBOX_BSS_SECTION: &str = "sandbox_bss";
+
+/// The section holding the read-only data of every sandboxed library, such as constants, string
+/// literals and C++ vtables. The `mpk` runtime makes it read-only and only accepts vtables that lie
+/// in it.
+pub const SANDBOX_RODATA_SECTION: &str = "sandbox_rodata";
+
+/// The section holding the code of every sandboxed library. The `mpk` runtime only accepts
+/// function pointers into this section (or into loaded libraries) as sandboxed code.
+pub const SANDBOX_TEXT_SECTION: &str = "sandbox_text";
+
+/// The C++ runtime symbols needed to throw and catch exceptions, which the shims catching them
+/// use and a library that throws them cannot do without. They are allowed imports when
+/// [`SandboxConfig::catch_exceptions`] is set.
+const EXCEPTION_IMPORTS: &[&str] = &[
+    "_Unwind_Resume",
+    "_ZTISt9exception",
+    "_ZTVN10__cxxabiv117__class_type_infoE",
+    "_ZTVN10__cxxabiv120__si_class_type_infoE",
+    "_ZTVN10__cxxabiv121__vmi_class_type_infoE",
+    "__cxa_allocate_exception",
+    "__cxa_begin_catch",
+    "__cxa_current_exception_type",
+    "__cxa_end_catch",
+    "__cxa_free_exception",
+    "__cxa_rethrow",
+    "__cxa_throw",
+    "__gxx_personality_v0",
+];
+
+enum Allow {
+    Function(String),
+    Type(String),
+    Var(String),
+    Item(String),
+    File(String),
+}
+
+/// A sandboxed C library to be compiled and bound from a build script.
+pub struct Build {
+    name: String,
+    files: Vec<PathBuf>,
+    private_files: Vec<PathBuf>,
+    allowed_imports: BTreeSet<String>,
+    include_dirs: Vec<PathBuf>,
+    defines: Vec<(String, Option<String>)>,
+    flags: Vec<String>,
+    headers: Vec<PathBuf>,
+    allowlist: Vec<Allow>,
+    variadics: Vec<variadic::Shim>,
+    wrap_static_fns: bool,
+    cet: bool,
+    sandbox: SandboxConfig,
+    compiler: PathBuf,
+    archiver: PathBuf,
+    out_dir: Option<PathBuf>,
+    bindgen: Vec<Box<dyn FnOnce(bindgen::Builder) -> bindgen::Builder>>,
+}
+
+impl Build {
+    /// Creates a new sandboxed library. `name` is used for the archive (`lib<name>.a`) and the
+    /// generated bindings file.
+    pub fn new(name: impl Into<String>) -> Build {
+        Build {
+            name: name.into(),
+            files: Vec::new(),
+            private_files: Vec::new(),
+            allowed_imports: BTreeSet::new(),
+            include_dirs: Vec::new(),
+            defines: Vec::new(),
+            flags: Vec::new(),
+            headers: Vec::new(),
+            allowlist: Vec::new(),
+            variadics: Vec::new(),
+            wrap_static_fns: false,
+            cet: false,
+            sandbox: SandboxConfig::new(),
+            compiler: PathBuf::from("clang"),
+            archiver: PathBuf::from("ar"),
+            out_dir: None,
+            bindgen: Vec::new(),
+        }
+    }
+
+    /// Adds a C source file to the library.
+    pub fn file(mut self, file: impl AsRef<Path>) -> Build {
+        self.files.push(file.as_ref().to_owned());
+        self
+    }
+
+    /// Adds several C source files to the library.
+    pub fn files<P: AsRef<Path>>(mut self, files: impl IntoIterator<Item = P>) -> Build {
+        self.files
+            .extend(files.into_iter().map(|f| f.as_ref().to_owned()));
+        self
+    }
+
+    /// Adds C source files whose global symbols are private to the library. Every symbol they
+    /// define, and every reference to one from the library's other objects, is renamed so that it
+    /// cannot clash with definitions outside the library. This lets a sandboxed library bring its
+    /// own copy of functions such as `malloc` or `memcpy` without interposing them for the host.
+    pub fn private_files<P: AsRef<Path>>(mut self, files: impl IntoIterator<Item = P>) -> Build {
+        self.private_files
+            .extend(files.into_iter().map(|f| f.as_ref().to_owned()));
+        self
+    }
+
+    /// Allows the library to reference `symbol` without defining it. Such a call or access runs
+    /// outside code with sandbox privileges, so by default, any symbol the library imports fails
+    /// the build.
+    pub fn allow_import(mut self, symbol: impl Into<String>) -> Build {
+        self.allowed_imports.insert(symbol.into());
+        self
+    }
+
+    /// Adds a directory to the include path, both when compiling and when generating bindings.
+    pub fn include(mut self, dir: impl AsRef<Path>) -> Build {
+        self.include_dirs.push(dir.as_ref().to_owned());
+        self
+    }
+
+    /// Defines a preprocessor macro, both when compiling and when generating bindings.
+    pub fn define(mut self, name: impl Into<String>, value: Option<&str>) -> Build {
+        self.defines.push((name.into(), value.map(str::to_owned)));
+        self
+    }
+
+    /// Adds a flag passed to the compiler when compiling the library's sources.
+    pub fn flag(mut self, flag: impl Into<String>) -> Build {
+        self.flags.push(flag.into());
+        self
+    }
+
+    /// Adds a header to generate sandboxed bindings for.
+    pub fn header(mut self, header: impl AsRef<Path>) -> Build {
+        self.headers.push(header.as_ref().to_owned());
+        self
+    }
+
+    /// Only generate bindings for functions matching `pattern`.
+    pub fn allowlist_function(mut self, pattern: impl Into<String>) -> Build {
+        self.allowlist.push(Allow::Function(pattern.into()));
+        self
+    }
+
+    /// Only generate bindings for types matching `pattern`.
+    pub fn allowlist_type(mut self, pattern: impl Into<String>) -> Build {
+        self.allowlist.push(Allow::Type(pattern.into()));
+        self
+    }
+
+    /// Only generate bindings for variables matching `pattern`.
+    pub fn allowlist_var(mut self, pattern: impl Into<String>) -> Build {
+        self.allowlist.push(Allow::Var(pattern.into()));
+        self
+    }
+
+    /// Only generate bindings for items matching `pattern`.
+    pub fn allowlist_item(mut self, pattern: impl Into<String>) -> Build {
+        self.allowlist.push(Allow::Item(pattern.into()));
+        self
+    }
+
+    /// Only generate bindings for items declared in files matching `pattern`.
+    pub fn allowlist_file(mut self, pattern: impl Into<String>) -> Build {
+        self.allowlist.push(Allow::File(pattern.into()));
+        self
+    }
+
+    /// Binds one instantiation of a variadic function, which bindgen cannot bind directly, as the
+    /// fixed-arity function `name`. `signature` gives the return type and the types of all
+    /// arguments, e.g. `int printf(const char *, int)`; the function must be declared by one of the
+    /// [`header`](Build::header)s.
+    ///
+    /// The instantiation is compiled into the library as a C function forwarding its arguments.
+    pub fn variadic(mut self, name: impl Into<String>, signature: &str) -> Build {
+        self.variadics
+            .push(variadic::Shim::parse(name.into(), signature));
+        self
+    }
+
+    /// Compiles the library with Intel CET instrumentation (`-fcf-protection=full`), and checks that
+    /// every object is marked as supporting both indirect branch tracking and shadow stacks.
+    pub fn cet(mut self, cet: bool) -> Build {
+        self.cet = cet;
+        self
+    }
+
+    /// Sets the C compiler. Defaults to `clang`.
+    pub fn compiler(mut self, compiler: impl AsRef<Path>) -> Build {
+        self.compiler = compiler.as_ref().to_owned();
+        self
+    }
+
+    /// Sets the archiver. Defaults to `ar`.
+    pub fn archiver(mut self, archiver: impl AsRef<Path>) -> Build {
+        self.archiver = archiver.as_ref().to_owned();
+        self
+    }
+
+    /// Sets the output directory. Defaults to `$OUT_DIR`.
+    pub fn out_dir(mut self, out_dir: impl AsRef<Path>) -> Build {
+        self.out_dir = Some(out_dir.as_ref().to_owned());
+        self
+    }
+
+    /// Generates wrappers for the `static` (typically `static inline`) functions declared by the
+    /// headers, so that they can be bound and called inside the sandbox like other functions. The
+    /// wrappers are compiled into the library.
+    pub fn wrap_static_fns(mut self, wrap: bool) -> Build {
+        self.wrap_static_fns = wrap;
+        self
+    }
+
+    /// Configures the generated sandbox type, e.g. its name and a global instance. Defaults to
+    /// [`SandboxConfig::new`].
+    pub fn sandbox(mut self, config: SandboxConfig) -> Build {
+        self.sandbox = config;
+        self
+    }
+
+    /// Applies additional configuration to the `bindgen::Builder` used to generate bindings.
+    pub fn bindgen(
+        mut self,
+        f: impl FnOnce(bindgen::Builder) -> bindgen::Builder + 'static,
+    ) -> Build {
+        self.bindgen.push(Box::new(f));
+        self
+    }
+
+    /// Compiles and archives the library, generates bindings, and tells Cargo to link against it.
+    ///
+    /// The symbols the library imports from outside itself are listed in `<name>.imports` in the
+    /// output directory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any step fails, as is customary for build scripts.
+    pub fn compile(mut self) {
+        let out_dir = self
+            .out_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(std::env::var("OUT_DIR").unwrap()));
+        let obj_dir = out_dir.join(&self.name);
+        std::fs::create_dir_all(&obj_dir).expect("could not create object directory");
+
+        if !self.variadics.is_empty() {
+            let header = out_dir.join(format!("{}_variadic.h", self.name));
+            let source = out_dir.join(format!("{}_variadic.c", self.name));
+            variadic::write(&self.variadics, &self.headers, &header, &source);
+            self.files.push(source);
+            self.headers.push(header);
+            for shim in &self.variadics {
+                self.allowlist
+                    .push(Allow::Function(format!("^{}$", shim.name())));
+            }
+        }
+
+        // Bindings come first, since bindgen writes the wrappers for `static` functions.
+        if !self.headers.is_empty() {
+            let bindings_path = out_dir.join(format!("{}.rs", self.name));
+            let static_fns = out_dir.join(format!("{}_static_fns", self.name));
+            for ext in ["c", "cpp"] {
+                let _ = std::fs::remove_file(static_fns.with_extension(ext));
+            }
+            self.generate_bindings(&bindings_path, &static_fns);
+            for ext in ["c", "cpp"] {
+                let source = static_fns.with_extension(ext);
+                if source.exists() {
+                    self.files.push(source);
+                }
+            }
+        }
+
+        let mut objs = Vec::<PathBuf>::new();
+        let mut private_objs = Vec::<PathBuf>::new();
+        for (file, private) in self
+            .files
+            .iter()
+            .map(|f| (f, false))
+            .chain(self.private_files.iter().map(|f| (f, true)))
+        {
+            println!("cargo:rerun-if-changed={}", file.display());
+            let obj_path = obj_dir.join(object_name(file));
+            self.compile_object(file, &obj_path);
+            if self.cet {
+                check_cet(&obj_path);
+            }
+            if private {
+                private_objs.push(obj_path.clone());
+            }
+            objs.push(obj_path);
+        }
+
+        let renames: HashMap<String, String> = private_objs
+            .iter()
+            .flat_map(|obj| global_symbols(obj, true))
+            .map(|symbol| {
+                let renamed = format!("__{}_{symbol}", self.name);
+                (symbol, renamed)
+            })
+            .collect();
+        for obj in &objs {
+            rewrite_object(obj, &renames);
+        }
+
+        if self.sandbox.catches_exceptions() {
+            self.allowed_imports
+                .extend(EXCEPTION_IMPORTS.iter().map(|&symbol| symbol.to_owned()));
+        }
+        let imports = imports::Imports::collect(&objs);
+        let report_path = out_dir.join(format!("{}.imports", self.name));
+        std::fs::write(&report_path, imports.report(&self.allowed_imports))
+            .expect("could not write import report");
+        let unexpected = imports.unexpected(&self.allowed_imports);
+        if !unexpected.is_empty() {
+            panic!(
+                "lib{}.a imports symbols from outside the sandbox: {} (see {}); allow intended \
+                 imports with `Build::allow_import`",
+                self.name,
+                unexpected.join(", "),
+                report_path.display()
+            );
+        }
+
+        let lib_path = out_dir.join(format!("lib{}.a", self.name));
+        self.archive(&lib_path, &objs);
+
+        println!("cargo:rustc-link-search={}", out_dir.to_str().unwrap());
+        println!("cargo:rustc-link-lib={}", self.name);
+    }
+
+    fn clang_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for dir in &self.include_dirs {
+            args.push(format!("-I{}", dir.display()));
+        }
+        for (name, value) in &self.defines {
+            match value {
+                Some(value) => args.push(format!("-D{name}={value}")),
+                None => args.push(format!("-D{name}")),
+            }
+        }
+        args
+    }
+
+    fn compile_object(&self, src: impl AsRef<OsStr>, out: impl AsRef<OsStr>) {
+        let cet = self.cet.then_some("-fcf-protection=full");
+
+        let process = Command::new(&self.compiler)
+            .args(&self.flags)
+            .args(self.clang_args())
+            .arg("-c")
+            .args(cet)
+            .arg("-o")
+            .arg(&out)
+            .arg(src)
+            .output()
+            .unwrap_or_else(|e| panic!("could not spawn `{}`: {e}", self.compiler.display()));
+        if !process.status.success() {
+            // Panic if the command was not successful.
+            panic!(
+                "could not compile object file: {}",
+                String::from_utf8_lossy(&process.stderr)
+            );
+        }
+    }
+
+    fn archive(&self, lib_path: &Path, objs: &[PathBuf]) {
+        // Remove any stale archive, since `ar` would otherwise keep members from previous builds.
+        let _ = std::fs::remove_file(lib_path);
+
+        let process = Command::new(&self.archiver)
+            .arg("rcs")
+            .arg(lib_path)
+            .args(objs)
+            .output()
+            .unwrap_or_else(|e| panic!("could not spawn `{}`: {e}", self.archiver.display()));
+        if !process.status.success() {
+            // Panic if the command was not successful.
+            panic!(
+                "could not emit library file: {}",
+                String::from_utf8_lossy(&process.stderr)
+            );
+        }
+    }
+
+    /// Generates bindings into `out`, and wrappers for `static` functions into `static_fns` with
+    /// the extension `.c` or `.cpp` if there are any.
+    fn generate_bindings(&mut self, out: &Path, static_fns: &Path) {
+        let mut builder = bindgen::Builder::default()
+            .sandbox(self.sandbox.clone())
+            .clang_args(self.clang_args())
+            .wrap_static_fns(self.wrap_static_fns)
+            .wrap_static_fns_path(static_fns)
+            .emit_diagnostics()
+            .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()));
+        // Variadic functions with shims are bound through them, so only those without shims are
+        // reported as skipped.
+        for shim in &self.variadics {
+            builder = builder.blocklist_function(format!("^{}$", shim.function()));
+        }
+        for header in &self.headers {
+            // The wrappers include the headers from the output directory.
+            let header = std::path::absolute(header).expect("could not resolve header path");
+            builder = builder.header(header.to_str().expect("header path is not UTF-8"));
+        }
+        for allow in std::mem::take(&mut self.allowlist) {
+            builder = match allow {
+                Allow::Function(p) => builder.allowlist_function(p),
+                Allow::Type(p) => builder.allowlist_type(p),
+                Allow::Var(p) => builder.allowlist_var(p),
+                Allow::Item(p) => builder.allowlist_item(p),
+                Allow::File(p) => builder.allowlist_file(p),
+            };
+        }
+        for f in std::mem::take(&mut self.bindgen) {
+            builder = f(builder);
+        }
+
+        builder
+            .generate()
+            .expect("Unable to generate bindings")
+            .write_to_file(out)
+            .expect("Couldn't write bindings!");
+    }
+}
+
+/// Returns the name of the object compiled from `file`. Sources with the same file name in
+/// different directories, such as `a/util.c` and `b/util.c`, must not overwrite each other's
+/// object, so the name includes a hash of the path as given.
+fn object_name(file: &Path) -> String {
+    let mut hasher = DefaultHasher::new();
+    file.hash(&mut hasher);
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    format!("{stem}-{:016x}.o", hasher.finish())
+}
+
+/// Checks that a compiled object carries the IBT and SHSTK properties. The linker drops a property
+/// from the output if any input lacks it, so one unmarked object silently disables CET for the
+/// whole binary.
+fn check_cet(obj_path: &Path) {
+    let obj = std::fs::read(obj_path).expect("could not read object file");
+    let missing = elf::Elf::parse(&obj)
+        .and_then(|elf| elf.missing_cet_features())
+        .unwrap_or_else(|e| panic!("could not read {}: {e}", obj_path.display()));
+    if !missing.is_empty() {
+        panic!(
+            "{} is not marked as CET-compatible (missing {})",
+            obj_path.display(),
+            missing.join(", ")
+        );
+    }
+}
+
+/// Returns the global symbols an object defines, or those it references without defining them.
+fn global_symbols(obj_path: &Path, defined: bool) -> Vec<String> {
+    let obj = std::fs::read(obj_path).expect("could not read object file");
+    let symbols = elf::Elf::parse(&obj)
+        .and_then(|elf| elf.symbols(elf::SHT_SYMTAB))
+        .unwrap_or_else(|e| panic!("could not read {}: {e}", obj_path.display()));
+    symbols
+        .iter()
+        .filter(|s| s.bind() != elf::STB_LOCAL && (s.shndx != elf::SHN_UNDEF) == defined)
+        .map(|s| s.name.to_owned())
+        .collect()
+}
+
+/// Moves the writable data of a compiled object into [`SANDBOX_SECTION`] and
+/// [`SANDBOX_BSS_SECTION`], its read-only data into [`SANDBOX_RODATA_SECTION`] and its code
+/// into [`SANDBOX_TEXT_SECTION`], and applies `renames` to its global symbols.
+fn rewrite_object(obj_path: &Path, renames: &HashMap<String, String>) {
+    let obj = std::fs::read(obj_path).expect("could not read object file");
+    let obj = rewrite::rename_symbols(&obj, renames)
+        .and_then(|obj| rewrite::move_data_sections(&obj, SANDBOX_SECTION, SANDBOX_BSS_SECTION))
+        .and_then(|obj| rewrite::move_rodata_sections(&obj, SANDBOX_RODATA_SECTION))
+        .and_then(|obj| rewrite::move_text_sections(&obj, SANDBOX_TEXT_SECTION))
+        .unwrap_or_else(|e| panic!("could not rewrite {}: {e}", obj_path.display()));
+    std::fs::write(obj_path, obj).expect("could not write object file");
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Returns a new, empty directory for the files of the test `name`.
+    pub(crate) fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sandbox-build-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Compiles `source` in `dir` with the system C or C++ compiler, depending on the extension
+    /// of `file`, and returns the path of the object.
+    pub(crate) fn compile(dir: &Path, file: &str, source: &str, flags: &[&str]) -> PathBuf {
+        let src = dir.join(file);
+        std::fs::write(&src, source).unwrap();
+        let obj = src.with_extension("o");
+        let compiler = if file.ends_with(".c") { "cc" } else { "c++" };
+        let output = Command::new(compiler)
+            .args(flags)
+            .arg("-c")
+            .arg("-o")
+            .arg(&obj)
+            .arg(&src)
+            .output()
+            .unwrap_or_else(|e| panic!("could not spawn `{compiler}`: {e}"));
+        assert!(
+            output.status.success(),
+            "could not compile {file}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        obj
+    }
+
+    #[test]
+    fn exception_imports_cover_throwing_and_catching() {
+        let dir = test_dir("exception-imports");
+        // Built like a sandboxed library, without the standard headers, so the C++ ABI is
+        // declared inline as the generated shims declare it.
+        let obj = compile(
+            &dir,
+            "throw.cc",
+            r#"
+            namespace std {
+            class exception {
+            public:
+                virtual ~exception() noexcept;
+                virtual const char *what() const noexcept;
+            };
+            }
+            extern "C" const void *current_type() noexcept
+                __asm__("__cxa_current_exception_type");
+
+            struct Base { virtual ~Base() {} };
+            struct Error : Base { int code; explicit Error(int code) : code(code) {} };
+            struct Other { virtual ~Other() {} };
+            struct Both : Error, Other { Both() : Error(2) {} };
+            void operator delete(void *, unsigned long) noexcept {}
+
+            extern "C" int fails(int which) {
+                if (which == 1) throw Error(1);
+                if (which == 2) throw Both();
+                return 0;
+            }
+
+            extern "C" int shim(int which) {
+                try {
+                    try {
+                        return fails(which);
+                    } catch (...) {
+                        throw;
+                    }
+                } catch (const std::exception &e) {
+                    return e.what() != nullptr;
+                } catch (const Error &e) {
+                    return e.code;
+                } catch (...) {
+                    return current_type() != nullptr;
+                }
+            }
+            "#,
+            &["-fPIC", "-nostdinc", "-nostdinc++"],
+        );
+        let imports = imports::Imports::collect(&[obj]);
+        let allowed = EXCEPTION_IMPORTS
+            .iter()
+            .map(|&symbol| symbol.to_owned())
+            .collect();
+        assert_eq!(imports.unexpected(&allowed), Vec::<&str>::new());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn object_names_are_unique_per_path() {
+        let a = object_name(Path::new("a/util.c"));
+        let b = object_name(Path::new("b/util.c"));
+        assert_ne!(a, b);
+        assert!(a.starts_with("util-") && a.ends_with(".o"));
+        assert_eq!(a, object_name(Path::new("a/util.c")));
+    }
+}
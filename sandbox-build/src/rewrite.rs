@@ -0,0 +1,294 @@
+//! `objcopy`-style rewriting of sandboxed object files.
+use std::{collections::HashMap, io};
+
+use crate::elf::{
+    Elf, SHF_ALLOC, SHF_EXECINSTR, SHF_MERGE, SHF_STRINGS, SHF_WRITE, SHT_NOBITS, SHT_PROGBITS,
+    SHT_RELA, SHT_SYMTAB, STB_LOCAL, SYM_SIZE,
+};
+
+/// The granularity of memory protection. Every moved section is aligned and padded to this size,
+/// so that the combined output section never shares a page with unsandboxed data.
+const PAGE_SIZE: u64 = 4096;
+
+/// Returns whether a section holds initialized writable data that belongs in the sandbox.
+fn is_sandboxed_data(name: &str, ty: u32, flags: u64) -> bool {
+    // `.data.rel.ro` is read-only after relocation; `move_rodata_sections` moves it instead.
+    ty == SHT_PROGBITS
+        && flags & (SHF_ALLOC | SHF_WRITE) == SHF_ALLOC | SHF_WRITE
+        && (name == ".data" || name.starts_with(".data."))
+        && !name.starts_with(".data.rel.ro")
+}
+
+/// Returns whether a section holds zero-initialized writable data that belongs in the sandbox.
+fn is_sandboxed_bss(name: &str, ty: u32, flags: u64) -> bool {
+    ty == SHT_NOBITS
+        && flags & (SHF_ALLOC | SHF_WRITE) == SHF_ALLOC | SHF_WRITE
+        && (name == ".bss" || name.starts_with(".bss."))
+}
+
+/// Returns whether a section holds data that is only written during relocation, such as C++
+/// vtables and type information.
+fn is_relro(name: &str, ty: u32, flags: u64) -> bool {
+    ty == SHT_PROGBITS
+        && flags & (SHF_ALLOC | SHF_WRITE) == SHF_ALLOC | SHF_WRITE
+        && (name == ".data.rel.ro" || name.starts_with(".data.rel.ro."))
+}
+
+/// Returns whether a section holds constant data, such as `const` globals and string literals.
+fn is_rodata(name: &str, ty: u32, flags: u64) -> bool {
+    ty == SHT_PROGBITS
+        && flags & (SHF_ALLOC | SHF_WRITE | SHF_EXECINSTR) == SHF_ALLOC
+        && (name == ".rodata" || name.starts_with(".rodata."))
+}
+
+/// Returns whether a section holds code.
+fn is_text(name: &str, ty: u32, flags: u64) -> bool {
+    ty == SHT_PROGBITS
+        && flags & (SHF_ALLOC | SHF_EXECINSTR) == SHF_ALLOC | SHF_EXECINSTR
+        && (name == ".text" || name.starts_with(".text."))
+}
+
+/// Renames the writable data sections (`.data*`) of a relocatable object to `target`, and its
+/// zero-initialized data sections (`.bss*`) to `bss_target`, aligning and padding each one to a
+/// page boundary.
+///
+/// The linker merges all input sections named `target` into a single output section and, since
+/// `target` is a valid C identifier, defines `__start_<target>` and `__stop_<target>` around it.
+/// This works with any linker and without a linker script. Zero-initialized data gets a section of
+/// its own, since linkers place `SHT_NOBITS` input sections in a separate output section even when
+/// the names match, and merging it into `target` would store every zero byte in the binary.
+pub(crate) fn move_data_sections(
+    obj: &[u8],
+    target: &str,
+    bss_target: &str,
+) -> io::Result<Vec<u8>> {
+    let obj = move_sections(obj, target, is_sandboxed_data, PAGE_SIZE)?;
+    move_sections(&obj, bss_target, is_sandboxed_bss, PAGE_SIZE)
+}
+
+/// Renames the read-only data sections (`.rodata*`) and the relocated read-only data sections
+/// (`.data.rel.ro*`) of a relocatable object to `target`, in the same way as
+/// [`move_data_sections`]. The renamed sections lose the linker's RELRO protection, so the runtime
+/// makes the padded output section read-only itself.
+pub(crate) fn move_rodata_sections(obj: &[u8], target: &str) -> io::Result<Vec<u8>> {
+    move_sections(
+        obj,
+        target,
+        |name, ty, flags| is_rodata(name, ty, flags) || is_relro(name, ty, flags),
+        PAGE_SIZE,
+    )
+}
+
+/// Renames the code sections (`.text*`) of a relocatable object to `target`, in the same way as
+/// [`move_data_sections`]. Code is not protected separately, so it is not padded; the bounds only
+/// tell the runtime which code pointers lead into the sandbox.
+pub(crate) fn move_text_sections(obj: &[u8], target: &str) -> io::Result<Vec<u8>> {
+    move_sections(obj, target, is_text, 1)
+}
+
+/// Renames the sections selected by `select` to `target`, aligning and padding each one to
+/// `granularity` bytes.
+fn move_sections(
+    obj: &[u8],
+    target: &str,
+    select: fn(&str, u32, u64) -> bool,
+    granularity: u64,
+) -> io::Result<Vec<u8>> {
+    let elf = Elf::parse(obj)?;
+    let mut out = obj.to_vec();
+
+    // New section names are appended to a copy of the section name table.
+    let mut shstrtab = elf.section_data(elf.shstrtab())?.to_vec();
+    let mut add_name = |name: &str| {
+        let offset = shstrtab.len() as u32;
+        shstrtab.extend_from_slice(name.as_bytes());
+        shstrtab.push(0);
+        offset
+    };
+    let target_name = add_name(target);
+    let rela_name = add_name(&format!(".rela{target}"));
+
+    let mut moved = vec![false; elf.sections().len()];
+    for section in elf.sections() {
+        let name = elf.section_name(section)?;
+        if section.size == 0 || !select(name, section.ty, section.flags) {
+            continue;
+        }
+        moved[section.index] = true;
+
+        let mut section = section.clone();
+        section.name = target_name;
+        // Mergeable constants and strings are only merged with sections of the same name and
+        // flags, so the renamed sections are not merged at all.
+        section.flags &= !(SHF_MERGE | SHF_STRINGS);
+        section.entsize = 0;
+        section.addralign = section.addralign.max(granularity);
+        let padded_size = section.size.next_multiple_of(granularity);
+        if section.ty != SHT_NOBITS && padded_size != section.size {
+            // Move the contents to the end of the file, followed by zero padding. `SHT_NOBITS`
+            // sections have no contents, so growing them is enough.
+            let contents = elf.section_data(&section)?;
+            out.resize(out.len().next_multiple_of(16), 0);
+            section.offset = out.len() as u64;
+            out.extend_from_slice(contents);
+            out.resize(section.offset as usize + padded_size as usize, 0);
+        }
+        section.size = padded_size;
+        section.write(&mut out);
+    }
+
+    // Keep relocation section names consistent with the sections they apply to.
+    for section in elf.sections() {
+        if section.ty == SHT_RELA && moved.get(section.info as usize) == Some(&true) {
+            let mut section = section.clone();
+            section.name = rela_name;
+            section.write(&mut out);
+        }
+    }
+
+    let mut strtab_header = elf.shstrtab().clone();
+    strtab_header.offset = out.len() as u64;
+    strtab_header.size = shstrtab.len() as u64;
+    out.extend_from_slice(&shstrtab);
+    strtab_header.write(&mut out);
+
+    Ok(out)
+}
+
+/// Renames the non-local symbols of a relocatable object, both definitions and references,
+/// according to `renames`.
+///
+/// Relocations refer to symbols by index, so only the names change. The new names are appended to
+/// a copy of the symbol string table, which is placed at the end of the file.
+pub(crate) fn rename_symbols(obj: &[u8], renames: &HashMap<String, String>) -> io::Result<Vec<u8>> {
+    let elf = Elf::parse(obj)?;
+    let Some(symtab) = elf.sections().iter().find(|s| s.ty == SHT_SYMTAB) else {
+        return Ok(obj.to_vec());
+    };
+    let mut out = obj.to_vec();
+
+    let mut strtab_header = elf.sections()[symtab.link as usize].clone();
+    let mut strtab = elf.section_data(&strtab_header)?.to_vec();
+    let mut renamed_any = false;
+    for (i, symbol) in elf.symbols(SHT_SYMTAB)?.iter().enumerate() {
+        if symbol.bind() == STB_LOCAL {
+            continue;
+        }
+        let Some(name) = renames.get(symbol.name) else {
+            continue;
+        };
+        let entry = symtab.offset as usize + i * SYM_SIZE;
+        out[entry..entry + 4].copy_from_slice(&(strtab.len() as u32).to_le_bytes());
+        strtab.extend_from_slice(name.as_bytes());
+        strtab.push(0);
+        renamed_any = true;
+    }
+    if !renamed_any {
+        return Ok(out);
+    }
+
+    strtab_header.offset = out.len() as u64;
+    strtab_header.size = strtab.len() as u64;
+    out.extend_from_slice(&strtab);
+    strtab_header.write(&mut out);
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+    use crate::tests::{compile, test_dir};
+
+    const SOURCE: &str = "
+        int counter = 1;
+        char zeroes[1 << 20];
+        const long table[4] = {2, 3, 5, 7};
+        int get(void) { return counter + zeroes[counter]; }
+        long lookup(int i) { return table[i & 3]; }
+        const char *greeting(void) { return \"hello\"; }
+    ";
+
+    fn rewrite(obj: &[u8]) -> Vec<u8> {
+        let obj = move_data_sections(obj, "sandbox_data", "sandbox_bss").unwrap();
+        let obj = move_rodata_sections(&obj, "sandbox_rodata").unwrap();
+        move_text_sections(&obj, "sandbox_text").unwrap()
+    }
+
+    #[test]
+    fn moves_sections_of_real_object() {
+        let dir = test_dir("rewrite");
+        let obj = compile(&dir, "lib.c", SOURCE, &["-O1", "-fno-common"]);
+        let out = rewrite(&std::fs::read(obj).unwrap());
+        let elf = Elf::parse(&out).unwrap();
+
+        let section = |name| elf.section_by_name(name);
+        let data = section("sandbox_data").expect("no sandbox_data");
+        assert_eq!(data.ty, SHT_PROGBITS);
+        assert_eq!(data.size % PAGE_SIZE, 0);
+        assert!(data.addralign >= PAGE_SIZE);
+        let bss = section("sandbox_bss").expect("no sandbox_bss");
+        assert_eq!(bss.ty, SHT_NOBITS);
+        assert_eq!(bss.size, 1 << 20);
+        let rodata = section("sandbox_rodata").expect("no sandbox_rodata");
+        assert_eq!(rodata.flags & (SHF_MERGE | SHF_STRINGS), 0);
+        assert_eq!(rodata.size % PAGE_SIZE, 0);
+        assert!(section("sandbox_text").is_some());
+        assert!(section(".relasandbox_text").is_some());
+        for name in [".data", ".bss", ".text", ".rodata", ".rodata.str1.1"] {
+            assert!(
+                section(name).is_none_or(|s| s.size == 0),
+                "{name} was not moved"
+            );
+        }
+        // The zero-initialized array is not stored in the object.
+        assert!(out.len() < 1 << 16);
+    }
+
+    #[test]
+    fn rewritten_object_links() {
+        let dir = test_dir("rewrite-link");
+        let obj = compile(&dir, "lib.c", SOURCE, &["-O1", "-fno-common"]);
+        std::fs::write(&obj, rewrite(&std::fs::read(&obj).unwrap())).unwrap();
+        let main = compile(
+            &dir,
+            "main.c",
+            "
+            extern char __start_sandbox_data[], __stop_sandbox_data[];
+            extern char __start_sandbox_bss[], __stop_sandbox_bss[];
+            extern char __start_sandbox_rodata[], __stop_sandbox_rodata[];
+            extern char zeroes[];
+            extern const long table[];
+            int get(void);
+            long lookup(int i);
+            const char *greeting(void);
+            int main(void) {
+                const char *s = greeting();
+                return !(__stop_sandbox_data - __start_sandbox_data == 4096
+                    && __start_sandbox_bss <= zeroes
+                    && zeroes + (1 << 20) <= __stop_sandbox_bss
+                    && __start_sandbox_rodata <= (const char *)table
+                    && (const char *)(table + 4) <= __stop_sandbox_rodata
+                    && __start_sandbox_rodata <= s && s + 6 <= __stop_sandbox_rodata
+                    && s[0] == 'h' && s[5] == 0
+                    && get() == 1 && lookup(2) == 5);
+            }
+            ",
+            &[],
+        );
+        let exe = dir.join("main");
+        let status = Command::new("cc")
+            .arg("-Wl,--gc-sections")
+            .arg("-o")
+            .arg(&exe)
+            .arg(&main)
+            .arg(&obj)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(Command::new(&exe).status().unwrap().success());
+        assert!(std::fs::metadata(&exe).unwrap().len() < 1 << 20);
+    }
+}
@@ -0,0 +1,209 @@
+//! Fixed-arity shims for variadic C functions.
+//!
+//! Sandboxed bindings cannot call a variadic function directly, so each call signature the library
+//! needs is instantiated as an ordinary C function that forwards its arguments. The shims are
+//! compiled into the library and declared in a generated header, from which bindgen binds them
+//! like any other function.
+use std::{fmt::Write, path::Path};
+
+/// A fixed-arity instantiation of a variadic function.
+pub(crate) struct Shim {
+    name: String,
+    ret: String,
+    function: String,
+    params: Vec<String>,
+}
+
+impl Shim {
+    /// Parses a signature such as `int printf(const char *, int)`, listing the return type and the
+    /// type of every argument, including the fixed ones. Panics if the signature is malformed.
+    pub(crate) fn parse(name: String, signature: &str) -> Shim {
+        let malformed =
+            || -> ! { panic!("malformed signature for variadic shim `{name}`: `{signature}`") };
+
+        let signature = signature.trim();
+        let (head, params) = signature
+            .strip_suffix(')')
+            .and_then(|s| s.split_once('('))
+            .unwrap_or_else(|| malformed());
+        let head = head.trim_end();
+        let function_start = head
+            .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map_or(0, |i| i + 1);
+        let (ret, function) = head.split_at(function_start);
+        if ret.trim().is_empty() || function.is_empty() {
+            malformed();
+        }
+
+        let params = split_params(params);
+        if params.iter().any(|p| p.is_empty()) {
+            malformed();
+        }
+
+        Shim {
+            name,
+            ret: ret.trim().to_owned(),
+            function: function.to_owned(),
+            params,
+        }
+    }
+
+    /// The name of the shim, which is also the name of its binding.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The name of the variadic function the shim calls.
+    pub(crate) fn function(&self) -> &str {
+        &self.function
+    }
+
+    fn declaration(&self) -> String {
+        format!(
+            "{} {}({});\n",
+            self.ret,
+            self.name,
+            param_list(&self.params)
+        )
+    }
+
+    fn definition(&self) -> String {
+        // `__typeof__` turns any type name into a specifier, so that parameters can be named even
+        // if their type is written as an abstract declarator such as `int (*)(int)`.
+        let params: Vec<_> = (0..self.params.len())
+            .map(|i| format!("__typeof__({}) a{i}", self.params[i]))
+            .collect();
+        let args: Vec<_> = (0..self.params.len()).map(|i| format!("a{i}")).collect();
+        let ret = if self.ret == "void" { "" } else { "return " };
+        format!(
+            "{} {}({}) {{\n    {ret}{}({});\n}}\n",
+            self.ret,
+            self.name,
+            param_list(&params),
+            self.function,
+            args.join(", ")
+        )
+    }
+}
+
+/// Joins parameters into a parameter list. An empty list is written as `void`, since `()` declares
+/// a function without a prototype in C before C23.
+fn param_list(params: &[String]) -> String {
+    if params.is_empty() {
+        "void".to_owned()
+    } else {
+        params.join(", ")
+    }
+}
+
+/// Splits a parameter list at the commas that are not nested in parentheses or brackets.
+fn split_params(params: &str) -> Vec<String> {
+    let params = params.trim();
+    if params.is_empty() || params == "void" {
+        return Vec::new();
+    }
+
+    let mut split = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in params.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                split.push(params[start..i].trim().to_owned());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(params[start..].trim().to_owned());
+    split
+}
+
+/// Writes the header declaring `shims` and the source defining them. Both include `headers`, which
+/// must declare the variadic functions and the types they use.
+pub(crate) fn write(shims: &[Shim], headers: &[impl AsRef<Path>], header: &Path, source: &Path) {
+    let mut decls = String::from("/* Generated by sandbox-build. */\n");
+    for h in headers {
+        let h = std::path::absolute(h).expect("could not resolve header path");
+        writeln!(decls, "#include \"{}\"", h.display()).unwrap();
+    }
+    decls.push('\n');
+    for shim in shims {
+        decls.push_str(&shim.declaration());
+    }
+
+    let mut defs = format!(
+        "/* Generated by sandbox-build. */\n#include \"{}\"\n",
+        header.display()
+    );
+    for shim in shims {
+        defs.push('\n');
+        defs.push_str(&shim.definition());
+    }
+
+    std::fs::write(header, decls).expect("could not write variadic shim header");
+    std::fs::write(source, defs).expect("could not write variadic shim source");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_signature() {
+        let shim = Shim::parse("printf_int".into(), " int printf(const char *, int) ");
+        assert_eq!(shim.ret, "int");
+        assert_eq!(shim.function, "printf");
+        assert_eq!(shim.params, ["const char *", "int"]);
+        assert_eq!(shim.declaration(), "int printf_int(const char *, int);\n");
+    }
+
+    #[test]
+    fn parses_function_pointer_parameters() {
+        let shim = Shim::parse(
+            "call_cb".into(),
+            "unsigned long *call(int (*)(int, char), void (*[2])(void), int)",
+        );
+        assert_eq!(shim.ret, "unsigned long *");
+        assert_eq!(shim.function, "call");
+        assert_eq!(
+            shim.params,
+            ["int (*)(int, char)", "void (*[2])(void)", "int"]
+        );
+        assert!(shim
+            .definition()
+            .contains("__typeof__(int (*)(int, char)) a0, __typeof__(void (*[2])(void)) a1"));
+    }
+
+    #[test]
+    fn parses_void_parameters_and_return() {
+        let shim = Shim::parse("log_none".into(), "void log(void)");
+        assert_eq!(shim.ret, "void");
+        assert!(shim.params.is_empty());
+        assert_eq!(shim.declaration(), "void log_none(void);\n");
+        assert_eq!(shim.definition(), "void log_none(void) {\n    log();\n}\n");
+        let shim = Shim::parse("log_none".into(), "void log()");
+        assert!(shim.params.is_empty());
+        assert_eq!(shim.declaration(), "void log_none(void);\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "malformed signature for variadic shim `bad`")]
+    fn rejects_missing_return_type() {
+        Shim::parse("bad".into(), "printf(const char *)");
+    }
+
+    #[test]
+    #[should_panic(expected = "malformed signature for variadic shim `bad`")]
+    fn rejects_missing_parentheses() {
+        Shim::parse("bad".into(), "int printf");
+    }
+
+    #[test]
+    #[should_panic(expected = "malformed signature for variadic shim `bad`")]
+    fn rejects_empty_parameter() {
+        Shim::parse("bad".into(), "int printf(const char *, , int)");
+    }
+}
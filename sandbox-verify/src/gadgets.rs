@@ -0,0 +1,159 @@
+//! Scanning for instructions that can modify PKRU.
+//!
+//! Sandboxed code must not be able to change its own protection key rights. The only instructions
+//! that write PKRU from user mode are `wrpkru` and `xrstor`, so every occurrence of their encodings
+//! in executable memory -- including ones that start in the middle of another instruction -- is a
+//! potential gadget, except for the protection switches of the `mpk` runtime itself.
+use std::{collections::HashSet, fmt, io};
+
+use sandbox_build::elf::{Elf, PF_X, PT_LOAD, SHF_EXECINSTR};
+
+/// The section in which `mpk` records the address of each of its `wrpkru` instructions.
+const PKRU_SITES_SECTION: &str = "mpk_pkru_sites";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GadgetKind {
+    /// `wrpkru` (`0F 01 EF`).
+    Wrpkru,
+    /// `xrstor`/`xrstor64` (`0F AE /5` with a memory operand), which can load PKRU from memory.
+    Xrstor,
+}
+
+impl fmt::Display for GadgetKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GadgetKind::Wrpkru => write!(f, "wrpkru"),
+            GadgetKind::Xrstor => write!(f, "xrstor"),
+        }
+    }
+}
+
+/// An occurrence of a PKRU-modifying instruction encoding.
+#[derive(Clone, Debug)]
+pub struct Gadget {
+    pub kind: GadgetKind,
+    /// The virtual address of the gadget, or its offset within `section` for relocatable objects.
+    pub address: u64,
+    pub file_offset: u64,
+    /// The section containing the gadget, for relocatable objects.
+    pub section: Option<usize>,
+}
+
+/// Returns the offset and kind of every gadget in `code`, at any byte offset.
+pub fn scan(code: &[u8]) -> Vec<(usize, GadgetKind)> {
+    code.windows(3)
+        .enumerate()
+        .filter_map(|(i, w)| match *w {
+            [0x0f, 0x01, 0xef] => Some((i, GadgetKind::Wrpkru)),
+            // A ModRM byte with `reg` = 5 and a memory operand (`mod` != 3). With a register
+            // operand, the same opcode is `lfence`.
+            [0x0f, 0xae, modrm] if (modrm >> 3) & 7 == 5 && modrm >> 6 != 3 => {
+                Some((i, GadgetKind::Xrstor))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns every gadget in the executable memory of `elf`.
+///
+/// For linked files, the executable segments are scanned in their entirety, including padding
+/// between sections. Relocatable objects have no segments, so their executable sections are scanned
+/// instead.
+pub fn find_gadgets(elf: &Elf) -> io::Result<Vec<Gadget>> {
+    let mut gadgets = Vec::new();
+    if elf.segments().iter().any(|s| s.ty == PT_LOAD) {
+        for segment in elf.segments() {
+            if segment.ty != PT_LOAD || segment.flags & PF_X == 0 {
+                continue;
+            }
+            for (offset, kind) in scan(elf.segment_data(segment)?) {
+                // The file offset cannot overflow, since the segment's contents lie within the
+                // file, but its address is not otherwise checked.
+                let address = segment.vaddr.checked_add(offset as u64).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "segment address out of range")
+                })?;
+                gadgets.push(Gadget {
+                    kind,
+                    address,
+                    file_offset: segment.offset + offset as u64,
+                    section: None,
+                });
+            }
+        }
+    } else {
+        for section in elf.sections() {
+            if section.flags & SHF_EXECINSTR == 0 {
+                continue;
+            }
+            for (offset, kind) in scan(elf.section_data(section)?) {
+                gadgets.push(Gadget {
+                    kind,
+                    address: offset as u64,
+                    file_offset: section.offset + offset as u64,
+                    section: Some(section.index),
+                });
+            }
+        }
+    }
+    Ok(gadgets)
+}
+
+/// Returns the addresses of the `wrpkru` instructions belonging to the `mpk` runtime. The sites are
+/// only known after linking, so this is empty for relocatable objects.
+pub fn pkru_sites(elf: &Elf) -> io::Result<HashSet<u64>> {
+    let Some(section) = elf.section_by_name(PKRU_SITES_SECTION) else {
+        return Ok(HashSet::new());
+    };
+    if elf.segments().is_empty() {
+        return Ok(HashSet::new());
+    }
+    Ok(elf
+        .section_data(section)?
+        .chunks_exact(4)
+        .enumerate()
+        .map(|(i, entry)| {
+            let relative = i32::from_le_bytes(entry.try_into().unwrap());
+            (section.addr + i as u64 * 4).wrapping_add_signed(relative.into())
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_wrpkru() {
+        // xor ecx, ecx; xor edx, edx; wrpkru; ret
+        let code = [0x31, 0xc9, 0x31, 0xd2, 0x0f, 0x01, 0xef, 0xc3];
+        assert_eq!(scan(&code), [(4, GadgetKind::Wrpkru)]);
+        // rdpkru shares the first two bytes.
+        assert_eq!(scan(&[0x0f, 0x01, 0xee]), []);
+    }
+
+    #[test]
+    fn distinguishes_xrstor_from_lfence() {
+        // xrstor [rdi]
+        assert_eq!(scan(&[0x0f, 0xae, 0x2f]), [(0, GadgetKind::Xrstor)]);
+        // xrstor64 [rsp + 8]
+        assert_eq!(
+            scan(&[0x48, 0x0f, 0xae, 0x6c, 0x24, 0x08]),
+            [(1, GadgetKind::Xrstor)]
+        );
+        // lfence (the same opcode with a register operand), and xsave [rdi] (/4).
+        assert_eq!(scan(&[0x0f, 0xae, 0xe8]), []);
+        assert_eq!(scan(&[0x0f, 0xae, 0x27]), []);
+    }
+
+    #[test]
+    fn finds_misaligned_encodings() {
+        // mov eax, 0xef010f90: the immediate contains wrpkru starting in the middle of the
+        // instruction.
+        let code = [0xb8, 0x90, 0x0f, 0x01, 0xef, 0xc3];
+        assert_eq!(scan(&code), [(2, GadgetKind::Wrpkru)]);
+        // An encoding at the very end of the code is found, and a truncated one is not.
+        assert_eq!(scan(&[0xc3, 0x0f, 0x01, 0xef]), [(1, GadgetKind::Wrpkru)]);
+        assert_eq!(scan(&[0xc3, 0x0f, 0x01]), []);
+    }
+}
@@ -0,0 +1,189 @@
+//! Post-link verification of binaries containing sandboxed code.
+//!
+//! ```text
+//! sandbox-verify gadgets [--allow SYMBOL]... FILE...
+//! sandbox-verify cet FILE...
+//! ```
+//!
+//! Reports every `wrpkru` or `xrstor` encoding in the executable memory of each file, other than
+//! the protection switches of the `mpk` runtime, and exits with a nonzero status if any are found.
+//! `--allow` accepts findings inside the named function (matched against the demangled name without
+//! its hash, or the raw symbol name).
+//!
+//! `cet` checks that each file is marked as supporting both indirect branch tracking and shadow
+//! stacks. The linker only marks its output if every input object was marked, so this catches any
+//! object built without `-fcf-protection`.
+mod gadgets;
+
+use std::{path::Path, process::ExitCode};
+
+use sandbox_build::elf::{Elf, Symbol, SHT_DYNSYM, SHT_SYMTAB, STT_FUNC};
+
+use gadgets::{Gadget, GadgetKind};
+
+fn usage() -> ExitCode {
+    eprintln!("usage: sandbox-verify gadgets [--allow SYMBOL]... FILE...");
+    eprintln!("       sandbox-verify cet FILE...");
+    ExitCode::from(2)
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("gadgets") => gadgets_command(args),
+        Some("cet") => cet_command(args),
+        _ => usage(),
+    }
+}
+
+/// Finds the function containing an address.
+struct Symbolizer<'a> {
+    functions: Vec<Symbol<'a>>,
+}
+
+impl<'a> Symbolizer<'a> {
+    fn new(elf: &Elf<'a>) -> std::io::Result<Symbolizer<'a>> {
+        let mut symbols = elf.symbols(SHT_SYMTAB)?;
+        if symbols.is_empty() {
+            symbols = elf.symbols(SHT_DYNSYM)?;
+        }
+        let functions: Vec<_> = symbols
+            .into_iter()
+            .filter(|s| s.ty() == STT_FUNC && s.size != 0)
+            .collect();
+        if let Some(s) = functions
+            .iter()
+            .find(|s| s.value.checked_add(s.size).is_none())
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "function `{}` extends past the end of the address space",
+                    s.name
+                ),
+            ));
+        }
+        Ok(Symbolizer { functions })
+    }
+
+    /// Returns the function containing `gadget` and the gadget's offset within it.
+    fn locate(&self, gadget: &Gadget) -> Option<(&Symbol<'a>, u64)> {
+        self.functions
+            .iter()
+            .find(|s| {
+                gadget.section.is_none_or(|i| s.shndx as usize == i)
+                    && s.value <= gadget.address
+                    && gadget.address < s.value + s.size
+            })
+            .map(|s| (s, gadget.address - s.value))
+    }
+}
+
+fn demangle(name: &str) -> String {
+    format!("{:#}", rustc_demangle::demangle(name))
+}
+
+/// Scans each file for gadgets, failing if any contains unexpected ones.
+fn gadgets_command(mut args: impl Iterator<Item = String>) -> ExitCode {
+    let mut allowed = Vec::new();
+    let mut files = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--allow" {
+            let Some(symbol) = args.next() else {
+                return usage();
+            };
+            allowed.push(symbol);
+        } else {
+            files.push(arg);
+        }
+    }
+    if files.is_empty() {
+        return usage();
+    }
+
+    let mut status = ExitCode::SUCCESS;
+    for file in &files {
+        match check_gadgets(Path::new(file), &allowed) {
+            Ok(0) => println!("{file}: no unexpected PKRU-modifying instructions"),
+            Ok(count) => {
+                eprintln!("{file}: found {count} unexpected PKRU-modifying instructions");
+                status = ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("{file}: {e}");
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+    status
+}
+
+/// Reports the unexpected gadgets in a file and returns how many there were.
+fn check_gadgets(path: &Path, allowed: &[String]) -> std::io::Result<usize> {
+    let data = std::fs::read(path)?;
+    let elf = Elf::parse(&data)?;
+    let sites = gadgets::pkru_sites(&elf)?;
+    let symbolizer = Symbolizer::new(&elf)?;
+
+    let mut count = 0;
+    for gadget in gadgets::find_gadgets(&elf)? {
+        if gadget.kind == GadgetKind::Wrpkru && sites.contains(&gadget.address) {
+            continue;
+        }
+
+        let location = symbolizer.locate(&gadget);
+        let function = location.map(|(s, _)| demangle(s.name));
+        if let (Some((symbol, _)), Some(function)) = (location, &function) {
+            if allowed.iter().any(|a| a == function || a == symbol.name) {
+                continue;
+            }
+        }
+
+        let address = match gadget.section {
+            Some(section) => {
+                let name = elf.section_name(&elf.sections()[section])?;
+                format!("{name}+{:#x}", gadget.address)
+            }
+            None => format!("{:#x}", gadget.address),
+        };
+        let mut report = format!(
+            "{}: unexpected {} at {address} (file offset {:#x})",
+            path.display(),
+            gadget.kind,
+            gadget.file_offset
+        );
+        match (location, function) {
+            (Some((_, offset)), Some(function)) => report += &format!(" in {function}+{offset:#x}"),
+            _ => report += " outside any known function",
+        }
+        eprintln!("{report}");
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Checks the CET properties of each file, failing if any lacks one.
+fn cet_command(args: impl Iterator<Item = String>) -> ExitCode {
+    let files: Vec<String> = args.collect();
+    if files.is_empty() {
+        return usage();
+    }
+
+    let mut status = ExitCode::SUCCESS;
+    for file in &files {
+        let missing = std::fs::read(file)
+            .and_then(|data| Elf::parse(&data).and_then(|elf| elf.missing_cet_features()));
+        match missing {
+            Ok(missing) if missing.is_empty() => println!("{file}: marked for IBT and SHSTK"),
+            Ok(missing) => {
+                eprintln!("{file}: not marked for {}", missing.join(" or "));
+                status = ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("{file}: {e}");
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+    status
+}
@@ -2,9 +2,16 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
-use std::sync::Mutex;
+/// The address space reserved for the heap of the sandboxed C library. Pages are only backed by
+/// memory once the allocator touches them.
+const HEAP_SIZE: usize = 1 << 30;
 
-pub struct Sandboxed(mpk::Sandbox);
-pub static SANDBOXED: Mutex<Sandboxed> = Mutex::new(Sandboxed(mpk::Sandbox::new()));
+fn init_heap(sandboxed: &mut Sandboxed) {
+    let heap = sandboxed
+        .sandbox_mut()
+        .map_memory(HEAP_SIZE)
+        .expect("could not map the sandbox heap");
+    sandboxed.sandbox_init(mpk::SandboxPtrMut::new(heap), HEAP_SIZE);
+}
 
-include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+include!(concat!(env!("OUT_DIR"), "/sandboxed.rs"));
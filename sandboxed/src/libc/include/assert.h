@@ -0,0 +1,11 @@
+/* No include guard: like the standard header, this may be included again after changing NDEBUG. */
+#undef assert
+
+#ifdef NDEBUG
+#define assert(expr) ((void)0)
+#else
+void __assert_fail(const char *expr, const char *file, unsigned int line,
+                   const char *func) __attribute__((noreturn));
+#define assert(expr)                                                           \
+  ((expr) ? (void)0 : __assert_fail(#expr, __FILE__, __LINE__, __func__))
+#endif
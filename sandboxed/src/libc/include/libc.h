@@ -0,0 +1,10 @@
+#ifndef SANDBOX_LIBC_H
+#define SANDBOX_LIBC_H
+
+#include <stddef.h>
+
+/* Hands the allocator the memory for its heap. Only the first call has an
+ * effect; until then, every allocation fails. */
+void libc_init(void *heap, size_t heap_size);
+
+#endif
@@ -0,0 +1,35 @@
+#ifndef SANDBOX_LIBC_STDIO_H
+#define SANDBOX_LIBC_STDIO_H
+
+#include <stdarg.h>
+#include <stddef.h>
+
+#define EOF (-1)
+
+/* A stream is a fixed-size buffer in sandbox memory. Output that does not fit
+ * is discarded and sets the stream's error flag. There are no input streams. */
+typedef struct FILE FILE;
+
+extern FILE *stdout;
+extern FILE *stderr;
+
+size_t fread(void *restrict ptr, size_t size, size_t count,
+             FILE *restrict stream);
+size_t fwrite(const void *restrict ptr, size_t size, size_t count,
+              FILE *restrict stream);
+int fputc(int c, FILE *stream);
+int fputs(const char *restrict s, FILE *restrict stream);
+int ferror(FILE *stream);
+
+int printf(const char *restrict format, ...)
+    __attribute__((format(printf, 1, 2)));
+int fprintf(FILE *restrict stream, const char *restrict format, ...)
+    __attribute__((format(printf, 2, 3)));
+int vfprintf(FILE *restrict stream, const char *restrict format, va_list args)
+    __attribute__((format(printf, 2, 0)));
+int snprintf(char *restrict buf, size_t size, const char *restrict format, ...)
+    __attribute__((format(printf, 3, 4)));
+int vsnprintf(char *restrict buf, size_t size, const char *restrict format,
+              va_list args) __attribute__((format(printf, 3, 0)));
+
+#endif
@@ -0,0 +1,18 @@
+#ifndef SANDBOX_LIBC_STDLIB_H
+#define SANDBOX_LIBC_STDLIB_H
+
+#include <stddef.h>
+
+void *malloc(size_t size);
+void *calloc(size_t count, size_t size);
+void *realloc(void *ptr, size_t size);
+void free(void *ptr);
+
+void abort(void) __attribute__((noreturn));
+
+void qsort(void *base, size_t count, size_t size,
+           int (*compare)(const void *, const void *));
+void *bsearch(const void *key, const void *base, size_t count, size_t size,
+              int (*compare)(const void *, const void *));
+
+#endif
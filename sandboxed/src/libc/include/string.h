@@ -0,0 +1,28 @@
+#ifndef SANDBOX_LIBC_STRING_H
+#define SANDBOX_LIBC_STRING_H
+
+#include <stddef.h>
+
+void *memcpy(void *restrict dst, const void *restrict src, size_t len);
+void *memmove(void *dst, const void *src, size_t len);
+void *memset(void *dst, int c, size_t len);
+int memcmp(const void *a, const void *b, size_t len);
+void *memchr(const void *s, int c, size_t len);
+
+size_t strlen(const char *s);
+int strcmp(const char *a, const char *b);
+int strncmp(const char *a, const char *b, size_t len);
+char *strchr(const char *s, int c);
+
+/* Freestanding compilation disables builtins, so map the hot functions back to
+ * them. The compiler expands small, fixed-size cases inline and calls the
+ * definitions above otherwise. */
+#ifndef SANDBOX_LIBC_NO_BUILTINS
+#define memcpy(dst, src, len) __builtin_memcpy(dst, src, len)
+#define memmove(dst, src, len) __builtin_memmove(dst, src, len)
+#define memset(dst, c, len) __builtin_memset(dst, c, len)
+#define memcmp(a, b, len) __builtin_memcmp(a, b, len)
+#define strlen(s) __builtin_strlen(s)
+#endif
+
+#endif
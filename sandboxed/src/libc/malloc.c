@@ -0,0 +1,91 @@
+/* Allocator for the sandbox heap. Requests are rounded up to a power of two,
+ * and freed blocks are kept on one free list per size class, so allocating and
+ * freeing take constant time. Memory is never returned to the heap. */
+#include <libc.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+#define MIN_CLASS 4 /* 16 bytes */
+#define NUM_CLASSES (sizeof(size_t) * 8)
+
+/* Precedes every block. Its size keeps the payload 16-byte aligned. */
+struct header {
+  size_t size_class;
+  size_t unused;
+};
+
+struct free_block {
+  struct free_block *next;
+};
+
+static char *heap_next;
+static char *heap_end;
+static struct free_block *free_lists[NUM_CLASSES];
+
+void libc_init(void *heap, size_t heap_size) {
+  if (heap_next)
+    return;
+  heap_next = heap;
+  heap_end = heap_next + heap_size;
+}
+
+static size_t size_class(size_t size) {
+  size_t c = MIN_CLASS;
+  while (((size_t)1 << c) < size)
+    c++;
+  return c;
+}
+
+void *malloc(size_t size) {
+  if (size > SIZE_MAX / 2)
+    return NULL;
+  size_t c = size_class(size);
+
+  struct free_block *block = free_lists[c];
+  if (block) {
+    free_lists[c] = block->next;
+    return block;
+  }
+
+  size_t needed = sizeof(struct header) + ((size_t)1 << c);
+  if ((size_t)(heap_end - heap_next) < needed)
+    return NULL;
+  struct header *header = (struct header *)heap_next;
+  heap_next += needed;
+  header->size_class = c;
+  return header + 1;
+}
+
+void *calloc(size_t count, size_t size) {
+  if (size && count > SIZE_MAX / size)
+    return NULL;
+  void *ptr = malloc(count * size);
+  if (ptr)
+    memset(ptr, 0, count * size);
+  return ptr;
+}
+
+void *realloc(void *ptr, size_t size) {
+  if (!ptr)
+    return malloc(size);
+  size_t old_size = (size_t)1 << ((struct header *)ptr - 1)->size_class;
+  if (size <= old_size)
+    return ptr;
+
+  void *new_ptr = malloc(size);
+  if (new_ptr) {
+    memcpy(new_ptr, ptr, old_size);
+    free(ptr);
+  }
+  return new_ptr;
+}
+
+void free(void *ptr) {
+  if (!ptr)
+    return;
+  struct free_block *block = ptr;
+  size_t c = ((struct header *)ptr - 1)->size_class;
+  block->next = free_lists[c];
+  free_lists[c] = block;
+}
@@ -0,0 +1,308 @@
+/* Streams writing to buffers in sandbox memory, and printf-style formatting. */
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#define STREAM_BUFFER_SIZE 4096
+
+struct FILE {
+  char *buf;
+  size_t size;
+  size_t len;
+  int error;
+};
+
+static char stdout_buf[STREAM_BUFFER_SIZE];
+static char stderr_buf[STREAM_BUFFER_SIZE];
+static FILE stdout_file = {stdout_buf, sizeof(stdout_buf), 0, 0};
+static FILE stderr_file = {stderr_buf, sizeof(stderr_buf), 0, 0};
+
+FILE *stdout = &stdout_file;
+FILE *stderr = &stderr_file;
+
+size_t fread(void *restrict ptr, size_t size, size_t count,
+             FILE *restrict stream) {
+  (void)ptr;
+  (void)size;
+  (void)count;
+  stream->error = 1;
+  return 0;
+}
+
+size_t fwrite(const void *restrict ptr, size_t size, size_t count,
+              FILE *restrict stream) {
+  if (!size || !count)
+    return 0;
+  size_t available = (stream->size - stream->len) / size;
+  if (count > available) {
+    count = available;
+    stream->error = 1;
+  }
+  memcpy(stream->buf + stream->len, ptr, count * size);
+  stream->len += count * size;
+  return count;
+}
+
+int fputc(int c, FILE *stream) {
+  unsigned char byte = (unsigned char)c;
+  return fwrite(&byte, 1, 1, stream) ? byte : EOF;
+}
+
+int fputs(const char *restrict s, FILE *restrict stream) {
+  size_t len = strlen(s);
+  return fwrite(s, 1, len, stream) == len ? 0 : EOF;
+}
+
+int ferror(FILE *stream) { return stream->error; }
+
+/* Formatted output goes to a sink, which counts every character but only
+ * stores as many as fit. */
+struct sink {
+  char *buf;
+  size_t size;
+  size_t len;
+};
+
+static void put(struct sink *sink, char c) {
+  if (sink->len < sink->size)
+    sink->buf[sink->len] = c;
+  sink->len++;
+}
+
+static void pad(struct sink *sink, char c, int count) {
+  for (; count > 0; count--)
+    put(sink, c);
+}
+
+enum {
+  FLAG_LEFT = 1,
+  FLAG_ZERO = 2,
+  FLAG_PLUS = 4,
+  FLAG_SPACE = 8,
+  FLAG_ALT = 16,
+};
+
+static void format_string(struct sink *sink, const char *s, int flags,
+                          int width, int precision) {
+  if (!s)
+    s = "(null)";
+  int len = 0;
+  while (s[len] && (precision < 0 || len < precision))
+    len++;
+  if (!(flags & FLAG_LEFT))
+    pad(sink, ' ', width - len);
+  for (int i = 0; i < len; i++)
+    put(sink, s[i]);
+  if (flags & FLAG_LEFT)
+    pad(sink, ' ', width - len);
+}
+
+static void format_number(struct sink *sink, uintmax_t value, int negative,
+                          unsigned base, int upper, int flags, int width,
+                          int precision) {
+  const char *digits = upper ? "0123456789ABCDEF" : "0123456789abcdef";
+  char buf[sizeof(uintmax_t) * 3];
+  int len = 0;
+  while (value) {
+    buf[len++] = digits[value % base];
+    value /= base;
+  }
+  if (precision < 0 && !len)
+    buf[len++] = '0';
+
+  char prefix[2];
+  int prefix_len = 0;
+  if (negative)
+    prefix[prefix_len++] = '-';
+  else if (flags & FLAG_PLUS)
+    prefix[prefix_len++] = '+';
+  else if (flags & FLAG_SPACE)
+    prefix[prefix_len++] = ' ';
+  if ((flags & FLAG_ALT) && base == 16) {
+    prefix[prefix_len++] = '0';
+    prefix[prefix_len++] = upper ? 'X' : 'x';
+  }
+
+  int zeros = precision > len ? precision - len : 0;
+  int total = prefix_len + zeros + len;
+  if (precision < 0 && (flags & FLAG_ZERO) && !(flags & FLAG_LEFT)) {
+    zeros += width - total;
+    total = width;
+  }
+
+  if (!(flags & FLAG_LEFT))
+    pad(sink, ' ', width - total);
+  for (int i = 0; i < prefix_len; i++)
+    put(sink, prefix[i]);
+  pad(sink, '0', zeros);
+  while (len)
+    put(sink, buf[--len]);
+  if (flags & FLAG_LEFT)
+    pad(sink, ' ', width - total);
+}
+
+/* Supports the flags `-0+ #`, width and precision (including `*`), the length
+ * modifiers `hh h l ll z t j`, and the conversions `d i u o x X c s p %`. */
+static void format(struct sink *sink, const char *fmt, va_list args) {
+  for (; *fmt; fmt++) {
+    if (*fmt != '%') {
+      put(sink, *fmt);
+      continue;
+    }
+    fmt++;
+
+    int flags = 0;
+    for (;; fmt++) {
+      if (*fmt == '-')
+        flags |= FLAG_LEFT;
+      else if (*fmt == '0')
+        flags |= FLAG_ZERO;
+      else if (*fmt == '+')
+        flags |= FLAG_PLUS;
+      else if (*fmt == ' ')
+        flags |= FLAG_SPACE;
+      else if (*fmt == '#')
+        flags |= FLAG_ALT;
+      else
+        break;
+    }
+
+    int width = 0;
+    if (*fmt == '*') {
+      width = va_arg(args, int);
+      if (width < 0) {
+        flags |= FLAG_LEFT;
+        width = -width;
+      }
+      fmt++;
+    } else {
+      while (*fmt >= '0' && *fmt <= '9')
+        width = width * 10 + (*fmt++ - '0');
+    }
+
+    int precision = -1;
+    if (*fmt == '.') {
+      fmt++;
+      precision = 0;
+      if (*fmt == '*') {
+        precision = va_arg(args, int);
+        fmt++;
+      } else {
+        while (*fmt >= '0' && *fmt <= '9')
+          precision = precision * 10 + (*fmt++ - '0');
+      }
+    }
+
+    int length = 0; /* -2: hh, -1: h, 0: int, 1: long, 2: long long */
+    for (;; fmt++) {
+      if (*fmt == 'h')
+        length--;
+      else if (*fmt == 'l')
+        length++;
+      else if (*fmt == 'z' || *fmt == 't' || *fmt == 'j')
+        length = 2;
+      else
+        break;
+    }
+
+    switch (*fmt) {
+    case 'd':
+    case 'i': {
+      intmax_t value = length >= 2   ? va_arg(args, long long)
+                       : length == 1 ? va_arg(args, long)
+                                     : va_arg(args, int);
+      if (length == -1)
+        value = (short)value;
+      else if (length <= -2)
+        value = (signed char)value;
+      uintmax_t magnitude = value < 0 ? -(uintmax_t)value : (uintmax_t)value;
+      format_number(sink, magnitude, value < 0, 10, 0, flags, width,
+                    precision);
+      break;
+    }
+    case 'u':
+    case 'o':
+    case 'x':
+    case 'X': {
+      uintmax_t value = length >= 2   ? va_arg(args, unsigned long long)
+                        : length == 1 ? va_arg(args, unsigned long)
+                                      : va_arg(args, unsigned);
+      if (length == -1)
+        value = (unsigned short)value;
+      else if (length <= -2)
+        value = (unsigned char)value;
+      unsigned base = *fmt == 'u' ? 10 : *fmt == 'o' ? 8 : 16;
+      format_number(sink, value, 0, base, *fmt == 'X', flags, width,
+                    precision);
+      break;
+    }
+    case 'p':
+      format_number(sink, (uintptr_t)va_arg(args, void *), 0, 16, 0,
+                    flags | FLAG_ALT, width, precision);
+      break;
+    case 'c': {
+      char c = (char)va_arg(args, int);
+      if (!(flags & FLAG_LEFT))
+        pad(sink, ' ', width - 1);
+      put(sink, c);
+      if (flags & FLAG_LEFT)
+        pad(sink, ' ', width - 1);
+      break;
+    }
+    case 's':
+      format_string(sink, va_arg(args, const char *), flags, width, precision);
+      break;
+    case '%':
+      put(sink, '%');
+      break;
+    default:
+      /* Unknown conversion: stop rather than misread the arguments. */
+      return;
+    }
+  }
+}
+
+int vsnprintf(char *restrict buf, size_t size, const char *restrict fmt,
+              va_list args) {
+  struct sink sink = {buf, size ? size - 1 : 0, 0};
+  format(&sink, fmt, args);
+  if (size)
+    buf[sink.len < sink.size ? sink.len : sink.size] = '\0';
+  return (int)sink.len;
+}
+
+int snprintf(char *restrict buf, size_t size, const char *restrict fmt, ...) {
+  va_list args;
+  va_start(args, fmt);
+  int len = vsnprintf(buf, size, fmt, args);
+  va_end(args);
+  return len;
+}
+
+int vfprintf(FILE *restrict stream, const char *restrict fmt, va_list args) {
+  struct sink sink = {stream->buf + stream->len, stream->size - stream->len, 0};
+  format(&sink, fmt, args);
+  if (sink.len > sink.size) {
+    stream->len = stream->size;
+    stream->error = 1;
+  } else {
+    stream->len += sink.len;
+  }
+  return (int)sink.len;
+}
+
+int fprintf(FILE *restrict stream, const char *restrict fmt, ...) {
+  va_list args;
+  va_start(args, fmt);
+  int len = vfprintf(stream, fmt, args);
+  va_end(args);
+  return len;
+}
+
+int printf(const char *restrict fmt, ...) {
+  va_list args;
+  va_start(args, fmt);
+  int len = vfprintf(stdout, fmt, args);
+  va_end(args);
+  return len;
+}
@@ -0,0 +1,68 @@
+#include <assert.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+/* There is no way out of the sandbox other than returning, so abnormal
+ * termination kills the whole process. */
+void abort(void) { __builtin_trap(); }
+
+void __assert_fail(const char *expr, const char *file, unsigned int line,
+                   const char *func) {
+  fprintf(stderr, "%s:%u: %s: Assertion `%s' failed.\n", file, line, func,
+          expr);
+  abort();
+}
+
+static void swap(char *a, char *b, size_t size) {
+  while (size--) {
+    char tmp = *a;
+    *a++ = *b;
+    *b++ = tmp;
+  }
+}
+
+static void sift_down(char *base, size_t root, size_t count, size_t size,
+                      int (*compare)(const void *, const void *)) {
+  for (;;) {
+    size_t child = 2 * root + 1;
+    if (child >= count)
+      return;
+    if (child + 1 < count &&
+        compare(base + child * size, base + (child + 1) * size) < 0)
+      child++;
+    if (compare(base + root * size, base + child * size) >= 0)
+      return;
+    swap(base + root * size, base + child * size, size);
+    root = child;
+  }
+}
+
+/* Heapsort, which needs no extra memory and no recursion. */
+void qsort(void *base, size_t count, size_t size,
+           int (*compare)(const void *, const void *)) {
+  char *b = base;
+  for (size_t i = count / 2; i-- > 0;)
+    sift_down(b, i, count, size, compare);
+  for (size_t end = count; end-- > 1;) {
+    swap(b, b + end * size, size);
+    sift_down(b, 0, end, size, compare);
+  }
+}
+
+void *bsearch(const void *key, const void *base, size_t count, size_t size,
+              int (*compare)(const void *, const void *)) {
+  const char *b = base;
+  while (count) {
+    const char *mid = b + (count / 2) * size;
+    int order = compare(key, mid);
+    if (order == 0)
+      return (void *)mid;
+    if (order > 0) {
+      b = mid + size;
+      count -= count / 2 + 1;
+    } else {
+      count /= 2;
+    }
+  }
+  return NULL;
+}
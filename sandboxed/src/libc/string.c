@@ -0,0 +1,88 @@
+/* Memory and string functions. The compiler also emits calls to memcpy,
+ * memmove, memset and memcmp on its own, for instance for struct copies. */
+#define SANDBOX_LIBC_NO_BUILTINS
+#include <string.h>
+
+/* Keep GCC from recognizing these loops as the functions they implement and
+ * compiling them into calls to themselves. */
+#if defined(__GNUC__) && !defined(__clang__)
+#pragma GCC optimize("no-tree-loop-distribute-patterns")
+#endif
+
+void *memcpy(void *restrict dst, const void *restrict src, size_t len) {
+  unsigned char *d = dst;
+  const unsigned char *s = src;
+  while (len--)
+    *d++ = *s++;
+  return dst;
+}
+
+void *memmove(void *dst, const void *src, size_t len) {
+  unsigned char *d = dst;
+  const unsigned char *s = src;
+  if (d < s) {
+    while (len--)
+      *d++ = *s++;
+  } else {
+    while (len--)
+      d[len] = s[len];
+  }
+  return dst;
+}
+
+void *memset(void *dst, int c, size_t len) {
+  unsigned char *d = dst;
+  while (len--)
+    *d++ = (unsigned char)c;
+  return dst;
+}
+
+int memcmp(const void *a, const void *b, size_t len) {
+  const unsigned char *x = a, *y = b;
+  for (; len; len--, x++, y++) {
+    if (*x != *y)
+      return *x - *y;
+  }
+  return 0;
+}
+
+void *memchr(const void *s, int c, size_t len) {
+  const unsigned char *p = s;
+  for (; len; len--, p++) {
+    if (*p == (unsigned char)c)
+      return (void *)p;
+  }
+  return NULL;
+}
+
+size_t strlen(const char *s) {
+  const char *p = s;
+  while (*p)
+    p++;
+  return p - s;
+}
+
+int strcmp(const char *a, const char *b) {
+  while (*a && *a == *b) {
+    a++;
+    b++;
+  }
+  return (unsigned char)*a - (unsigned char)*b;
+}
+
+int strncmp(const char *a, const char *b, size_t len) {
+  for (; len; len--, a++, b++) {
+    if (*a != *b || !*a)
+      return (unsigned char)*a - (unsigned char)*b;
+  }
+  return 0;
+}
+
+char *strchr(const char *s, int c) {
+  for (;; s++) {
+    if (*s == (char)c)
+      return (char *)s;
+    if (!*s)
+      return NULL;
+  }
+}
@@ -1,3 +1,12 @@
+#include <libc.h>
+#include <stdlib.h>
+
+void sandbox_init(void *heap, size_t heap_size) { libc_init(heap, heap_size); }
+
+void *sandbox_alloc(size_t size) { return malloc(size); }
+
+void sandbox_free(void *ptr) { free(ptr); }
+
 int VAL = 5;
 
 int sandboxed(int i) { return i * 3 * VAL; }
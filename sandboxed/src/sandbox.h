@@ -1,5 +1,14 @@
 #include "cmark/cmark.h"
 
+/* Hands the sandbox's C library the memory for its heap, which must lie in a
+ * sandbox region. Only the first call has an effect. */
+void sandbox_init(void *heap, size_t heap_size);
+
+/* Allocate and free memory on the sandbox's heap, e.g. for documents passed
+ * in and strings returned by cmark. */
+void *sandbox_alloc(size_t size);
+void sandbox_free(void *ptr);
+
 extern int VAL;
 
 int sandboxed(int i);
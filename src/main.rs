@@ -13,13 +13,45 @@ fn call_sandboxed_function(x: i32) -> i32 {
 #[cfg(test)]
 #[cfg(feature = "bench")]
 mod bench {
-    use std::ffi::CString;
+    use std::ffi::c_char;
 
-    use mpk::SandboxPtr;
+    use mpk::{SandboxPtr, SandboxPtrMut};
+    use sandboxed::Sandboxed;
 
     extern crate test;
     use test::Bencher;
 
+    /// Copies `document` onto the sandbox's heap, since cmark can only read sandbox memory.
+    fn copy_in(sandbox: &mut Sandboxed, document: &[u8]) -> SandboxPtrMut<c_char> {
+        let ptr = sandbox
+            .sandbox_alloc(document.len())
+            .expect("sandbox heap exhausted")
+            .get()
+            .cast::<u8>();
+        SandboxPtrMut::new(ptr)
+            .as_slice(document.len())
+            .as_mut(sandbox.sandbox_mut())
+            .copy_from_slice(document);
+        SandboxPtrMut::new(ptr.cast())
+    }
+
+    /// Parses the document at `document` and renders it to HTML inside the sandbox, freeing
+    /// everything cmark allocated.
+    fn render(sandbox: &mut Sandboxed, document: SandboxPtrMut<c_char>, len: usize) -> usize {
+        let options = sandboxed::CMARK_OPT_DEFAULT as i32;
+        let root = sandbox.cmark_parse_document(SandboxPtr::new(document.get()), len, options);
+        let html = sandbox.cmark_render_html(root, options);
+        sandbox.cmark_node_free(root);
+        html.map_or(0, |html| html.as_c_str(sandbox.sandbox()).to_bytes().len())
+    }
+
+    fn bench_document(b: &mut Bencher, document: &[u8]) {
+        let mut sandbox = sandboxed::SANDBOXED.lock().unwrap();
+        let ptr = copy_in(&mut sandbox, document);
+        b.iter(|| render(&mut sandbox, ptr, document.len()));
+        sandbox.sandbox_free(SandboxPtrMut::new(ptr.get().cast()));
+    }
+
     #[bench]
     fn nop(b: &mut Bencher) {
         let mut sandbox = sandboxed::SANDBOXED.lock().unwrap();
@@ -28,34 +60,15 @@ mod bench {
 
     #[bench]
     fn cmark_simple(b: &mut Bencher) {
-        let mut sandbox = sandboxed::SANDBOXED.lock().unwrap();
-
-        b.iter(|| {
-            let document = c"Hello, *world*";
-            let document = sandbox.cmark_parse_document(
-                SandboxPtr::from_cstr(document),
-                document.to_bytes().len(),
-                sandboxed::CMARK_OPT_DEFAULT as i32,
-            );
-            let html = sandbox.cmark_render_html(document, sandboxed::CMARK_OPT_DEFAULT as i32);
-
-            html
-        });
+        bench_document(b, b"Hello, *world*");
     }
 
     #[bench]
     fn cmark_large(b: &mut Bencher) {
-        let mut sandbox = sandboxed::SANDBOXED.lock().unwrap();
-        let document = CString::new(include_bytes!("./progit-bench.md")).unwrap();
-        let len = document.to_bytes().len();
-        let document = unsafe { SandboxPtr::new_unchecked(document.as_ptr()) };
-
-        b.iter(|| {
-            let document =
-                sandbox.cmark_parse_document(document, len, sandboxed::CMARK_OPT_DEFAULT as i32);
-            let html = sandbox.cmark_render_html(document, sandboxed::CMARK_OPT_DEFAULT as i32);
-
-            html
-        });
+        // The Pro Git book is not checked in, so it is read when the benchmark runs.
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/src/progit-bench.md");
+        let document =
+            std::fs::read(path).unwrap_or_else(|err| panic!("could not read {path}: {err}"));
+        bench_document(b, &document);
     }
 }